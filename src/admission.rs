@@ -0,0 +1,37 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use tracing::Level;
+
+// Counts records that have been queued but not yet exported (or given up on).
+#[derive(Clone, Default)]
+pub(crate) struct InFlight(Arc<AtomicUsize>);
+
+impl InFlight {
+    pub(crate) fn add(&self, records: usize) {
+        self.0.fetch_add(records, Ordering::Relaxed);
+    }
+
+    pub(crate) fn complete(&self, records: usize) {
+        let _ = self.0.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |in_flight| Some(in_flight.saturating_sub(records)));
+    }
+
+    pub(crate) fn get(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// Once more than `max_in_flight` records are waiting for the exporter, records less
+// severe than `shed_below` are dropped instead of blocking the caller on a full queue.
+// More severe records are still admitted (and may block), so an overload sheds DEBUG
+// and INFO long before it costs a single ERROR.
+pub(crate) struct LoadShedding {
+    pub(crate) max_in_flight: usize,
+    pub(crate) shed_below: Level,
+}
+
+impl LoadShedding {
+    pub(crate) fn admit(&self, in_flight: &InFlight, level: &Level) -> bool {
+        *level <= self.shed_below || in_flight.get() < self.max_in_flight
+    }
+}
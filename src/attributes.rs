@@ -0,0 +1,48 @@
+use crate::opentelclient::KeyValue;
+use crate::opentelclient::any_value::Value::StringValue;
+
+// OpenTelemetry's default attribute count limit.
+const DEFAULT_MAX_ATTRIBUTES: usize = 128;
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct AttributeLimits {
+    pub(crate) max_count: usize,
+    pub(crate) max_value_length: Option<usize>,
+}
+
+impl Default for AttributeLimits {
+    fn default() -> Self {
+        Self {
+            max_count: DEFAULT_MAX_ATTRIBUTES,
+            max_value_length: None,
+        }
+    }
+}
+
+impl AttributeLimits {
+    // Truncates string values in place and drops attributes beyond the count limit,
+    // returning how many were dropped for `dropped_attributes_count`.
+    pub(crate) fn apply(&self, attributes: &mut Vec<KeyValue>) -> u32 {
+        if let Some(max_length) = self.max_value_length {
+            for attribute in attributes.iter_mut() {
+                if let Some(StringValue(value)) = attribute.value.as_mut().and_then(|value| value.value.as_mut()) {
+                    truncate(value, max_length);
+                }
+            }
+        }
+        let dropped = attributes.len().saturating_sub(self.max_count);
+        attributes.truncate(self.max_count);
+        dropped as u32
+    }
+}
+
+fn truncate(value: &mut String, max_length: usize) {
+    if value.len() <= max_length {
+        return;
+    }
+    let mut end = max_length;
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    value.truncate(end);
+}
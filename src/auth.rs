@@ -0,0 +1,86 @@
+use std::sync::{Arc, Mutex, PoisonError};
+
+use tonic::service::Interceptor;
+use tonic::service::interceptor::InterceptedService;
+use tonic::transport::Channel;
+use tonic::{Request, Status};
+
+use crate::service::{parse_headers, StaticHeaders};
+
+// The channel to the main endpoint, adding the authentication headers and running the
+// user's interceptors on every call on it: exports, the hedged copies, the capabilities
+// probe and quota polls.
+pub(crate) type AuthChannel = InterceptedService<Channel, AuthInterceptor>;
+
+// Interceptors set with `with_interceptor`. Every clone of the channel shares them, so
+// state such as a refreshed token is seen by all calls.
+pub(crate) type SharedInterceptor = Arc<Mutex<dyn Interceptor + Send>>;
+
+// Authentication headers set with `with_bearer_token` or `with_api_key_header`, then the
+// user's interceptors in the order they were added. Header values are marked sensitive,
+// so they don't show up in the Debug output of the metadata.
+#[derive(Clone, Default)]
+pub(crate) struct AuthInterceptor {
+    headers: Arc<StaticHeaders>,
+    interceptors: Arc<Vec<SharedInterceptor>>,
+}
+
+impl AuthInterceptor {
+    pub(crate) fn new(headers: &[(String, String)], interceptors: Vec<SharedInterceptor>) -> Result<Self, String> {
+        let mut headers = parse_headers(headers, "authentication header")?;
+        for (_, value) in &mut headers {
+            value.set_sensitive(true);
+        }
+        Ok(Self { headers: Arc::new(headers), interceptors: Arc::new(interceptors) })
+    }
+
+    pub(crate) fn channel(&self, channel: Channel) -> AuthChannel {
+        InterceptedService::new(channel, self.clone())
+    }
+}
+
+impl Interceptor for AuthInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        for (key, value) in self.headers.iter() {
+            request.metadata_mut().insert(key.clone(), value.clone());
+        }
+        // An interceptor that panicked once (in a hedge or probe task, say) is called again
+        // rather than taking every later call down with it.
+        for interceptor in self.interceptors.iter() {
+            request = interceptor.lock().unwrap_or_else(PoisonError::into_inner).call(request)?;
+        }
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic;
+    use std::sync::{Arc, Mutex};
+
+    use tonic::service::Interceptor;
+    use tonic::{Request, Status};
+
+    use super::AuthInterceptor;
+
+    // Panics on its first call.
+    struct FailsOnce(usize);
+
+    impl Interceptor for FailsOnce {
+        fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+            self.0 += 1;
+            assert!(self.0 > 1, "interceptor failed");
+            Ok(request)
+        }
+    }
+
+    #[test]
+    fn poisoned_interceptor_is_still_called() {
+        let interceptor = Arc::new(Mutex::new(FailsOnce(0)));
+        let mut auth = AuthInterceptor::new(&[], vec![interceptor.clone()]).unwrap();
+        let mut first = auth.clone();
+        assert!(panic::catch_unwind(move || first.call(Request::new(())).is_ok()).is_err());
+        assert!(auth.call(Request::new(())).is_ok());
+        assert_eq!(interceptor.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).0, 2);
+    }
+}
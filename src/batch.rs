@@ -0,0 +1,130 @@
+use std::ops::Range;
+use std::sync::Arc;
+
+use bytes::BufMut;
+use prost::Message;
+
+use crate::envelope::{scope_logs_len, Envelope};
+use crate::exporter::ExporterConfig;
+use crate::opentelclient::LogRecord;
+use crate::resource::ResourceSnapshot;
+
+// A record on its way from a sender to an exporter thread, with the resource that was
+// current when it was logged.
+pub(crate) struct Queued {
+    pub(crate) record: LogRecord,
+    pub(crate) resource: Arc<ResourceSnapshot>,
+}
+
+// The records of the next export. Consecutive records logged under the same resource
+// form a group, and every group is encoded as its own ResourceLogs.
+#[derive(Default)]
+pub(crate) struct Batch {
+    pub(crate) records: Vec<LogRecord>,
+    groups: Vec<(usize, Arc<ResourceSnapshot>)>,
+    record_bytes: usize,
+}
+
+impl Batch {
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        Self { records: Vec::with_capacity(capacity), groups: Vec::new(), record_bytes: 0 }
+    }
+
+    pub(crate) fn push(&mut self, record: LogRecord, resource: &Arc<ResourceSnapshot>) {
+        match self.groups.last_mut() {
+            Some((end, last)) if last.version == resource.version => *end += 1,
+            _ => self.groups.push((self.records.len() + 1, resource.clone())),
+        }
+        self.record_bytes += record.encoded_len();
+        self.records.push(record);
+    }
+
+    pub(crate) fn push_queued(&mut self, queued: Queued) {
+        self.push(queued.record, &queued.resource);
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    // Encoded size of the records alone; the ResourceLogs and ScopeLogs around them add a
+    // little more per group.
+    pub(crate) fn record_bytes(&self) -> usize {
+        self.record_bytes
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.records.clear();
+        self.groups.clear();
+        self.record_bytes = 0;
+    }
+
+    // Sizes of the groups' records, for `encoded_len` and `encode`.
+    pub(crate) fn scope_logs_lens(&self) -> Vec<usize> {
+        self.groups().map(|(range, _)| scope_logs_len(&self.records[range])).collect()
+    }
+
+    // `envelope` is kept between batches and only re-encoded when a group's resource
+    // differs from the one it was built for.
+    pub(crate) fn encoded_len(&self, config: &ExporterConfig, envelope: &mut Envelope, scope_logs_lens: &[usize]) -> usize {
+        self.groups().zip(scope_logs_lens).map(|((_, resource), scope_logs_len)| {
+            envelope.refresh(config, resource);
+            envelope.encoded_len(*scope_logs_len)
+        }).sum()
+    }
+
+    pub(crate) fn encode(&self, config: &ExporterConfig, envelope: &mut Envelope, scope_logs_lens: &[usize], buf: &mut impl BufMut) {
+        for ((range, resource), scope_logs_len) in self.groups().zip(scope_logs_lens) {
+            envelope.refresh(config, resource);
+            envelope.encode(&self.records[range], *scope_logs_len, buf);
+        }
+    }
+
+    fn groups(&self) -> impl Iterator<Item=(Range<usize>, &ResourceSnapshot)> {
+        let mut start = 0;
+        self.groups.iter().map(move |(end, resource)| {
+            let range = start..*end;
+            start = *end;
+            (range, resource.as_ref())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prost::Message;
+
+    use super::Batch;
+    use crate::envelope::Envelope;
+    use crate::exporter::ExporterConfig;
+    use crate::follow::line_record;
+    use crate::opentelclient::{ExportLogsServiceRequest, Resource, ResourceLogs, ScopeLogs};
+
+    #[test]
+    fn encodes_like_prost() {
+        let config = ExporterConfig::new("test".to_string());
+        let resource = config.resource.current();
+        let mut batch = Batch::default();
+        for line in ["first", "second"] {
+            batch.push(line_record(1, 2, line.to_string(), Vec::new()), &resource);
+        }
+        let mut envelope = Envelope::new(&config, &resource);
+        let scope_logs_lens = batch.scope_logs_lens();
+        let mut encoded = Vec::new();
+        batch.encode(&config, &mut envelope, &scope_logs_lens, &mut encoded);
+
+        let request = ExportLogsServiceRequest {
+            resource_logs: vec![ResourceLogs {
+                resource: Some(Resource { attributes: resource.attributes.clone(), dropped_attributes_count: 0 }),
+                scope_logs: vec![ScopeLogs { scope: None, log_records: batch.records.clone(), schema_url: String::new() }],
+                schema_url: String::new(),
+            }],
+        };
+        assert_eq!(encoded, request.encode_to_vec());
+        assert_eq!(batch.encoded_len(&config, &mut envelope, &scope_logs_lens), encoded.len());
+    }
+}
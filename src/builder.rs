@@ -0,0 +1,1507 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::runtime::Runtime;
+#[cfg(feature = "tls")]
+use tonic::transport::{Certificate, ClientTlsConfig, Identity};
+use tonic::service::Interceptor;
+use tonic::transport::{Channel, Endpoint, Uri};
+use tower::{BoxError, Layer, Service};
+use tracing::Level;
+use tracing::level_filters::LevelFilter;
+
+use crate::admission::LoadShedding;
+use crate::auth::{AuthInterceptor, SharedInterceptor};
+use crate::capabilities::Capabilities;
+use crate::capture::CaptureWriter;
+use crate::clock::{Clock, CoarseClock};
+use crate::config::TelescopeConfig;
+#[cfg(feature = "tls")]
+use crate::config::TlsSettings;
+use crate::dedup::Deduplicator;
+use crate::disk_queue::DiskQueue;
+#[cfg(feature = "disk-queue-encryption")]
+use crate::disk_queue::DiskQueueKeyProvider;
+use crate::export::ExportClient;
+use crate::filter::{DynamicFilter, parse_directive};
+use crate::endpoint;
+use crate::error::{invalid_uri, ConfigError, TelescopeError};
+use crate::exemption::SamplingExemptions;
+use crate::expr::RecordFilter;
+use crate::exporter::{exporter_runtime, ExporterConfig, start_logging_thread};
+use crate::handle::TelescopeHandle;
+use crate::hedge::Hedge;
+use crate::ids::IdGenerator;
+use crate::memory_pressure::MemoryPressureSignal;
+use crate::normalize::KeyNormalizer;
+use crate::pipeline::Processor;
+use crate::queue::{OverflowPolicy, queue, QueueReceiver, QueueSender};
+use crate::quota::{Quota, Quotas};
+use crate::resolver::{ConnectedPeer, Resolver, ResolvingConnector};
+use crate::retry::RetryPolicy;
+use crate::opentelclient::ExportLogsServiceResponse;
+use crate::routing::{RecordSender, Route, RouteMatcher};
+use crate::sender::{Owner, TelescopeSender};
+use crate::sequence::TimestampSequence;
+use crate::service::{BoxExportService, ExportFailure, ExportRequest, ExportService, parse_headers};
+use crate::sink::{Sink, SinkService};
+use crate::support::RecentRecords;
+use crate::stats::ExportStats;
+use crate::span_fields::{SpanFieldConflict, SpanFieldRules};
+use crate::span_metrics::{SpanMetrics, SpanStats};
+use crate::sticky::{StickyDebug, StickyDebugState};
+use crate::tail::TailBuffering;
+use crate::transport::TelescopeTransport;
+use crate::usage::UsageClient;
+use crate::TelescopeLayer;
+
+#[derive(Clone)]
+pub(crate) enum Target {
+    Url(String),
+    Channel(Channel),
+    // Connected by the builder like an url, with the caller's endpoint settings; its
+    // channel is kept in the channel map under the endpoint's uri.
+    Endpoint(Endpoint),
+    Transport(TelescopeTransport),
+    Sink(SinkService),
+    // The pipeline of another layer; see `TelescopeLayer::builder_with_sender`.
+    Shared(TelescopeSender),
+}
+
+impl Target {
+    // Endpoints with the same url share one channel (and so one HTTP/2 connection). Urls
+    // not connected yet get a channel that connects on first use, which has to be
+    // created inside a tokio runtime.
+    fn connect(self, hedge: Option<Hedge>, stats: Option<Arc<ExportStats>>, capabilities: Option<Arc<Capabilities>>,
+               auth: &AuthInterceptor, channels: &mut HashMap<String, Channel>) -> Result<BoxExportService, TelescopeError> {
+        let channel = match self {
+            Target::Url(url) => match channels.get(&url) {
+                Some(channel) => channel.clone(),
+                None => {
+                    let channel = Channel::from_shared(url.clone())
+                        .map_err(|error| invalid_uri(&url, error))?
+                        .connect_lazy();
+                    channels.insert(url, channel.clone());
+                    channel
+                }
+            },
+            Target::Channel(channel) => channel,
+            Target::Endpoint(endpoint) => match channels.get(&endpoint.uri().to_string()) {
+                Some(channel) => channel.clone(),
+                None => endpoint.connect_lazy(),
+            },
+            Target::Transport(transport) => transport.channel,
+            Target::Sink(sink) => return Ok(BoxExportService::new(sink)),
+            Target::Shared(_) => {
+                return Err(ConfigError { problems: vec!["a shared pipeline can only be the main target".to_string()] }.into());
+            }
+        };
+        Ok(BoxExportService::new(ExportService { client: ExportClient::new(auth.channel(channel)), hedge, stats, capabilities }))
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Target::Url(url) => url.clone(),
+            Target::Channel(_) => "channel".to_string(),
+            Target::Endpoint(endpoint) => endpoint.uri().to_string(),
+            Target::Transport(_) => "transport".to_string(),
+            Target::Sink(_) => "sink".to_string(),
+            Target::Shared(_) => "shared".to_string(),
+        }
+    }
+
+    // The channel a connected gRPC target exports over.
+    fn channel(&self, channels: &HashMap<String, Channel>) -> Option<Channel> {
+        match self {
+            Target::Url(url) => channels.get(url).cloned(),
+            Target::Channel(channel) => Some(channel.clone()),
+            Target::Endpoint(endpoint) => channels.get(&endpoint.uri().to_string()).cloned(),
+            Target::Transport(transport) => Some(transport.channel.clone()),
+            Target::Sink(_) | Target::Shared(_) => None,
+        }
+    }
+}
+
+pub struct TelescopeLayerBuilder {
+    target: Target,
+    config: ExporterConfig,
+    hedge: Option<(String, Duration)>,
+    routes: Vec<(RouteMatcher, Target)>,
+    pub(crate) mirrors: Vec<Target>,
+    pub(crate) processors: Vec<Box<dyn Processor>>,
+    strict_processors: bool,
+    generate_trace_ids: bool,
+    message_in_attributes: bool,
+    timestamp_sequence: bool,
+    logs: bool,
+    runtime_metrics: Option<Duration>,
+    quota: Option<(u64, Duration)>,
+    severity_quotas: Vec<(Level, u64, Duration)>,
+    load_shedding: Option<LoadShedding>,
+    server_sampling: bool,
+    quota_polling: Option<Duration>,
+    resolver: Option<Arc<dyn Resolver>>,
+    resolved_addresses: HashMap<String, Vec<IpAddr>>,
+    connect_timeout: Option<Duration>,
+    auth_headers: Vec<(String, String)>,
+    interceptors: Vec<SharedInterceptor>,
+    metadata_headers: Vec<(String, String)>,
+    peer: Arc<ConnectedPeer>,
+    span_metrics: Option<SpanMetrics>,
+    span_fields: Option<SpanFieldRules>,
+    sticky_debug: Option<StickyDebug>,
+    flight_recorder: Option<usize>,
+    queue_capacity: usize,
+    overflow: OverflowPolicy,
+    min_level: LevelFilter,
+    directives: Vec<Result<(Option<String>, LevelFilter), String>>,
+    record_filter: Option<RecordFilter>,
+    key_normalizer: Option<KeyNormalizer>,
+    exemptions: SamplingExemptions,
+    tail_buffering: Option<TailBuffering>,
+    dedup_window: Option<Duration>,
+    connection_events: bool,
+    disk_queue: Option<PathBuf>,
+    #[cfg(feature = "disk-queue-encryption")]
+    disk_queue_key: Option<DiskQueueKeyProvider>,
+    #[cfg(feature = "tls")]
+    tls: ClientTlsConfig,
+    #[cfg(feature = "tls")]
+    tls_settings: TlsSettings,
+    capture: Option<(PathBuf, u64)>,
+}
+
+impl TelescopeLayerBuilder {
+    pub(crate) fn new(service_name: String, url: String) -> Self {
+        Self::with_target(service_name, Target::Url(endpoint::normalize(&url)))
+    }
+
+    pub(crate) fn with_channel(service_name: String, channel: Channel) -> Self {
+        Self::with_target(service_name, Target::Channel(channel))
+    }
+
+    pub(crate) fn with_endpoint(service_name: String, endpoint: Endpoint) -> Self {
+        Self::with_target(service_name, Target::Endpoint(endpoint))
+    }
+
+    pub(crate) fn with_transport(service_name: String, transport: TelescopeTransport) -> Self {
+        Self::with_target(service_name, Target::Transport(transport))
+    }
+
+    pub(crate) fn with_sink(service_name: String, sink: impl Sink) -> Self {
+        Self::with_target(service_name, Target::Sink(SinkService::new(sink)))
+    }
+
+    pub(crate) fn with_sender(service_name: String, sender: TelescopeSender) -> Self {
+        Self::with_target(service_name, Target::Shared(sender))
+    }
+
+    pub(crate) fn with_target(service_name: String, target: Target) -> Self {
+        Self {
+            target,
+            config: ExporterConfig::new(service_name),
+            hedge: None,
+            routes: Vec::new(),
+            mirrors: Vec::new(),
+            processors: Vec::new(),
+            strict_processors: false,
+            generate_trace_ids: false,
+            message_in_attributes: false,
+            timestamp_sequence: false,
+            logs: true,
+            runtime_metrics: None,
+            quota: None,
+            severity_quotas: Vec::new(),
+            load_shedding: None,
+            server_sampling: false,
+            quota_polling: None,
+            resolver: None,
+            resolved_addresses: HashMap::new(),
+            connect_timeout: None,
+            auth_headers: Vec::new(),
+            interceptors: Vec::new(),
+            metadata_headers: Vec::new(),
+            peer: Arc::default(),
+            span_metrics: None,
+            span_fields: None,
+            sticky_debug: None,
+            flight_recorder: None,
+            queue_capacity: 1000,
+            overflow: OverflowPolicy::Block,
+            min_level: LevelFilter::INFO,
+            directives: Vec::new(),
+            record_filter: None,
+            key_normalizer: None,
+            exemptions: SamplingExemptions::default(),
+            tail_buffering: None,
+            dedup_window: None,
+            connection_events: false,
+            disk_queue: None,
+            #[cfg(feature = "disk-queue-encryption")]
+            disk_queue_key: None,
+            #[cfg(feature = "tls")]
+            tls: ClientTlsConfig::new(),
+            #[cfg(feature = "tls")]
+            tls_settings: TlsSettings::default(),
+            capture: None,
+        }
+    }
+
+    /// Reuse the exporter thread's batch, envelope and encode buffers between exports
+    /// instead of allocating them per batch. For very high record rates this pairs well
+    /// with a faster global allocator (`mimalloc` or `tikv-jemallocator`) in the
+    /// application, which takes care of the per-event allocations on the logging threads.
+    pub fn with_arena_mode(mut self, enabled: bool) -> Self {
+        self.config.arena_mode = enabled;
+        self
+    }
+
+    /// Name of the exporter thread, `telescope-exporter` by default.
+    pub fn with_exporter_thread_name(mut self, name: impl Into<String>) -> Self {
+        self.config.thread_name = Some(name.into());
+        self
+    }
+
+    /// Niceness applied to the exporter thread (Linux only, ignored elsewhere). Raising
+    /// it above the application's own threads keeps exports from competing with them.
+    pub fn with_exporter_thread_niceness(mut self, niceness: i32) -> Self {
+        self.config.thread_niceness = Some(niceness);
+        self
+    }
+
+    /// Pin the exporter thread to the given core id.
+    pub fn with_exporter_thread_core(mut self, core: usize) -> Self {
+        self.config.thread_core = Some(core);
+        self
+    }
+
+    /// Send a W3C `traceparent` header on every export RPC. The ids are recorded on the
+    /// internal `telescope.export` span so client batches can be matched with the
+    /// server's ingestion traces.
+    pub fn with_export_trace_propagation(mut self, enabled: bool) -> Self {
+        self.config.propagate_trace_context = enabled;
+        self
+    }
+
+    /// Cap the export rate while draining the backlog that built up during an outage.
+    pub fn with_backlog_pacing(mut self, records_per_sec: u32) -> Self {
+        self.config.backlog_records_per_sec = Some(records_per_sec);
+        self
+    }
+
+    /// Wait a random time up to `max` before the first retry of an outage, so replicas
+    /// that lost the collector together don't all come back at once.
+    pub fn with_backlog_initial_delay(mut self, max: Duration) -> Self {
+        self.config.backlog_initial_delay = Some(max);
+        self
+    }
+
+    /// Stretch the periodic flush interval by a per-process offset in `[0, max)`. The
+    /// offset is derived from the pid and service name, so it is stable within a process
+    /// but keeps large fleets from flushing in lockstep.
+    pub fn with_flush_jitter(mut self, max: Duration) -> Self {
+        self.config.flush_jitter = max;
+        self
+    }
+
+    /// How long the exporter lets records pile up in its queue between two wakeups
+    /// (100ms by default). Every wakeup drains what arrived, up to a full batch, in one
+    /// go; a longer window means fewer wakeups under sustained load, at the cost of
+    /// records waiting that much longer before they are batched.
+    pub fn with_coalescing_window(mut self, window: Duration) -> Self {
+        self.config.coalescing_window = window;
+        self
+    }
+
+    /// Queue up to `capacity` records per exporter (1000 by default) and decide what
+    /// happens to records logged while the queue is full. Records dropped that way are
+    /// counted in [`TelescopeStats::records_dropped`](crate::TelescopeStats::records_dropped).
+    pub fn with_queue(mut self, capacity: usize, overflow: OverflowPolicy) -> Self {
+        self.queue_capacity = capacity;
+        self.overflow = overflow;
+        self
+    }
+
+    /// Ask the main endpoint what it supports before the first export, with an empty
+    /// export carrying `x-telescope-capabilities`, and cap batches to the size it
+    /// announces (see [`ServerCapabilities`](crate::ServerCapabilities)); with
+    /// [`Self::with_attribute_dictionary`] the probe also fetches the dictionary.
+    /// Capabilities announced on later responses are picked up either way; generic
+    /// collectors announce none and the exporter keeps its defaults.
+    pub fn with_capabilities_probe(mut self, enabled: bool) -> Self {
+        self.config.capabilities_probe = enabled;
+        self
+    }
+
+    /// Also cut batches by size: a batch is sent before its records' encoded size would
+    /// exceed `bytes`, on top of the 1000 record limit. Keeps requests under a collector's
+    /// message size limit when bodies vary a lot; leave some room for the resource and
+    /// scope around the records. A single record larger than `bytes` is sent on its own.
+    pub fn with_max_batch_bytes(mut self, bytes: usize) -> Self {
+        self.config.max_batch_bytes = Some(bytes);
+        self
+    }
+
+    /// Let a telescope server replace frequently repeated record attribute values (file
+    /// paths, targets) by small integer ids. Every export carries
+    /// `x-telescope-dictionary-version` with the version of the dictionary the exporter
+    /// holds, and the server sends a newer one in its response headers when it has one;
+    /// record attribute values found in it are then sent as their ids. Resource
+    /// attributes, such as the service name, are always sent as they are. Only applies to
+    /// the main endpoint, and needs a server that understands it: a generic collector
+    /// would store the ids. Can't be combined with a disk queue, which doesn't keep the
+    /// version, nor with a fallback sink or hedging, which would get the ids too.
+    pub fn with_attribute_dictionary(mut self, enabled: bool) -> Self {
+        self.config.attribute_dictionary = enabled;
+        self
+    }
+
+    /// Hold up to `max_records` of the first records instead of exporting them until the
+    /// collector answers an (empty) export, checked once per second, then send them
+    /// before anything logged later. Keeps startup logs from being retried against, and
+    /// given up on by, a collector that isn't up yet. Once the buffer is full records
+    /// are exported as usual; on shutdown the held records are flushed like any other.
+    pub fn with_early_buffer(mut self, max_records: usize) -> Self {
+        self.config.early_buffer = Some(max_records);
+        self
+    }
+
+    /// Send a duplicate of any export that has not completed within `after` to
+    /// `secondary_url` and keep whichever succeeds first. Both copies carry the same
+    /// `x-telescope-batch-id` header for server-side deduplication.
+    pub fn with_hedging(mut self, secondary_url: String, after: Duration) -> Self {
+        self.hedge = Some((endpoint::normalize(&secondary_url), after));
+        self
+    }
+
+    /// Send records whose target matches `pattern` (`audit::*`, `access_log`) to a
+    /// separate endpoint with its own batching. Routes are tried in the order they were
+    /// added; unmatched records go to the main endpoint.
+    pub fn with_route(mut self, pattern: impl Into<String>, url: String) -> Self {
+        self.routes.push((RouteMatcher::Target(pattern.into()), Target::Url(endpoint::normalize(&url))));
+        self
+    }
+
+    pub fn with_route_channel(mut self, pattern: impl Into<String>, channel: Channel) -> Self {
+        self.routes.push((RouteMatcher::Target(pattern.into()), Target::Channel(channel)));
+        self
+    }
+
+    pub fn with_route_sink(mut self, pattern: impl Into<String>, sink: impl Sink) -> Self {
+        self.routes.push((RouteMatcher::Target(pattern.into()), Target::Sink(SinkService::new(sink))));
+        self
+    }
+
+    /// Send records matching `filter` to a separate endpoint, like [`Self::with_route`].
+    pub fn with_route_filter(mut self, filter: RecordFilter, url: String) -> Self {
+        self.routes.push((RouteMatcher::Filter(filter), Target::Url(endpoint::normalize(&url))));
+        self
+    }
+
+    pub fn with_route_filter_sink(mut self, filter: RecordFilter, sink: impl Sink) -> Self {
+        self.routes.push((RouteMatcher::Filter(filter), Target::Sink(SinkService::new(sink))));
+        self
+    }
+
+    /// Also export every record to `url`, with its own batching and retries, e.g. to
+    /// ship to a second telescope during a migration.
+    pub fn with_mirror(mut self, url: String) -> Self {
+        self.mirrors.push(Target::Url(endpoint::normalize(&url)));
+        self
+    }
+
+    pub fn with_mirror_sink(mut self, sink: impl Sink) -> Self {
+        self.mirrors.push(Target::Sink(SinkService::new(sink)));
+        self
+    }
+
+    /// Run `processor` on every record before it is filtered, routed and batched.
+    /// Processors run in the order they are added, on the thread that logged.
+    pub fn with_processor(mut self, processor: impl Processor) -> Self {
+        self.processors.push(Box::new(processor));
+        self
+    }
+
+    /// Panic right after a processor that breaks a record (see [`Processor`]), naming it
+    /// by its position in the chain, instead of repairing the record once the chain is
+    /// through. Meant for the tests of processors; it panics on the thread that logged.
+    pub fn with_strict_processors(mut self, enabled: bool) -> Self {
+        self.strict_processors = enabled;
+        self
+    }
+
+    /// Export records from `level` up instead of from INFO, e.g. `Level::DEBUG` to ship
+    /// debug logs or `Level::WARN` to skip INFO. Applies to every target without a level
+    /// set through [`TelescopeHandle::set_target_level`]; records still have to pass
+    /// any filter installed in front of the layer.
+    pub fn with_min_level(mut self, level: impl Into<LevelFilter>) -> Self {
+        self.min_level = level.into();
+        self
+    }
+
+    /// Set levels with `EnvFilter`-style directives, e.g. `my_crate=debug,hyper=warn`:
+    /// comma separated `target=level` pairs, and optionally a bare level replacing the
+    /// default set with [`Self::with_min_level`]. Unlike a filter in front of the layer
+    /// they only decide what this layer exports, so other layers still see everything.
+    /// Invalid directives are reported by [`Self::validate`].
+    pub fn with_directives(mut self, directives: &str) -> Self {
+        self.directives.extend(directives.split(',')
+            .filter(|directive| !directive.trim().is_empty())
+            .map(parse_directive));
+        self
+    }
+
+    /// Only export records matching `filter`, e.g.
+    /// `severity >= WARN || attributes["customer_tier"] == "enterprise"`.
+    pub fn with_record_filter(mut self, filter: RecordFilter) -> Self {
+        self.record_filter = Some(filter);
+        self
+    }
+
+    /// Rewrite attribute keys (event fields, inherited span fields and context
+    /// attributes) to dot separated snake_case, see [`KeyNormalizer`].
+    pub fn with_key_normalizer(mut self, normalizer: KeyNormalizer) -> Self {
+        self.key_normalizer = Some(normalizer);
+        self
+    }
+
+    /// Write batches the endpoint rejects or can't be reached for to `sink` (e.g. a
+    /// [`crate::FileSink`]) instead of retrying them. Batches are only retried when the
+    /// sink fails as well.
+    pub fn with_fallback_sink(mut self, sink: impl Sink) -> Self {
+        self.config.fallback = Some(SinkService::new(sink));
+        self
+    }
+
+    /// Maximum number of attributes per record and on the resource (128 by default).
+    /// Anything beyond it is dropped and reported through `dropped_attributes_count`.
+    pub fn with_max_attributes(mut self, max: usize) -> Self {
+        self.config.attribute_limits.max_count = max;
+        self
+    }
+
+    /// Truncate string attribute values to at most `max` bytes.
+    pub fn with_max_attribute_value_length(mut self, max: usize) -> Self {
+        self.config.attribute_limits.max_value_length = Some(max);
+        self
+    }
+
+    /// Give every root span without an incoming `traceparent` a freshly generated trace
+    /// id, so all records emitted inside one request span can be grouped in telescope
+    /// even when the application has no tracing backend.
+    pub fn with_generated_trace_ids(mut self, enabled: bool) -> Self {
+        self.generate_trace_ids = enabled;
+        self
+    }
+
+    /// Also copy the message into a `message` attribute. The body always carries it;
+    /// this is for query engines that only index attributes.
+    pub fn with_message_in_attributes(mut self, enabled: bool) -> Self {
+        self.message_in_attributes = enabled;
+        self
+    }
+
+    /// Append an internal summary record (record count, min/max timestamp, dropped
+    /// attributes) to every batch so the server can check batches arrived complete.
+    pub fn with_batch_summary(mut self, enabled: bool) -> Self {
+        self.config.batch_summary = enabled;
+        self
+    }
+
+    /// Time source for timestamps, flush timers and backoff. Defaults to [`crate::SystemClock`].
+    pub fn with_clock(mut self, clock: impl Clock) -> Self {
+        self.config.clock = Arc::new(clock);
+        self
+    }
+
+    /// Cache timestamps and refresh them every `resolution` instead of reading the
+    /// clock for every record. Wraps the clock configured so far.
+    pub fn with_coarse_timestamps(mut self, resolution: Duration) -> Self {
+        self.config.clock = Arc::new(CoarseClock::new(self.config.clock.clone(), resolution));
+        self
+    }
+
+    /// Number records logged within the same nanosecond, which is common with
+    /// [`Self::with_coarse_timestamps`] or a [`crate::MonotonicClock`], with a
+    /// `telescope.seq_in_ns` attribute (1 for the second record, 2 for the third, ...),
+    /// so records can be put back in the order they were logged.
+    pub fn with_timestamp_sequence(mut self, enabled: bool) -> Self {
+        self.timestamp_sequence = enabled;
+        self
+    }
+
+    /// Generator for the trace and span ids the layer creates itself, e.g.
+    /// [`crate::UlidIdGenerator`] for time-ordered trace ids. Random by default.
+    pub fn with_id_generator(mut self, ids: impl IdGenerator) -> Self {
+        self.config.id_generator = Arc::new(ids);
+        self
+    }
+
+    /// Export at most `records` per `interval`. Records over the quota are dropped and
+    /// counted; the count is reported in a single internal record when the next interval
+    /// starts.
+    pub fn with_quota(mut self, records: u64, interval: Duration) -> Self {
+        self.quota = Some((records, interval));
+        self
+    }
+
+    /// Give `level` its own quota. Records of that level are only counted against this
+    /// quota, never against the one set with [`Self::with_quota`].
+    pub fn with_severity_quota(mut self, level: Level, records: u64, interval: Duration) -> Self {
+        self.severity_quotas.retain(|(quota_level, _, _)| *quota_level != level);
+        self.severity_quotas.push((level, records, interval));
+        self
+    }
+
+    /// Set `ResourceLogs.schema_url`, the OpenTelemetry schema the resource attributes
+    /// follow, so the server can translate them between schema versions.
+    pub fn with_schema_url(self, schema_url: impl Into<String>) -> Self {
+        self.config.resource.set_schema_url(schema_url.into());
+        self
+    }
+
+    /// Wrap the export service in a tower middleware (timeout, load shed, concurrency
+    /// limit, ...). Layers are applied in the order they are added, inside the retry
+    /// policy, so e.g. a timeout applies to every attempt. The retry layer needs a
+    /// `Clone` service; wrap non-`Clone` middleware such as rate limiting in a `Buffer`.
+    ///
+    /// The payload a layer passes on is exported (and disk queued) byte for byte, so
+    /// fields or attributes it adds are kept even where this client doesn't know them.
+    pub fn with_export_layer<L>(mut self, layer: L) -> Self
+        where
+            L: Layer<BoxExportService> + Send + Sync + 'static,
+            L::Service: Service<ExportRequest, Response=ExportLogsServiceResponse, Error=BoxError> + Clone + Send + 'static,
+            <L::Service as Service<ExportRequest>>::Future: Send + 'static,
+    {
+        self.config.export_layers.push(Arc::new(move |service| BoxExportService::new(layer.layer(service))));
+        self
+    }
+
+    /// Never drop records whose target matches `pattern` (`security::*`) through quotas,
+    /// load shedding, server sampling or error deduplication.
+    pub fn with_sampling_exemption_target(mut self, pattern: impl Into<String>) -> Self {
+        self.exemptions.targets.push(pattern.into());
+        self
+    }
+
+    /// Never drop records matching `filter` (e.g. `attributes["audit"] == true`) through
+    /// quotas, load shedding, server sampling or error deduplication. Matching needs the finished record,
+    /// so every event is converted before the quotas are checked; prefer
+    /// [`Self::with_sampling_exemption_target`] where a target is enough.
+    pub fn with_sampling_exemption(mut self, filter: RecordFilter) -> Self {
+        self.exemptions.filters.push(filter);
+        self
+    }
+
+    /// Shed records less severe than `shed_below` while more than `max_in_flight` records
+    /// are queued but not yet exported, instead of blocking the logging thread. Records
+    /// at `shed_below` or more severe are always admitted.
+    ///
+    /// The export stack gets tower's load-shed layer as well: a batch the endpoint can't
+    /// take right away (its connection is at its stream limit, or a concurrency limit
+    /// added with [`Self::with_export_layer`] is reached) is rejected and retried after
+    /// the backoff, rather than waited on. Records back up in the queue meanwhile, where
+    /// the less severe ones are shed.
+    pub fn with_load_shedding(mut self, max_in_flight: usize, shed_below: Level) -> Self {
+        self.load_shedding = Some(LoadShedding { max_in_flight, shed_below });
+        self.config.load_shed = true;
+        self
+    }
+
+    /// Follow sampling hints from a telescope server in the `x-telescope-sample` response
+    /// header, so it can thin out clients centrally while its ingestion is overloaded:
+    /// `info=0.1,debug=0;ttl=60` keeps a random 10% of INFO and no DEBUG records for the
+    /// next minute. Each hint replaces the previous one and levels it doesn't name are
+    /// kept. Only the main endpoint's responses are read.
+    pub fn with_server_sampling(mut self, enabled: bool) -> Self {
+        self.server_sampling = enabled;
+        self
+    }
+
+    /// Verify `https://` endpoints against `domain` instead of their host name, e.g. when
+    /// connecting by IP address or through a tunnel.
+    #[cfg(feature = "tls")]
+    pub fn with_tls_domain(mut self, domain: impl Into<String>) -> Self {
+        let domain = domain.into();
+        self.tls = self.tls.domain_name(domain.clone());
+        self.tls_settings.domain = Some(domain);
+        self
+    }
+
+    /// Trust the PEM encoded root CA certificate(s) in `pem` for `https://` endpoints, in
+    /// addition to the system roots, for collectors with certificates from a private
+    /// PKI.
+    #[cfg(feature = "tls")]
+    pub fn with_tls_ca_certificate(mut self, pem: impl AsRef<[u8]>) -> Self {
+        self.tls = self.tls.ca_certificate(Certificate::from_pem(pem));
+        self.tls_settings.ca_certificates = true;
+        self.tls_settings.ca_certificate_file = None;
+        self
+    }
+
+    /// Like [`Self::with_tls_ca_certificate`], reading the certificate(s) from `path` when
+    /// the layer is built.
+    #[cfg(feature = "tls")]
+    pub fn with_tls_ca_certificate_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.tls_settings.ca_certificate_file = Some(path.into());
+        self
+    }
+
+    /// Authenticate to `https://` endpoints with a client certificate, for collectors
+    /// requiring mutual TLS: `cert` is the PEM encoded certificate (chain) and `key` its
+    /// PEM encoded private key. Invalid PEM fails the build.
+    #[cfg(feature = "tls")]
+    pub fn with_tls_identity(mut self, cert: impl AsRef<[u8]>, key: impl AsRef<[u8]>) -> Self {
+        self.tls = self.tls.identity(Identity::from_pem(cert, key));
+        self.tls_settings.client_identity = true;
+        self
+    }
+
+    /// TLS settings for every `https://` url endpoint (main, hedging, routes and mirrors),
+    /// replacing the default: the system roots and the endpoint's host name.
+    #[cfg(feature = "tls")]
+    pub fn with_tls_config(mut self, tls: ClientTlsConfig) -> Self {
+        self.tls = tls;
+        self.tls_settings = TlsSettings {
+            ca_certificate_file: self.tls_settings.ca_certificate_file.take(),
+            custom_config: true,
+            ..TlsSettings::default()
+        };
+        self
+    }
+
+    /// Resolve the host names of url endpoints (main, hedging, routes and mirrors), and
+    /// of an endpoint passed in, with `resolver` instead of the system resolver. Channels
+    /// and transports passed in connect however they were built.
+    pub fn with_resolver(mut self, resolver: impl Resolver) -> Self {
+        self.resolver = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Connect to `address` for endpoints on `host`, without asking any resolver. Call
+    /// again to add more addresses, raced as described at [`Self::with_connect_timeout`].
+    pub fn with_resolved_address(mut self, host: impl Into<String>, address: IpAddr) -> Self {
+        self.resolved_addresses.entry(host.into()).or_default().push(address);
+        self
+    }
+
+    /// Give up on connecting to one address of an url endpoint after `timeout`. Hosts
+    /// with several addresses have them tried concurrently, IPv6 and IPv4 alternating
+    /// with a new attempt every 250 ms, so a dead address only delays the connection
+    /// if all of them are; the timeout bounds how long each attempt may hang on.
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Send `Authorization: Bearer <token>` with every request to the main endpoint
+    /// (exports, hedged copies and quota polls), e.g. for a hosted collector. Routes and
+    /// mirrors, which may point elsewhere, don't get it.
+    pub fn with_bearer_token(self, token: impl Into<String>) -> Self {
+        let token = token.into();
+        self.with_api_key_header("authorization", format!("Bearer {token}"))
+    }
+
+    /// Send the header `name: value` with every request to the main endpoint, like
+    /// [`Self::with_bearer_token`], for collectors that take an API key in a header of
+    /// their own (e.g. `x-api-key`). Replaces an earlier value of the same header.
+    pub fn with_api_key_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        let name = name.into().to_ascii_lowercase();
+        self.auth_headers.retain(|(existing, _)| *existing != name);
+        self.auth_headers.push((name, value.into()));
+        self
+    }
+
+    /// Run `interceptor` on every request to the main endpoint, after the authentication
+    /// headers are added, e.g. to refresh and attach a short-lived token or to sign
+    /// requests. Clones of the channel share it, so state it keeps (a cached token) is
+    /// seen by every request. A request it rejects fails like an unreachable endpoint
+    /// would, and is retried. Call again for more; they run in the order added.
+    pub fn with_interceptor(mut self, interceptor: impl Interceptor + Send + 'static) -> Self {
+        self.interceptors.push(Arc::new(Mutex::new(interceptor)));
+        self
+    }
+
+    /// Send the gRPC metadata header `name: value` with every export request, e.g. a
+    /// tenant id or routing key for backends that multiplex tenants by header. Unlike
+    /// authentication headers, these go to every endpoint of the layer: main, hedging,
+    /// routes and mirrors. Call again for more headers; the same name again replaces the
+    /// value.
+    pub fn with_metadata(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        let name = name.into().to_ascii_lowercase();
+        self.metadata_headers.retain(|(existing, _)| *existing != name);
+        self.metadata_headers.push((name, value.into()));
+        self
+    }
+
+    /// Retry failed exports as `policy` says instead of every second, forever, e.g. with
+    /// exponential backoff and jitter, or giving up on a batch after some attempts so the
+    /// batches behind it aren't held back indefinitely.
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.config.retry = policy;
+        self
+    }
+
+    /// Ask telescope's quota endpoint (`telescope.v1.QuotaService/GetUsage`) for the
+    /// service's remaining ingestion quota every `interval`, report it in
+    /// [`TelescopeStats::quota`](crate::TelescopeStats::quota), and hold exports back
+    /// while it is used up instead of sending batches the server would reject. Records
+    /// wait in the queue meanwhile, so the queue must drop records when it fills up
+    /// rather than block the application until the quota resets: set
+    /// [`OverflowPolicy::DropNewest`] or [`OverflowPolicy::DropOldest`] through
+    /// [`Self::with_queue`]. Only the main endpoint is polled, and only a gRPC one: not
+    /// a sink.
+    pub fn with_quota_polling(mut self, interval: Duration) -> Self {
+        self.quota_polling = Some(interval);
+        self
+    }
+
+    /// Measure span durations and export them, either as a record per closed span or
+    /// as periodic per-name summaries. Combine with `with_logs(false)` to export spans
+    /// only, while logs go to another layer.
+    pub fn with_span_metrics(mut self, span_metrics: SpanMetrics) -> Self {
+        self.span_metrics = Some(span_metrics);
+        self
+    }
+
+    /// Export an internal record with process RSS, CPU time and utilization, open file
+    /// descriptors (Linux only) and the worker count, live tasks and global queue depth
+    /// of the tokio runtime `build` runs on, every `interval`.
+    pub fn with_runtime_metrics(mut self, interval: Duration) -> Self {
+        self.runtime_metrics = Some(interval);
+        self
+    }
+
+    /// Export log events (on by default). Turning them off leaves the span records of
+    /// [`Self::with_span_metrics`] on the same connection and batches.
+    pub fn with_logs(mut self, enabled: bool) -> Self {
+        self.logs = enabled;
+        self
+    }
+
+    /// Copy the fields of enclosing spans (e.g. `#[instrument]` arguments) onto every
+    /// record emitted inside them. Fields of the event itself take precedence.
+    pub fn with_span_fields(mut self, enabled: bool) -> Self {
+        self.span_fields = enabled.then(|| self.span_fields.take().unwrap_or_default());
+        self
+    }
+
+    /// Don't inherit fields matching `field_pattern` from spans matching `span_pattern`.
+    /// Both patterns may use `*`, e.g. `("*", "self")` or `("db::*", "conn")`. Enables
+    /// span field inheritance.
+    pub fn with_span_field_exclusion(mut self, span_pattern: impl Into<String>, field_pattern: impl Into<String>) -> Self {
+        self.span_fields.get_or_insert_with(SpanFieldRules::default)
+            .exclusions.push((span_pattern.into(), field_pattern.into()));
+        self
+    }
+
+    /// Cap inherited string span field values at `max` bytes, including the `…` marking
+    /// a cut value. Enables span field inheritance.
+    pub fn with_span_field_max_length(mut self, max: usize) -> Self {
+        self.span_fields.get_or_insert_with(SpanFieldRules::default).max_value_length = Some(max);
+        self
+    }
+
+    /// Only inherit fields matching one of `field_patterns` from spans matching
+    /// `span_pattern`. The first allowlist whose span pattern matches applies; exclusions
+    /// still apply on top. Enables span field inheritance.
+    pub fn with_span_field_allowlist<I>(mut self, span_pattern: impl Into<String>, field_patterns: I) -> Self
+        where I: IntoIterator, I::Item: Into<String>
+    {
+        self.span_fields.get_or_insert_with(SpanFieldRules::default)
+            .allowlists.push((span_pattern.into(), field_patterns.into_iter().map(Into::into).collect()));
+        self
+    }
+
+    /// Only inherit fields from the `depth` innermost enclosing spans. Enables span field
+    /// inheritance.
+    pub fn with_span_field_max_depth(mut self, depth: usize) -> Self {
+        self.span_fields.get_or_insert_with(SpanFieldRules::default).max_depth = Some(depth);
+        self
+    }
+
+    /// How fields inherited from different spans with the same key are resolved,
+    /// [`SpanFieldConflict::Innermost`] by default. Enables span field inheritance.
+    pub fn with_span_field_conflict(mut self, conflict: SpanFieldConflict) -> Self {
+        self.span_fields.get_or_insert_with(SpanFieldRules::default).conflict = conflict;
+        self
+    }
+
+    /// Temporarily export DEBUG records of targets that produce a burst of errors. Only
+    /// sees DEBUG records that pass the filters in front of the layer.
+    pub fn with_sticky_debug(mut self, sticky_debug: StickyDebug) -> Self {
+        self.sticky_debug = Some(sticky_debug);
+        self
+    }
+
+    /// Keep the last `records` DEBUG and TRACE records of every trace (or of every thread,
+    /// outside of spans) that aren't exported, and export them ahead of the next ERROR in
+    /// the same trace. They are discarded with the trace's root span otherwise.
+    pub fn with_flight_recorder(mut self, records: usize) -> Self {
+        self.flight_recorder = Some(records);
+        self
+    }
+
+    /// Hold back the records of request spans and decide what to export when the span
+    /// closes. Only sees DEBUG records that pass the filters in front of the layer.
+    pub fn with_tail_buffering(mut self, tail_buffering: TailBuffering) -> Self {
+        self.tail_buffering = Some(tail_buffering);
+        self
+    }
+
+    /// Collapse identical errors (same `exception.stacktrace`, or same message for ERROR
+    /// records without one) within `window`. The first one is exported immediately; when
+    /// the window ends a copy of it carrying `telescope.dedup.suppressed` reports how many
+    /// repeats were dropped.
+    pub fn with_error_dedup(mut self, window: Duration) -> Self {
+        self.dedup_window = Some(window);
+        self
+    }
+
+    /// Export an internal record whenever the main endpoint becomes reachable or stops
+    /// being reachable. The state itself is always available from
+    /// [`TelescopeHandle::stats`].
+    pub fn with_connection_events(mut self, enabled: bool) -> Self {
+        self.connection_events = enabled;
+        self
+    }
+
+    /// Called on the exporter thread for every failed export attempt, with the request
+    /// id sent in the `x-telescope-request-id` header.
+    pub fn with_export_error_callback(mut self, callback: impl Fn(&ExportFailure) + Send + Sync + 'static) -> Self {
+        self.config.error_callback = Some(Arc::new(callback));
+        self
+    }
+
+    /// Persist batches that fail to export in `dir` instead of retrying them in memory,
+    /// and send them once the endpoint takes batches again, oldest first. Batches left
+    /// in `dir` by a previous run are sent as well.
+    pub fn with_disk_queue(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.disk_queue = Some(dir.into());
+        self
+    }
+
+    /// Encrypt batches in the disk queue with AES-256-GCM, using the 32 byte key
+    /// returned by `key`. Batches queued in plaintext by an earlier run are encrypted
+    /// when the queue is opened.
+    #[cfg(feature = "disk-queue-encryption")]
+    pub fn with_disk_queue_key(mut self, key: impl FnOnce() -> Result<Vec<u8>, BoxError> + Send + 'static) -> Self {
+        self.disk_queue_key = Some(Box::new(key));
+        self
+    }
+
+    /// Like [`Self::with_disk_queue_key`], reading the key hex encoded from the
+    /// environment variable `var`.
+    #[cfg(feature = "disk-queue-encryption")]
+    pub fn with_disk_queue_key_env(self, var: impl Into<String>) -> Self {
+        let var = var.into();
+        self.with_disk_queue_key(move || {
+            let hex = std::env::var(&var).map_err(|error| format!("{var}: {error}"))?;
+            (0..hex.len())
+                .step_by(2)
+                .map(|i| hex.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+                .collect::<Option<Vec<u8>>>()
+                .ok_or_else(|| format!("{var} is not hex encoded").into())
+        })
+    }
+
+    /// Flush whatever is buffered without waiting for a full batch, and give batch
+    /// buffers back to the allocator, while the cgroup v2 memory pressure (`some avg10`
+    /// in `memory.pressure`, in percent) is above `threshold`. Checked once per second.
+    #[cfg(target_os = "linux")]
+    pub fn with_cgroup_memory_pressure(mut self, threshold: f64) -> Self {
+        self.config.memory_pressure = Some(MemoryPressureSignal::CgroupPsi(threshold));
+        self
+    }
+
+    /// Like [`Self::with_cgroup_memory_pressure`], asking `under_pressure` instead, e.g.
+    /// to follow the allocator's own statistics. Called once per second on the exporter
+    /// thread.
+    pub fn with_memory_pressure_callback(mut self, under_pressure: impl Fn() -> bool + Send + Sync + 'static) -> Self {
+        self.config.memory_pressure = Some(MemoryPressureSignal::Callback(Arc::new(under_pressure)));
+        self
+    }
+
+    /// Append the raw fields of every `sample_every`th event to the file at `path`,
+    /// before they are converted, filtered or sampled. Read the file back with
+    /// [`CapturedEvent::read_all`](crate::CapturedEvent::read_all) and feed it to [`TelescopeLayer::replay_capture`] on a
+    /// layer with the same configuration to reproduce an attribute mapping issue.
+    pub fn with_capture_file(mut self, path: impl Into<PathBuf>, sample_every: u64) -> Self {
+        self.capture = Some((path.into(), sample_every));
+        self
+    }
+
+    /// Check the configuration without building the layer, listing every problem found.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+        if let Target::Url(url) = &self.target {
+            check_url("endpoint", url, &mut problems);
+        }
+        if let Some((url, _)) = &self.hedge {
+            check_url("hedging endpoint", url, &mut problems);
+        }
+        for (_, target) in &self.routes {
+            if let Target::Url(url) = target {
+                check_url("route endpoint", url, &mut problems);
+            }
+        }
+        for target in &self.mirrors {
+            if let Target::Url(url) = target {
+                check_url("mirror endpoint", url, &mut problems);
+            }
+        }
+        problems.extend(self.directives.iter().filter_map(|directive| directive.clone().err()));
+        if self.config.coalescing_window.is_zero() {
+            problems.push("coalescing window must not be zero".to_string());
+        }
+        if self.quota.is_some_and(|(_, interval)| interval.is_zero())
+            || self.severity_quotas.iter().any(|(_, _, interval)| interval.is_zero()) {
+            problems.push("quota interval must not be zero".to_string());
+        }
+        problems.extend(self.config.retry.problems());
+        if self.connect_timeout.is_some_and(|timeout| timeout.is_zero()) {
+            problems.push("connect timeout must not be zero".to_string());
+        }
+        if self.quota_polling.is_some_and(|interval| interval.is_zero()) {
+            problems.push("quota polling interval must not be zero".to_string());
+        }
+        if let Err(problem) = parse_headers(&self.auth_headers, "authentication header") {
+            problems.push(problem);
+        }
+        if let Err(problem) = parse_headers(&self.metadata_headers, "metadata header") {
+            problems.push(problem);
+        }
+        for (name, _) in &self.metadata_headers {
+            if name.starts_with("grpc-") || name.starts_with("x-telescope-") {
+                problems.push(format!("metadata header {name:?} is reserved"));
+            }
+        }
+        if !self.auth_headers.is_empty() && matches!(self.target, Target::Sink(_)) {
+            problems.push("authentication headers need a gRPC endpoint, not a sink".to_string());
+        }
+        if !self.interceptors.is_empty() && matches!(self.target, Target::Sink(_)) {
+            problems.push("interceptors need a gRPC endpoint, not a sink".to_string());
+        }
+        if self.quota_polling.is_some() && matches!(self.target, Target::Sink(_)) {
+            problems.push("quota polling needs a gRPC endpoint, not a sink".to_string());
+        }
+        if self.quota_polling.is_some() && self.overflow == OverflowPolicy::Block {
+            problems.push("quota polling needs a queue that drops records when full, not one that blocks".to_string());
+        }
+        if self.runtime_metrics.is_some_and(|interval| interval.is_zero()) {
+            problems.push("runtime metrics interval must not be zero".to_string());
+        }
+        if matches!(self.span_metrics, Some(SpanMetrics::Summary(interval)) if interval.is_zero()) {
+            problems.push("span summary interval must not be zero".to_string());
+        }
+        if self.dedup_window.is_some_and(|window| window.is_zero()) {
+            problems.push("error dedup window must not be zero".to_string());
+        }
+        if self.queue_capacity == 0 {
+            problems.push("queue capacity must be at least 1".to_string());
+        }
+        if self.config.max_batch_bytes == Some(0) {
+            problems.push("max batch size must be at least 1 byte".to_string());
+        }
+        if self.config.early_buffer == Some(0) {
+            problems.push("early buffer must hold at least one record".to_string());
+        }
+        if self.flight_recorder == Some(0) {
+            problems.push("flight recorder must hold at least one record".to_string());
+        }
+        if self.tail_buffering.as_ref().is_some_and(|tail_buffering| tail_buffering.max_records() == 0) {
+            problems.push("tail buffering must hold at least one record per span".to_string());
+        }
+        if let Some(dir) = self.disk_queue.as_ref().filter(|dir| dir.exists() && !dir.is_dir()) {
+            problems.push(format!("disk queue {} is not a directory", dir.display()));
+        }
+        if self.config.attribute_dictionary {
+            let compressed_elsewhere = [
+                ("a disk queue", self.disk_queue.is_some()),
+                ("a fallback sink", self.config.fallback.is_some()),
+                ("hedging", self.hedge.is_some()),
+            ];
+            for (setting, set) in compressed_elsewhere {
+                if set {
+                    problems.push(format!("attribute dictionary can't be combined with {setting}"));
+                }
+            }
+        }
+        #[cfg(feature = "disk-queue-encryption")]
+        if self.disk_queue_key.is_some() && self.disk_queue.is_none() {
+            problems.push("disk queue key is set but there is no disk queue".to_string());
+        }
+        if matches!(self.target, Target::Shared(_)) {
+            let exporter_settings = [
+                ("routes", !self.routes.is_empty()),
+                ("mirrors", !self.mirrors.is_empty()),
+                ("hedging", self.hedge.is_some()),
+                ("a disk queue", self.disk_queue.is_some()),
+                ("processors", !self.processors.is_empty()),
+                ("a record filter", self.record_filter.is_some()),
+                ("runtime metrics", self.runtime_metrics.is_some()),
+                ("span summaries", matches!(self.span_metrics, Some(SpanMetrics::Summary(_)))),
+                ("error dedup", self.dedup_window.is_some()),
+                ("an attribute dictionary", self.config.attribute_dictionary),
+                ("quota polling", self.quota_polling.is_some()),
+                ("authentication headers", !self.auth_headers.is_empty()),
+                ("interceptors", !self.interceptors.is_empty()),
+                ("metadata headers", !self.metadata_headers.is_empty()),
+            ];
+            for (setting, set) in exporter_settings {
+                if set {
+                    problems.push(format!("{setting} can't be set on a layer sharing another layer's pipeline"));
+                }
+            }
+        }
+        #[cfg(feature = "tls")]
+        if let Some(path) = self.tls_settings.ca_certificate_file.as_ref().filter(|path| !path.is_file()) {
+            problems.push(format!("CA certificate file {} does not exist", path.display()));
+        }
+        if let Some((path, sample_every)) = &self.capture {
+            if *sample_every == 0 {
+                problems.push("capture sample rate must be at least 1".to_string());
+            }
+            if path.parent().is_some_and(|dir| !dir.as_os_str().is_empty() && !dir.is_dir()) {
+                problems.push(format!("directory of capture file {} does not exist", path.display()));
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError { problems })
+        }
+    }
+
+    /// Build the layer, or report why it can't be: every configuration problem at once
+    /// (see [`Self::validate`]), or the first resource that could not be set up. Every
+    /// endpoint is connected before this returns.
+    pub async fn try_build(self) -> Result<TelescopeLayer, TelescopeError> {
+        self.validate()?;
+        let mut channels = HashMap::new();
+        for url in self.urls() {
+            if !channels.contains_key(url) {
+                let endpoint = self.endpoint(url)?;
+                let channel = endpoint.connect_with_connector(self.connector(self.is_main(url))).await.map_err(TelescopeError::Transport)?;
+                channels.insert(url.to_string(), channel);
+            }
+        }
+        if let Target::Endpoint(endpoint) = &self.target {
+            let channel = match self.endpoint_connector() {
+                Some(connector) => endpoint.connect_with_connector(connector).await,
+                None => endpoint.connect().await,
+            };
+            channels.insert(endpoint.uri().to_string(), channel.map_err(TelescopeError::Transport)?);
+        }
+        self.build_connected(channels, None)
+    }
+
+    /// Like [`Self::try_build`], but callable outside an async runtime and without
+    /// waiting for the collector: the exporter thread connects in the background, and
+    /// records logged in the meantime stay queued (and are retried) until it can reach
+    /// the collector. Only configuration and local resources (disk queue, capture file,
+    /// exporter threads) can fail the build.
+    pub fn try_build_lazy(self) -> Result<TelescopeLayer, TelescopeError> {
+        self.validate()?;
+        let runtime = exporter_runtime().map_err(TelescopeError::Exporter)?;
+        self.build_connected(HashMap::new(), Some(runtime))
+    }
+
+    fn is_main(&self, url: &str) -> bool {
+        matches!(&self.target, Target::Url(main) if main == url)
+    }
+
+    fn connector(&self, main: bool) -> ResolvingConnector {
+        ResolvingConnector {
+            resolver: self.resolver.clone(),
+            addresses: Arc::new(self.resolved_addresses.clone()),
+            attempt_timeout: self.connect_timeout,
+            peer: main.then(|| self.peer.clone()),
+        }
+    }
+
+    // An endpoint passed in connects with its own connector, which applies its TCP
+    // settings (keep-alive, nodelay), unless the builder has to resolve its host.
+    fn endpoint_connector(&self) -> Option<ResolvingConnector> {
+        (self.resolver.is_some() || !self.resolved_addresses.is_empty()).then(|| self.connector(true))
+    }
+
+    fn endpoint(&self, url: &str) -> Result<Endpoint, TelescopeError> {
+        let endpoint = Channel::from_shared(url.to_string()).map_err(|error| invalid_uri(url, error))?;
+        #[cfg(feature = "tls")]
+        if endpoint.uri().scheme_str() == Some("https") {
+            return endpoint.tls_config(self.tls_config()?).map_err(TelescopeError::Transport);
+        }
+        Ok(endpoint)
+    }
+
+    #[cfg(feature = "tls")]
+    fn tls_config(&self) -> Result<ClientTlsConfig, TelescopeError> {
+        let Some(path) = &self.tls_settings.ca_certificate_file else {
+            return Ok(self.tls.clone());
+        };
+        let pem = std::fs::read(path)
+            .map_err(|error| TelescopeError::Tls(std::io::Error::new(error.kind(), format!("{}: {error}", path.display()))))?;
+        Ok(self.tls.clone().ca_certificate(Certificate::from_pem(pem)))
+    }
+
+    // Needs a runtime context.
+    fn lazy_channel(&self, url: &str) -> Result<Channel, TelescopeError> {
+        let endpoint = self.endpoint(url)?;
+        Ok(endpoint.connect_with_connector_lazy(self.connector(self.is_main(url))))
+    }
+
+    fn urls(&self) -> impl Iterator<Item=&str> {
+        let routes = self.routes.iter().map(|(_, target)| target);
+        std::iter::once(&self.target).chain(routes).chain(&self.mirrors).filter_map(|target| match target {
+            Target::Url(url) => Some(url.as_str()),
+            _ => None,
+        })
+    }
+
+    // Endpoints missing from `channels` are connected lazily, on `runtime` if given (it
+    // then also runs the main exporter).
+    fn build_connected(mut self, mut channels: HashMap<String, Channel>, runtime: Option<Runtime>) -> Result<TelescopeLayer, TelescopeError> {
+        // Everything that can fail is set up before the first exporter thread starts.
+        let capture = self.capture.take()
+            .map(|(path, sample_every)| CaptureWriter::open(&path, sample_every))
+            .transpose()
+            .map_err(TelescopeError::Capture)?;
+        let settings = Arc::new(self.settings());
+        if let Target::Shared(backend) = &self.target {
+            let backend = backend.clone();
+            return Ok(self.into_shared_layer(backend, settings, capture));
+        }
+        #[cfg(feature = "disk-queue-encryption")]
+        let disk_queue_key = self.disk_queue_key.take()
+            .map(|key| key())
+            .transpose()
+            .map_err(TelescopeError::DiskQueue)?;
+        let disk_queue = self.disk_queue.take()
+            .map(|dir| {
+                #[cfg(feature = "disk-queue-encryption")]
+                if let Some(key) = &disk_queue_key {
+                    return DiskQueue::open_encrypted(dir, key);
+                }
+                DiskQueue::open(dir)
+            })
+            .transpose()
+            .map_err(|error| TelescopeError::DiskQueue(error.into()))?;
+        // Runtime metrics are about the application's runtime, not the exporter's.
+        self.config.runtime_metrics = self.runtime_metrics
+            .map(|interval| (interval, tokio::runtime::Handle::try_current().ok()));
+        let stats = match &self.target {
+            Target::Transport(transport) => transport.stats(self.config.clock.clone(), self.connection_events),
+            _ => Arc::new(ExportStats::new(self.config.clock.clone(), self.connection_events).with_peer(self.peer.clone())),
+        };
+        let guard = runtime.as_ref().map(Runtime::enter);
+        let lazy_urls = self.urls().filter(|url| !channels.contains_key(*url)).map(str::to_string).collect::<Vec<_>>();
+        for url in lazy_urls {
+            let channel = self.lazy_channel(&url)?;
+            channels.insert(url, channel);
+        }
+        if let Target::Endpoint(endpoint) = &self.target {
+            channels.entry(endpoint.uri().to_string()).or_insert_with(|| match self.endpoint_connector() {
+                Some(connector) => endpoint.connect_with_connector_lazy(connector),
+                None => endpoint.connect_lazy(),
+            });
+        }
+        let auth = AuthInterceptor::new(&self.auth_headers, std::mem::take(&mut self.interceptors)).map_err(|problem| ConfigError { problems: vec![problem] })?;
+        self.config.metadata = Arc::new(parse_headers(&self.metadata_headers, "metadata header")
+            .map_err(|problem| ConfigError { problems: vec![problem] })?);
+        let hedge = match self.hedge.take() {
+            Some((url, after)) => Some(Hedge { client: ExportClient::new(auth.channel(self.lazy_channel(&url)?)), after }),
+            None => None,
+        };
+        let capabilities = Arc::new(Capabilities::default());
+        let destination = self.target.clone().connect(hedge, Some(stats.clone()), Some(capabilities.clone()), &auth, &mut channels)?;
+        let quota_polling = self.quota_polling
+            .and_then(|interval| Some((UsageClient::new(auth.channel(self.target.channel(&channels)?)), interval)));
+        let mut route_destinations = Vec::with_capacity(self.routes.len());
+        for (matcher, target) in std::mem::take(&mut self.routes) {
+            route_destinations.push((matcher, target.connect(None, None, None, &AuthInterceptor::default(), &mut channels)?));
+        }
+        let mut mirror_destinations = Vec::with_capacity(self.mirrors.len());
+        for target in std::mem::take(&mut self.mirrors) {
+            mirror_destinations.push(target.connect(None, None, None, &AuthInterceptor::default(), &mut channels)?);
+        }
+        drop(guard);
+
+        let mut routes = Vec::with_capacity(route_destinations.len());
+        for (matcher, destination) in route_destinations {
+            let (tx, rx) = self.queue(&stats);
+            self.start_exporter(rx, destination, None)?;
+            routes.push(Route { matcher, tx });
+        }
+        let mut mirrors = Vec::with_capacity(mirror_destinations.len());
+        for destination in mirror_destinations {
+            let (tx, rx) = self.queue(&stats);
+            self.start_exporter(rx, destination, None)?;
+            mirrors.push(tx);
+        }
+
+        let span_stats = Arc::new(SpanStats::default());
+        if let Some(SpanMetrics::Summary(interval)) = self.span_metrics {
+            self.config.span_summaries = Some((span_stats.clone(), interval));
+        }
+        let dedup = self.dedup_window.map(|window| Arc::new(Deduplicator::new(window)));
+        self.config.dedup = dedup.clone();
+        self.config.stats = Some(stats.clone());
+        self.config.capabilities = Some(capabilities.clone());
+        self.config.quota_polling = quota_polling;
+        let recent = Arc::new(RecentRecords::default());
+        self.config.recent = Some(recent.clone());
+        self.config.disk_queue = disk_queue.map(Arc::new);
+        let (tx, rx) = self.queue(&stats);
+        let clock = self.config.clock.clone();
+        let sender = RecordSender {
+            tx,
+            routes: Arc::new(routes),
+            mirrors: Arc::new(mirrors),
+            processors: Arc::new(std::mem::take(&mut self.processors)),
+            strict_processors: self.strict_processors,
+            record_filter: self.record_filter.take(),
+            in_flight: self.config.in_flight.clone(),
+            resource: self.config.resource.clone(),
+        };
+        let handle = TelescopeHandle {
+            resource: self.config.resource.clone(),
+            filter: Arc::new(self.level_filter()),
+            stats,
+            disk_queue: self.config.disk_queue.clone(),
+            sender: sender.clone(),
+            clock: clock.clone(),
+            shutdown: self.config.shutdown.clone(),
+            capabilities,
+            config: settings,
+            recent,
+            probe: Arc::new(Mutex::new(self.config.export_stack(destination.clone()))),
+        };
+        let owner = Arc::new(Owner { shutdown: self.config.shutdown.clone(), clock });
+        self.start_exporter(rx, destination, runtime)?;
+        Ok(self.into_layer(sender, handle, owner, span_stats, dedup, capture))
+    }
+
+    fn queue(&self, stats: &Arc<ExportStats>) -> (QueueSender, QueueReceiver) {
+        queue(self.queue_capacity, self.overflow, self.config.in_flight.clone(), stats.clone())
+    }
+
+    // The configuration reported by `TelescopeHandle::current_config`, before the
+    // resource and levels are filled in.
+    fn settings(&self) -> TelescopeConfig {
+        TelescopeConfig {
+            service_name: self.config.service_name.clone(),
+            endpoint: self.target.describe(),
+            hedge_endpoint: self.hedge.as_ref().map(|(url, _)| url.clone()),
+            hedge_after: self.hedge.as_ref().map(|(_, after)| *after),
+            routes: self.routes.iter().map(|(_, target)| target.describe()).collect(),
+            mirrors: self.mirrors.iter().map(Target::describe).collect(),
+            resource_attributes: Vec::new(),
+            schema_url: String::new(),
+            level: self.min_level,
+            target_levels: Vec::new(),
+            record_filter: self.record_filter.as_ref().map(|filter| filter.as_str().to_string()),
+            queue_capacity: self.queue_capacity,
+            overflow: self.overflow,
+            coalescing_window: self.config.coalescing_window,
+            flush_jitter: self.config.flush_jitter,
+            early_buffer: self.config.early_buffer,
+            max_batch_bytes: self.config.max_batch_bytes,
+            batch_summary: self.config.batch_summary,
+            capabilities_probe: self.config.capabilities_probe,
+            attribute_dictionary: self.config.attribute_dictionary,
+            retry: self.config.retry,
+            connect_timeout: self.connect_timeout,
+            #[cfg(feature = "tls")]
+            tls: Some(self.tls_settings.clone()),
+            #[cfg(not(feature = "tls"))]
+            tls: None,
+            quota: self.quota,
+            severity_quotas: self.severity_quotas.clone(),
+            load_shedding: self.load_shedding.as_ref().map(|shedding| (shedding.max_in_flight, shedding.shed_below)),
+            server_sampling: self.server_sampling,
+            sampling_exemption_targets: self.exemptions.targets.clone(),
+            sampling_exemption_filters: self.exemptions.filters.iter().map(|filter| filter.as_str().to_string()).collect(),
+            error_dedup_window: self.dedup_window,
+            quota_polling: self.quota_polling,
+            authentication_headers: self.auth_headers.iter().map(|(name, _)| name.clone()).collect(),
+            metadata_headers: self.metadata_headers.clone(),
+            disk_queue: self.disk_queue.clone(),
+        }
+    }
+
+    fn level_filter(&self) -> DynamicFilter {
+        let default = self.directives.iter().rev()
+            .find_map(|directive| match directive {
+                Ok((None, level)) => Some(*level),
+                _ => None,
+            })
+            .unwrap_or(self.min_level);
+        let filter = DynamicFilter::new(default);
+        for (target, level) in self.directives.iter().flatten() {
+            if let Some(target) = target {
+                filter.set(target.clone(), *level);
+            }
+        }
+        filter
+    }
+
+    fn start_exporter(&self, rx: QueueReceiver, destination: BoxExportService, runtime: Option<Runtime>) -> Result<(), TelescopeError> {
+        let runtime = match runtime {
+            Some(runtime) => runtime,
+            None => exporter_runtime().map_err(TelescopeError::Exporter)?,
+        };
+        start_logging_thread(rx, destination, self.config.clone(), runtime).map_err(TelescopeError::Exporter)
+    }
+
+    // A layer of its own (levels, quotas, sampling, resource) feeding `backend`'s
+    // exporters, routes and mirrors.
+    fn into_shared_layer(self, backend: TelescopeSender, settings: Arc<TelescopeConfig>, capture: Option<CaptureWriter>) -> TelescopeLayer {
+        let resource = self.config.resource.clone();
+        let sender = RecordSender { resource: resource.clone(), ..backend.handle.sender.clone() };
+        let handle = TelescopeHandle {
+            resource,
+            filter: Arc::new(self.level_filter()),
+            sender: sender.clone(),
+            clock: self.config.clock.clone(),
+            config: settings,
+            ..backend.handle
+        };
+        self.into_layer(sender, handle, backend.owner, Arc::new(SpanStats::default()), None, capture)
+    }
+
+    fn into_layer(self, sender: RecordSender, handle: TelescopeHandle, owner: Arc<Owner>, span_stats: Arc<SpanStats>,
+                  dedup: Option<Arc<Deduplicator>>, capture: Option<CaptureWriter>) -> TelescopeLayer {
+        let clock = self.config.clock.clone();
+        TelescopeLayer {
+            attribute_limits: self.config.attribute_limits,
+            generate_trace_ids: self.generate_trace_ids,
+            message_in_attributes: self.message_in_attributes,
+            timestamp_sequence: self.timestamp_sequence.then(TimestampSequence::default),
+            logs: self.logs,
+            quotas: Quotas {
+                global: self.quota.map(|(records, interval)| Quota::new(records, interval, clock.now_unix_nano())),
+                by_level: self.severity_quotas.into_iter()
+                    .map(|(level, records, interval)| (level, Quota::new(records, interval, clock.now_unix_nano())))
+                    .collect(),
+            },
+            id_generator: self.config.id_generator.clone(),
+            filter: handle.filter.clone(),
+            load_shedding: self.load_shedding,
+            server_sampling: self.server_sampling.then(|| handle.capabilities.clone()),
+            in_flight: sender.in_flight.clone(),
+            span_metrics: self.span_metrics,
+            span_stats,
+            span_fields: self.span_fields,
+            sticky_debug: self.sticky_debug.map(StickyDebugState::new),
+            flight_recorder: self.flight_recorder,
+            tail_buffering: self.tail_buffering,
+            dedup,
+            key_normalizer: self.key_normalizer,
+            exemptions: self.exemptions,
+            capture,
+            owner,
+            sender,
+            handle,
+            clock,
+        }
+    }
+
+    /// Like [`Self::try_build`].
+    ///
+    /// # Panics
+    ///
+    /// If the configuration is invalid or the layer can't be set up, listing why.
+    pub async fn build(self) -> TelescopeLayer {
+        self.try_build().await.unwrap_or_else(|error| panic!("{error}"))
+    }
+}
+
+fn check_url(role: &str, url: &str, problems: &mut Vec<String>) {
+    match url.parse::<Uri>() {
+        Ok(uri) if uri.host().is_none() => problems.push(format!("{role} {url:?} has no host")),
+        Ok(uri) if uri.scheme_str() == Some("https") && !cfg!(feature = "tls") => {
+            problems.push(format!("{role} {url:?} needs TLS, which needs the `tls` feature"));
+        }
+        Ok(uri) if !matches!(uri.scheme_str(), Some("http" | "https")) => {
+            problems.push(format!("{role} {url:?} must use http, https, grpc or grpcs"));
+        }
+        Ok(_) => {}
+        Err(error) => problems.push(format!("{role} {url:?} is not a valid url: {error}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use tower::BoxError;
+
+    use crate::error::TelescopeError;
+    use crate::retry::RetryPolicy;
+    use crate::service::ExportRequest;
+    use crate::sink::Sink;
+    use crate::TelescopeLayer;
+
+    struct Discard;
+
+    impl Sink for Discard {
+        fn export(&mut self, _: &ExportRequest) -> Result<(), BoxError> {
+            Ok(())
+        }
+    }
+
+    // An empty directory of its own for every test.
+    fn scratch_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("telescope-{}-{test}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn invalid_endpoint_is_a_config_error() {
+        let result = TelescopeLayer::builder("test".to_string(), "http://collector:43 17".to_string()).try_build_lazy();
+        assert!(matches!(result, Err(TelescopeError::Config(error)) if error.problems.len() == 1 && error.problems[0].contains("is not a valid url")));
+    }
+
+    #[test]
+    fn empty_flight_recorder_is_a_config_error() {
+        let error = TelescopeLayer::builder_with_sink("test".to_string(), Discard).with_flight_recorder(0).validate().unwrap_err();
+        assert_eq!(error.problems, ["flight recorder must hold at least one record"]);
+    }
+
+    #[test]
+    fn config_json_has_no_bare_infinity() {
+        let policy = RetryPolicy::exponential(Duration::from_secs(1), Duration::from_secs(60)).with_multiplier(f64::INFINITY);
+        let json = TelescopeLayer::builder_with_sink("test".to_string(), Discard).with_retry_policy(policy).settings().to_json();
+        assert!(json.contains(r#""multiplier":"inf","jitter":0,"#), "{json}");
+    }
+
+    #[test]
+    fn disk_queue_that_cannot_be_created_fails_the_build() {
+        let dir = scratch_dir("disk-queue");
+        let file = dir.join("file");
+        fs::write(&file, b"").unwrap();
+        let result = TelescopeLayer::builder_with_sink("test".to_string(), Discard)
+            .with_disk_queue(file.join("queue"))
+            .try_build_lazy();
+        assert!(matches!(result, Err(TelescopeError::DiskQueue(_))));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn capture_file_that_cannot_be_opened_fails_the_build() {
+        let dir = scratch_dir("capture");
+        let result = TelescopeLayer::builder_with_sink("test".to_string(), Discard)
+            .with_capture_file(&dir, 1)
+            .try_build_lazy();
+        assert!(matches!(result, Err(TelescopeError::Capture(_))));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,65 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tonic::metadata::MetadataMap;
+
+use crate::dictionary::Dictionary;
+use crate::server_sampling::ServerSampling;
+
+// Sent with the capabilities probe; servers that know it answer with the headers below
+// (on every response, not just the probe's).
+pub(crate) const CAPABILITIES_REQUEST_HEADER: &str = "x-telescope-capabilities";
+const MAX_BATCH_RECORDS_HEADER: &str = "x-telescope-max-batch-records";
+
+/// What the main endpoint told the exporter about itself, from
+/// [`TelescopeHandle::server_capabilities`](crate::TelescopeHandle::server_capabilities).
+/// Generic OTLP collectors announce nothing, and the exporter keeps its defaults.
+///
+/// Only the batch size limit and the attribute dictionary are negotiated. Compression
+/// and encoding are not: exports are always uncompressed OTLP protobuf, which every
+/// collector accepts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ServerCapabilities {
+    /// Largest batch the server accepts; batches are capped to it.
+    pub max_batch_records: Option<usize>,
+    /// Version of the attribute dictionary the server sent, see
+    /// [`TelescopeLayerBuilder::with_attribute_dictionary`](crate::TelescopeLayerBuilder::with_attribute_dictionary).
+    pub dictionary_version: Option<u64>,
+}
+
+// Updated from the response headers of every export to the main endpoint.
+#[derive(Default)]
+pub(crate) struct Capabilities {
+    // 0 while unknown.
+    max_batch_records: AtomicUsize,
+    dictionary: Mutex<Option<Arc<Dictionary>>>,
+    pub(crate) sampling: ServerSampling,
+}
+
+impl Capabilities {
+    pub(crate) fn update(&self, metadata: &MetadataMap) {
+        let max_batch_records = metadata.get(MAX_BATCH_RECORDS_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<usize>().ok());
+        if let Some(max_batch_records) = max_batch_records {
+            self.max_batch_records.store(max_batch_records, Ordering::Relaxed);
+        }
+        if let Some(dictionary) = Dictionary::from_metadata(metadata) {
+            *self.dictionary.lock().unwrap() = Some(Arc::new(dictionary));
+        }
+        self.sampling.update(metadata);
+    }
+
+    pub(crate) fn dictionary(&self) -> Option<Arc<Dictionary>> {
+        self.dictionary.lock().unwrap().clone()
+    }
+
+    pub(crate) fn snapshot(&self) -> ServerCapabilities {
+        let max_batch_records = self.max_batch_records.load(Ordering::Relaxed);
+        ServerCapabilities {
+            max_batch_records: (max_batch_records > 0).then_some(max_batch_records),
+            dictionary_version: self.dictionary().map(|dictionary| dictionary.version),
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tracing::{Event, Level};
+use tracing::field::{Field, Visit};
+
+const HEADER: &str = "# telescope capture v1";
+
+/// A field value as `tracing` handed it to the layer, before it was converted into an
+/// OTLP attribute.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CapturedValue {
+    F64(f64),
+    I64(i64),
+    U64(u64),
+    Bool(bool),
+    Str(String),
+    /// Recorded through `Debug`, already formatted.
+    Debug(String),
+}
+
+/// An event written to a capture file by
+/// [`TelescopeLayerBuilder::with_capture_file`](crate::TelescopeLayerBuilder::with_capture_file).
+///
+/// Span fields, context attributes and trace context are not captured; replaying an
+/// event reproduces the conversion of the event's own fields.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct CapturedEvent {
+    pub time_unix_nano: u64,
+    pub level: Level,
+    pub target: String,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub fields: Vec<(String, CapturedValue)>,
+}
+
+impl CapturedEvent {
+    /// Read every event in the capture file at `path`, oldest first.
+    pub fn read_all(path: impl AsRef<Path>) -> io::Result<Vec<CapturedEvent>> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut events = Vec::new();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let event = decode(&line).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("malformed capture on line {}", index + 1))
+            })?;
+            events.push(event);
+        }
+        Ok(events)
+    }
+}
+
+/// Appends every `sample_every`th event to a capture file.
+pub(crate) struct CaptureWriter {
+    file: Mutex<BufWriter<File>>,
+    sample_every: u64,
+    seen: AtomicU64,
+}
+
+impl CaptureWriter {
+    pub(crate) fn open(path: &Path, sample_every: u64) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut file = BufWriter::new(file);
+        if file.get_ref().metadata()?.len() == 0 {
+            writeln!(file, "{HEADER}")?;
+            file.flush()?;
+        }
+        Ok(Self {
+            file: Mutex::new(file),
+            sample_every: sample_every.max(1),
+            seen: AtomicU64::new(0),
+        })
+    }
+
+    pub(crate) fn capture(&self, event: &Event<'_>, time_unix_nano: u64) {
+        if !self.seen.fetch_add(1, Ordering::Relaxed).is_multiple_of(self.sample_every) {
+            return;
+        }
+        let metadata = event.metadata();
+        let mut visitor = CaptureVisitor(Vec::new());
+        event.record(&mut visitor);
+        let captured = CapturedEvent {
+            time_unix_nano,
+            level: *metadata.level(),
+            target: metadata.target().to_string(),
+            file: metadata.file().map(str::to_string),
+            line: metadata.line(),
+            fields: visitor.0,
+        };
+        // Flushed per event so the capture is usable even if the process dies.
+        let mut file = self.file.lock().unwrap();
+        let _ = writeln!(file, "{}", encode(&captured)).and_then(|_| file.flush());
+    }
+}
+
+struct CaptureVisitor(Vec<(String, CapturedValue)>);
+
+impl Visit for CaptureVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.push((field.name().to_string(), CapturedValue::F64(value)));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.push((field.name().to_string(), CapturedValue::I64(value)));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.push((field.name().to_string(), CapturedValue::U64(value)));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.push((field.name().to_string(), CapturedValue::Bool(value)));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.push((field.name().to_string(), CapturedValue::Str(value.to_string())));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.push((field.name().to_string(), CapturedValue::Debug(format!("{:?}", value))));
+    }
+}
+
+// One event per line, tab separated: time, level, target, file, line, then a
+// name, kind, value triple per field. Tabs, newlines and backslashes are escaped.
+fn encode(event: &CapturedEvent) -> String {
+    let mut columns = vec![
+        event.time_unix_nano.to_string(),
+        event.level.to_string(),
+        escape(&event.target),
+        event.file.as_deref().map(escape).unwrap_or_default(),
+        event.line.map(|line| line.to_string()).unwrap_or_default(),
+    ];
+    for (name, value) in &event.fields {
+        let (kind, value) = match value {
+            CapturedValue::F64(value) => ("f64", value.to_string()),
+            CapturedValue::I64(value) => ("i64", value.to_string()),
+            CapturedValue::U64(value) => ("u64", value.to_string()),
+            CapturedValue::Bool(value) => ("bool", value.to_string()),
+            CapturedValue::Str(value) => ("str", escape(value)),
+            CapturedValue::Debug(value) => ("debug", escape(value)),
+        };
+        columns.extend([escape(name), kind.to_string(), value]);
+    }
+    columns.join("\t")
+}
+
+fn decode(line: &str) -> Option<CapturedEvent> {
+    let columns: Vec<&str> = line.split('\t').collect();
+    if columns.len() < 5 {
+        return None;
+    }
+    let (header, fields) = columns.split_at(5);
+    if !fields.len().is_multiple_of(3) {
+        return None;
+    }
+    let fields = fields.chunks(3)
+        .map(|field| {
+            let value = match field[1] {
+                "f64" => CapturedValue::F64(field[2].parse().ok()?),
+                "i64" => CapturedValue::I64(field[2].parse().ok()?),
+                "u64" => CapturedValue::U64(field[2].parse().ok()?),
+                "bool" => CapturedValue::Bool(field[2].parse().ok()?),
+                "str" => CapturedValue::Str(unescape(field[2])?),
+                "debug" => CapturedValue::Debug(unescape(field[2])?),
+                _ => return None,
+            };
+            Some((unescape(field[0])?, value))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(CapturedEvent {
+        time_unix_nano: header[0].parse().ok()?,
+        level: header[1].parse().ok()?,
+        target: unescape(header[2])?,
+        file: (!header[3].is_empty()).then(|| unescape(header[3])).flatten(),
+        line: (!header[4].is_empty()).then(|| header[4].parse().ok()).flatten(),
+        fields,
+    })
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(value: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        unescaped.push(match chars.next()? {
+            '\\' => '\\',
+            't' => '\t',
+            'n' => '\n',
+            'r' => '\r',
+            _ => return None,
+        });
+    }
+    Some(unescaped)
+}
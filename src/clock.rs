@@ -0,0 +1,262 @@
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::instrumentation;
+
+/// Time source used for record timestamps, the flush timer and retry backoff.
+pub trait Clock: Send + Sync + 'static {
+    /// Wall clock time in nanoseconds since the unix epoch.
+    fn now_unix_nano(&self) -> u64;
+
+    /// Monotonic time used for intervals.
+    fn now(&self) -> Instant;
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+// Latest timestamp handed out by `SystemClock`, shared by every layer in the process.
+static LAST_SYSTEM_UNIX_NANO: AtomicU64 = AtomicU64::new(0);
+// Timestamps `SystemClock` moved forward, for `TelescopeStats::timestamps_clamped`.
+static SYSTEM_TIMESTAMPS_CLAMPED: AtomicU64 = AtomicU64::new(0);
+
+pub(crate) fn system_timestamps_clamped() -> u64 {
+    SYSTEM_TIMESTAMPS_CLAMPED.load(Ordering::Relaxed)
+}
+
+/// Reads `SystemTime::now()` for every timestamp.
+///
+/// Timestamps never go backwards: if the system clock is stepped back (a VM snapshot
+/// restored, a manual clock reset) or reads before 1970, every timestamp is 1ns after
+/// the previous one until the clock catches up again. Such timestamps are counted in
+/// [`TelescopeStats::timestamps_clamped`](crate::TelescopeStats::timestamps_clamped) and,
+/// with the `metrics` feature, the `telescope_timestamps_clamped_total` counter.
+///
+/// To keep that order across threads, every timestamp in the process is taken through
+/// one compare-and-swap on a shared atomic, so threads logging at a high rate contend
+/// on its cache line. [`MonotonicClock`] reads no shared state.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_unix_nano(&self) -> u64 {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_nanos() as u64);
+        let mut last = LAST_SYSTEM_UNIX_NANO.load(Ordering::Relaxed);
+        loop {
+            let next = if now > last { now } else { last + 1 };
+            match LAST_SYSTEM_UNIX_NANO.compare_exchange_weak(last, next, Ordering::Relaxed, Ordering::Relaxed) {
+                Ok(_) => {
+                    if now < last {
+                        SYSTEM_TIMESTAMPS_CLAMPED.fetch_add(1, Ordering::Relaxed);
+                        instrumentation::timestamp_clamped();
+                    }
+                    return next;
+                }
+                Err(current) => last = current,
+            }
+        }
+    }
+
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Reads the wall clock once and derives every later timestamp from the monotonic
+/// clock, so timestamps never go backwards when the system clock is stepped.
+#[derive(Clone, Copy, Debug)]
+pub struct MonotonicClock {
+    anchor_unix_nano: u64,
+    anchor: Instant,
+}
+
+impl MonotonicClock {
+    pub fn new() -> Self {
+        Self {
+            anchor_unix_nano: SystemClock.now_unix_nano(),
+            anchor: Instant::now(),
+        }
+    }
+}
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MonotonicClock {
+    fn now_unix_nano(&self) -> u64 {
+        self.anchor_unix_nano + self.anchor.elapsed().as_nanos() as u64
+    }
+
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Clock that only moves when told to. `sleep` blocks until another thread has
+/// [advanced](Self::advance) the clock past the wake-up time, so a test decides exactly
+/// when flush timers and backoff fire.
+#[derive(Debug)]
+pub struct ManualClock {
+    start_unix_nano: u64,
+    start: Instant,
+    elapsed_nanos: AtomicU64,
+    // Held while advancing, so a sleeper can't miss the notification.
+    advancing: Mutex<()>,
+    advanced: Condvar,
+}
+
+impl ManualClock {
+    pub fn new(start_unix_nano: u64) -> Self {
+        Self {
+            start_unix_nano,
+            start: Instant::now(),
+            elapsed_nanos: AtomicU64::new(0),
+            advancing: Mutex::new(()),
+            advanced: Condvar::new(),
+        }
+    }
+
+    /// Moves the clock forward, waking up threads whose sleep is over.
+    pub fn advance(&self, duration: Duration) {
+        let _advancing = self.advancing.lock().unwrap();
+        self.elapsed_nanos.fetch_add(duration.as_nanos() as u64, Ordering::SeqCst);
+        self.advanced.notify_all();
+    }
+
+    fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.elapsed_nanos.load(Ordering::SeqCst))
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_unix_nano(&self) -> u64 {
+        self.start_unix_nano + self.elapsed().as_nanos() as u64
+    }
+
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        let wake_up = self.elapsed() + duration;
+        let mut advancing = self.advancing.lock().unwrap();
+        while self.elapsed() < wake_up {
+            advancing = self.advanced.wait(advancing).unwrap();
+        }
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    fn now_unix_nano(&self) -> u64 {
+        (**self).now_unix_nano()
+    }
+
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        (**self).sleep(duration)
+    }
+}
+
+/// Wraps another clock and serves cached timestamps, trading precision for a cheaper
+/// per-event timestamp on very hot logging paths.
+pub struct CoarseClock<C: Clock> {
+    state: Arc<CoarseState<C>>,
+    refresh_every_events: Option<u64>,
+}
+
+struct CoarseState<C> {
+    inner: C,
+    unix_nano: AtomicU64,
+    events: AtomicU64,
+}
+
+impl<C: Clock> CoarseClock<C> {
+    /// Refresh the cached time from a background thread every `resolution`. If the
+    /// thread can't be started, the time is read for every timestamp instead.
+    pub fn new(inner: C, resolution: Duration) -> Self {
+        let mut clock = Self::with_state(inner, None);
+        let state = Arc::downgrade(&clock.state);
+        let spawned = thread::Builder::new().name("telescope-clock".to_string()).spawn(move || {
+            while let Some(state) = state.upgrade() {
+                state.unix_nano.store(state.inner.now_unix_nano(), Ordering::Relaxed);
+                drop(state);
+                thread::sleep(resolution);
+            }
+        });
+        if spawned.is_err() {
+            clock.refresh_every_events = Some(1);
+        }
+        clock
+    }
+
+    /// Refresh the cached time on every `events`-th timestamp instead of on a timer.
+    pub fn every_n_events(inner: C, events: u64) -> Self {
+        Self::with_state(inner, Some(events.max(1)))
+    }
+
+    fn with_state(inner: C, refresh_every_events: Option<u64>) -> Self {
+        let unix_nano = AtomicU64::new(inner.now_unix_nano());
+        Self {
+            state: Arc::new(CoarseState { inner, unix_nano, events: AtomicU64::new(0) }),
+            refresh_every_events,
+        }
+    }
+}
+
+impl<C: Clock> Clock for CoarseClock<C> {
+    fn now_unix_nano(&self) -> u64 {
+        if let Some(every) = self.refresh_every_events {
+            if self.state.events.fetch_add(1, Ordering::Relaxed).is_multiple_of(every) {
+                let now = self.state.inner.now_unix_nano();
+                self.state.unix_nano.store(now, Ordering::Relaxed);
+                return now;
+            }
+        }
+        self.state.unix_nano.load(Ordering::Relaxed)
+    }
+
+    fn now(&self) -> Instant {
+        self.state.inner.now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.state.inner.sleep(duration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::{Clock, ManualClock};
+
+    #[test]
+    fn manual_sleep_waits_for_advance() {
+        let clock = Arc::new(ManualClock::new(0));
+        let sleeper = thread::spawn({
+            let clock = clock.clone();
+            move || clock.sleep(Duration::from_secs(2))
+        });
+        // Give the sleeper time to start sleeping.
+        thread::sleep(Duration::from_millis(50));
+        clock.advance(Duration::from_secs(1));
+        thread::sleep(Duration::from_millis(50));
+        assert!(!sleeper.is_finished());
+        clock.advance(Duration::from_secs(1));
+        sleeper.join().unwrap();
+        assert_eq!(clock.now_unix_nano(), 2_000_000_000);
+    }
+}
@@ -0,0 +1,271 @@
+use std::fmt::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tracing::Level;
+use tracing::level_filters::LevelFilter;
+
+use crate::decode::push_json_string;
+use crate::queue::OverflowPolicy;
+use crate::retry::{RetryExhausted, RetryPolicy};
+
+/// The effective configuration of a running layer, from
+/// [`crate::TelescopeHandle::current_config`]: what it was built with, and the resource
+/// and levels as changed through the handle since. Secrets are left out: authentication
+/// headers are listed by name only, and disk queue keys not at all.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct TelescopeConfig {
+    pub service_name: String,
+    /// Url of the main endpoint, or `channel`, `transport`, `sink` or `shared` for a
+    /// target passed in.
+    pub endpoint: String,
+    pub hedge_endpoint: Option<String>,
+    /// How long an export may take before it is hedged.
+    pub hedge_after: Option<Duration>,
+    /// Endpoints of the routes, in matching order, described like `endpoint`.
+    pub routes: Vec<String>,
+    pub mirrors: Vec<String>,
+    pub resource_attributes: Vec<(String, String)>,
+    pub schema_url: String,
+    /// Level of targets without a level of their own.
+    pub level: LevelFilter,
+    /// Levels of targets, most specific first.
+    pub target_levels: Vec<(String, LevelFilter)>,
+    /// Source of the record filter, if any.
+    pub record_filter: Option<String>,
+    pub queue_capacity: usize,
+    pub overflow: OverflowPolicy,
+    pub coalescing_window: Duration,
+    pub flush_jitter: Duration,
+    pub early_buffer: Option<usize>,
+    pub max_batch_bytes: Option<usize>,
+    pub batch_summary: bool,
+    pub capabilities_probe: bool,
+    pub attribute_dictionary: bool,
+    pub retry: RetryPolicy,
+    pub connect_timeout: Option<Duration>,
+    pub tls: Option<TlsSettings>,
+    /// Records per interval.
+    pub quota: Option<(u64, Duration)>,
+    pub severity_quotas: Vec<(Level, u64, Duration)>,
+    /// Records in flight above which less severe records than the level are shed.
+    pub load_shedding: Option<(usize, Level)>,
+    pub server_sampling: bool,
+    pub sampling_exemption_targets: Vec<String>,
+    /// Sources of the sampling exemption filters.
+    pub sampling_exemption_filters: Vec<String>,
+    pub error_dedup_window: Option<Duration>,
+    pub quota_polling: Option<Duration>,
+    pub authentication_headers: Vec<String>,
+    pub metadata_headers: Vec<(String, String)>,
+    pub disk_queue: Option<PathBuf>,
+}
+
+/// TLS settings of `https://` url endpoints, as far as they were made through the
+/// builder. Certificates and keys themselves are left out.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TlsSettings {
+    pub domain: Option<String>,
+    pub ca_certificate_file: Option<PathBuf>,
+    /// Whether CA certificates were passed in memory.
+    pub ca_certificates: bool,
+    pub client_identity: bool,
+    /// Whether a whole `ClientTlsConfig` was passed in, which the other fields know
+    /// nothing about.
+    pub custom_config: bool,
+}
+
+impl TelescopeConfig {
+    /// The configuration as a JSON object, e.g. for an admin endpoint or a support
+    /// bundle. Durations are in milliseconds, levels lowercase, and numbers JSON can't
+    /// hold (an infinite retry multiplier) strings.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{");
+        field(&mut json, "service_name");
+        push_json_string(&mut json, &self.service_name);
+        field(&mut json, "endpoint");
+        push_json_string(&mut json, &self.endpoint);
+        field(&mut json, "hedge_endpoint");
+        optional(&mut json, self.hedge_endpoint.as_deref(), push_json_string);
+        field(&mut json, "hedge_after_ms");
+        optional(&mut json, self.hedge_after, millis);
+        field(&mut json, "routes");
+        strings(&mut json, &self.routes);
+        field(&mut json, "mirrors");
+        strings(&mut json, &self.mirrors);
+        field(&mut json, "resource_attributes");
+        pairs(&mut json, self.resource_attributes.iter().map(|(key, value)| (key.as_str(), value.as_str())));
+        field(&mut json, "schema_url");
+        push_json_string(&mut json, &self.schema_url);
+        field(&mut json, "level");
+        push_json_string(&mut json, &level(self.level));
+        field(&mut json, "target_levels");
+        let target_levels = self.target_levels.iter().map(|(target, filter)| (target.clone(), level(*filter))).collect::<Vec<_>>();
+        pairs(&mut json, target_levels.iter().map(|(target, level)| (target.as_str(), level.as_str())));
+        field(&mut json, "record_filter");
+        optional(&mut json, self.record_filter.as_deref(), push_json_string);
+        let _ = write!(json, ",\"queue_capacity\":{}", self.queue_capacity);
+        field(&mut json, "overflow");
+        push_json_string(&mut json, match self.overflow {
+            OverflowPolicy::Block => "block",
+            OverflowPolicy::DropNewest => "drop_newest",
+            OverflowPolicy::DropOldest => "drop_oldest",
+        });
+        field(&mut json, "coalescing_window_ms");
+        millis(&mut json, self.coalescing_window);
+        field(&mut json, "flush_jitter_ms");
+        millis(&mut json, self.flush_jitter);
+        field(&mut json, "early_buffer");
+        optional(&mut json, self.early_buffer, integer);
+        field(&mut json, "max_batch_bytes");
+        optional(&mut json, self.max_batch_bytes, integer);
+        let _ = write!(json, ",\"batch_summary\":{},\"capabilities_probe\":{},\"attribute_dictionary\":{}",
+                       self.batch_summary, self.capabilities_probe, self.attribute_dictionary);
+        field(&mut json, "retry");
+        retry(&mut json, &self.retry);
+        field(&mut json, "connect_timeout_ms");
+        optional(&mut json, self.connect_timeout, millis);
+        field(&mut json, "tls");
+        optional(&mut json, self.tls.as_ref(), tls);
+        field(&mut json, "quota");
+        optional(&mut json, self.quota, |json, (records, interval)| {
+            let _ = write!(json, "{{\"records\":{records},\"interval_ms\":{}}}", interval.as_millis());
+        });
+        field(&mut json, "severity_quotas");
+        json.push('[');
+        for (index, (level, records, interval)) in self.severity_quotas.iter().enumerate() {
+            if index > 0 {
+                json.push(',');
+            }
+            json.push_str("{\"level\":");
+            push_json_string(&mut json, &level.as_str().to_ascii_lowercase());
+            let _ = write!(json, ",\"records\":{records},\"interval_ms\":{}}}", interval.as_millis());
+        }
+        json.push(']');
+        field(&mut json, "load_shedding");
+        optional(&mut json, self.load_shedding, |json, (max_in_flight, shed_below)| {
+            let _ = write!(json, "{{\"max_in_flight\":{max_in_flight},\"shed_below\":");
+            push_json_string(json, &shed_below.as_str().to_ascii_lowercase());
+            json.push('}');
+        });
+        let _ = write!(json, ",\"server_sampling\":{}", self.server_sampling);
+        field(&mut json, "sampling_exemption_targets");
+        strings(&mut json, &self.sampling_exemption_targets);
+        field(&mut json, "sampling_exemption_filters");
+        strings(&mut json, &self.sampling_exemption_filters);
+        field(&mut json, "error_dedup_window_ms");
+        optional(&mut json, self.error_dedup_window, millis);
+        field(&mut json, "quota_polling_ms");
+        optional(&mut json, self.quota_polling, millis);
+        field(&mut json, "authentication_headers");
+        strings(&mut json, &self.authentication_headers);
+        field(&mut json, "metadata_headers");
+        pairs(&mut json, self.metadata_headers.iter().map(|(name, value)| (name.as_str(), value.as_str())));
+        field(&mut json, "disk_queue");
+        optional(&mut json, self.disk_queue.as_deref(), path);
+        json.push('}');
+        json
+    }
+}
+
+// Starts the next member of the object being written.
+pub(crate) fn field(json: &mut String, name: &str) {
+    if !json.ends_with('{') {
+        json.push(',');
+    }
+    push_json_string(json, name);
+    json.push(':');
+}
+
+pub(crate) fn optional<T>(json: &mut String, value: Option<T>, write: impl FnOnce(&mut String, T)) {
+    match value {
+        Some(value) => write(json, value),
+        None => json.push_str("null"),
+    }
+}
+
+fn strings(json: &mut String, values: &[String]) {
+    json.push('[');
+    for (index, value) in values.iter().enumerate() {
+        if index > 0 {
+            json.push(',');
+        }
+        push_json_string(json, value);
+    }
+    json.push(']');
+}
+
+// Key/value pairs as an array of two element arrays, keeping their order.
+fn pairs<'a>(json: &mut String, pairs: impl Iterator<Item=(&'a str, &'a str)>) {
+    json.push('[');
+    for (index, (key, value)) in pairs.enumerate() {
+        if index > 0 {
+            json.push(',');
+        }
+        json.push('[');
+        push_json_string(json, key);
+        json.push(',');
+        push_json_string(json, value);
+        json.push(']');
+    }
+    json.push(']');
+}
+
+fn retry(json: &mut String, policy: &RetryPolicy) {
+    json.push('{');
+    field(json, "initial_backoff_ms");
+    millis(json, policy.initial_backoff);
+    field(json, "max_backoff_ms");
+    millis(json, policy.max_backoff);
+    field(json, "multiplier");
+    number(json, policy.multiplier);
+    field(json, "jitter");
+    number(json, policy.jitter);
+    field(json, "max_attempts");
+    optional(json, policy.max_attempts, integer);
+    field(json, "on_exhausted");
+    match policy.on_exhausted {
+        RetryExhausted::Drop => push_json_string(json, "drop"),
+        RetryExhausted::Requeue { max_batches } => {
+            let _ = write!(json, "{{\"requeue\":{{\"max_batches\":{max_batches}}}}}");
+        }
+    }
+    json.push('}');
+}
+
+fn tls(json: &mut String, tls: &TlsSettings) {
+    json.push('{');
+    field(json, "domain");
+    optional(json, tls.domain.as_deref(), push_json_string);
+    field(json, "ca_certificate_file");
+    optional(json, tls.ca_certificate_file.as_deref(), path);
+    let _ = write!(json, ",\"ca_certificates\":{},\"client_identity\":{},\"custom_config\":{}}}",
+                   tls.ca_certificates, tls.client_identity, tls.custom_config);
+}
+
+fn millis(json: &mut String, duration: Duration) {
+    let _ = write!(json, "{}", duration.as_millis());
+}
+
+fn integer(json: &mut String, value: impl fmt::Display) {
+    let _ = write!(json, "{value}");
+}
+
+// JSON has no infinity or NaN.
+fn number(json: &mut String, value: f64) {
+    if value.is_finite() {
+        let _ = write!(json, "{value}");
+    } else {
+        push_json_string(json, &value.to_string());
+    }
+}
+
+fn path(json: &mut String, path: &Path) {
+    push_json_string(json, &path.display().to_string());
+}
+
+fn level(level: LevelFilter) -> String {
+    level.to_string().to_ascii_lowercase()
+}
@@ -0,0 +1,265 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::clock::Clock;
+use crate::follow::{follow, line_record};
+use crate::internal::attribute;
+use crate::opentelclient::any_value::Value::StringValue;
+use crate::opentelclient::KeyValue;
+use crate::routing::RecordSender;
+use crate::timestamp::parse_rfc3339;
+
+// Size at which a message still being joined is exported as is, so a container that
+// never finishes a line can't grow it without bound.
+const MAX_JOINED_LEN: usize = 1024 * 1024;
+
+/// Line format of a container runtime's log files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContainerLogFormat {
+    /// Docker's `json-file` driver: `{"log":"...\n","stream":"stdout","time":"..."}`.
+    DockerJson,
+    /// The CRI format used by containerd and CRI-O: `<time> <stream> <P|F> <message>`.
+    Cri,
+}
+
+/// Why a container log line couldn't be converted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContainerLogError {
+    pub message: String,
+}
+
+impl fmt::Display for ContainerLogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ContainerLogError {}
+
+fn error(message: &str) -> ContainerLogError {
+    ContainerLogError { message: message.to_string() }
+}
+
+/// Turns the stdout/stderr log lines of containers that can't be instrumented into
+/// records, exported through the layer's pipeline (record filter, routes, batching) like
+/// the application's own events. Get one from
+/// [`crate::TelescopeLayer::container_log_bridge`].
+///
+/// Lines the runtime split up (Docker at 16 KiB, CRI `P` lines) are joined again, up to
+/// 1 MiB; the rest of a longer message follows in records of its own. Each record
+/// carries the original timestamp, the `log.iostream` attribute and the attributes added
+/// with [`Self::with_attribute`], at INFO severity.
+pub struct ContainerLogBridge {
+    sender: RecordSender,
+    clock: Arc<dyn Clock>,
+    format: ContainerLogFormat,
+    target: String,
+    attributes: Vec<KeyValue>,
+    // Unfinished stdout and stderr messages.
+    partial: Mutex<[String; 2]>,
+}
+
+impl ContainerLogBridge {
+    pub(crate) fn new(sender: RecordSender, clock: Arc<dyn Clock>, format: ContainerLogFormat) -> Self {
+        Self {
+            sender,
+            clock,
+            format,
+            target: "container".to_string(),
+            attributes: Vec::new(),
+            partial: Mutex::new([String::new(), String::new()]),
+        }
+    }
+
+    /// Target used for routing and record filters, `container` by default.
+    pub fn with_target(mut self, target: impl Into<String>) -> Self {
+        self.target = target.into();
+        self
+    }
+
+    /// Add an attribute to every record, e.g. `k8s.pod.name` or `container.name`.
+    pub fn with_attribute(mut self, key: &str, value: impl Into<String>) -> Self {
+        self.attributes.push(attribute(key, StringValue(value.into())));
+        self
+    }
+
+    /// Convert one line of a container log file and export it.
+    pub fn ingest_line(&self, line: &str) -> Result<(), ContainerLogError> {
+        let line = match self.format {
+            ContainerLogFormat::DockerJson => parse_docker_json(line)?,
+            ContainerLogFormat::Cri => parse_cri(line)?,
+        };
+        let stream = usize::from(line.stream == "stderr");
+        let message = {
+            let mut partial = self.partial.lock().unwrap();
+            partial[stream].push_str(&line.message);
+            if line.partial && partial[stream].len() < MAX_JOINED_LEN {
+                return Ok(());
+            }
+            std::mem::take(&mut partial[stream])
+        };
+        let mut attributes = Vec::with_capacity(self.attributes.len() + 1);
+        attributes.push(attribute("log.iostream", StringValue(line.stream)));
+        attributes.extend(self.attributes.iter().cloned());
+        self.sender.send(&self.target, line_record(line.time_unix_nano, self.clock.now_unix_nano(), message, attributes));
+        Ok(())
+    }
+
+    /// Follow the log file at `path` on a background thread, like `tail -F`, ingesting
+    /// every line from the start of the file. Lines that can't be parsed are skipped.
+    pub fn follow(self, path: impl Into<PathBuf>) -> std::io::Result<()> {
+        follow(path.into(), "telescope-container-log", move |line| {
+            let _ = self.ingest_line(line);
+        })
+    }
+}
+
+struct ContainerLine {
+    time_unix_nano: u64,
+    stream: String,
+    message: String,
+    partial: bool,
+}
+
+fn parse_docker_json(line: &str) -> Result<ContainerLine, ContainerLogError> {
+    let fields = json::parse_object(line).ok_or_else(|| error("not a JSON object"))?;
+    let field = |name: &str| fields.iter().find(|(key, _)| key == name).and_then(|(_, value)| value.as_deref());
+    let mut message = field("log").ok_or_else(|| error("missing log"))?.to_string();
+    // Docker ends complete lines with a newline and splits longer ones without.
+    let partial = !message.ends_with('\n');
+    if !partial {
+        message.pop();
+        if message.ends_with('\r') {
+            message.pop();
+        }
+    }
+    Ok(ContainerLine {
+        time_unix_nano: parse_rfc3339(field("time").ok_or_else(|| error("missing time"))?).ok_or_else(|| error("invalid timestamp"))?,
+        stream: field("stream").unwrap_or("stdout").to_string(),
+        message,
+        partial,
+    })
+}
+
+fn parse_cri(line: &str) -> Result<ContainerLine, ContainerLogError> {
+    let mut parts = line.splitn(4, ' ');
+    let time = parts.next().ok_or_else(|| error("missing time"))?;
+    let stream = parts.next().ok_or_else(|| error("missing stream"))?;
+    let partial = match parts.next() {
+        Some("P") => true,
+        Some("F") => false,
+        _ => return Err(error("missing P/F tag")),
+    };
+    Ok(ContainerLine {
+        time_unix_nano: parse_rfc3339(time).ok_or_else(|| error("invalid timestamp"))?,
+        stream: stream.to_string(),
+        message: parts.next().unwrap_or_default().to_string(),
+        partial,
+    })
+}
+
+// Just enough JSON for log driver lines: a flat object whose string values are kept and
+// whose other values (numbers, nested `attrs` objects, ...) are skipped.
+mod json {
+    use std::iter::Peekable;
+    use std::str::Chars;
+
+    pub(super) fn parse_object(text: &str) -> Option<Vec<(String, Option<String>)>> {
+        let mut chars = text.trim().chars().peekable();
+        let mut fields = Vec::new();
+        expect(&mut chars, '{')?;
+        skip_whitespace(&mut chars);
+        if chars.peek() == Some(&'}') {
+            return Some(fields);
+        }
+        loop {
+            skip_whitespace(&mut chars);
+            expect(&mut chars, '"')?;
+            let key = string(&mut chars)?;
+            skip_whitespace(&mut chars);
+            expect(&mut chars, ':')?;
+            skip_whitespace(&mut chars);
+            let value = if chars.peek() == Some(&'"') {
+                chars.next();
+                Some(string(&mut chars)?)
+            } else {
+                skip_value(&mut chars)?;
+                None
+            };
+            fields.push((key, value));
+            skip_whitespace(&mut chars);
+            match chars.next()? {
+                ',' => continue,
+                '}' => return Some(fields),
+                _ => return None,
+            }
+        }
+    }
+
+    fn expect(chars: &mut Peekable<Chars>, expected: char) -> Option<()> {
+        (chars.next()? == expected).then_some(())
+    }
+
+    fn skip_whitespace(chars: &mut Peekable<Chars>) {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    // After the opening quote.
+    fn string(chars: &mut Peekable<Chars>) -> Option<String> {
+        let mut value = String::new();
+        loop {
+            match chars.next()? {
+                '"' => return Some(value),
+                '\\' => match chars.next()? {
+                    'n' => value.push('\n'),
+                    'r' => value.push('\r'),
+                    't' => value.push('\t'),
+                    'b' => value.push('\u{8}'),
+                    'f' => value.push('\u{c}'),
+                    'u' => {
+                        let mut code = hex4(chars)?;
+                        if (0xD800..0xDC00).contains(&code) {
+                            // A surrogate pair spells out one character.
+                            expect(chars, '\\')?;
+                            expect(chars, 'u')?;
+                            code = 0x10000 + ((code - 0xD800) << 10) + (hex4(chars)?.checked_sub(0xDC00)?);
+                        }
+                        value.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                    }
+                    other => value.push(other),
+                },
+                c => value.push(c),
+            }
+        }
+    }
+
+    fn hex4(chars: &mut Peekable<Chars>) -> Option<u32> {
+        (0..4).try_fold(0, |code, _| Some(code * 16 + chars.next()?.to_digit(16)?))
+    }
+
+    fn skip_value(chars: &mut Peekable<Chars>) -> Option<()> {
+        let mut depth = 0usize;
+        loop {
+            match chars.peek()? {
+                '"' => {
+                    chars.next();
+                    string(chars)?;
+                }
+                '{' | '[' => {
+                    chars.next();
+                    depth += 1;
+                }
+                '}' | ']' if depth == 0 => return Some(()),
+                '}' | ']' => {
+                    chars.next();
+                    depth -= 1;
+                }
+                ',' if depth == 0 => return Some(()),
+                _ => {
+                    chars.next();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::future::Future;
+use std::sync::Arc;
+
+use tokio::task::JoinHandle;
+
+use crate::internal::attribute;
+use crate::opentelclient::any_value::Value::StringValue;
+use crate::opentelclient::KeyValue;
+
+tokio::task_local! {
+    static CONTEXT: TelescopeContext;
+}
+
+/// Correlation attributes (request id, tenant, ...) added to every record logged while
+/// the context is in scope, including from tasks started with [`TelescopeContext::spawn`].
+#[derive(Clone, Debug, Default)]
+pub struct TelescopeContext {
+    attributes: Arc<Vec<KeyValue>>,
+}
+
+impl TelescopeContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The context in scope for the current task, if any.
+    pub fn current() -> Option<Self> {
+        CONTEXT.try_with(|context| context.clone()).ok()
+    }
+
+    pub fn with(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        let key = key.into();
+        let attributes = Arc::make_mut(&mut self.attributes);
+        attributes.retain(|attribute| attribute.key != key);
+        attributes.push(attribute(&key, StringValue(value.into())));
+        self
+    }
+
+    /// Run `future` with this context in scope.
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        CONTEXT.scope(self, future).await
+    }
+
+    /// Run `f` with this context in scope.
+    pub fn in_scope<R>(self, f: impl FnOnce() -> R) -> R {
+        CONTEXT.sync_scope(self, f)
+    }
+
+    /// `tokio::spawn` that carries the current context into the new task.
+    pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+        where
+            F: Future + Send + 'static,
+            F::Output: Send + 'static,
+    {
+        match Self::current() {
+            Some(context) => tokio::spawn(CONTEXT.scope(context, future)),
+            None => tokio::spawn(future),
+        }
+    }
+
+    pub(crate) fn attributes(&self) -> &[KeyValue] {
+        &self.attributes
+    }
+}
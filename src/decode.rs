@@ -0,0 +1,119 @@
+use std::fmt::Write;
+
+use prost::Message;
+
+use crate::opentelclient::{AnyValue, ExportLogsServiceRequest, KeyValue, LogRecord};
+use crate::opentelclient::any_value::Value;
+use crate::trace_context::hex;
+
+// Sinks that speak another format (journal, syslog, ...) work on the decoded batch
+// rather than on the encoded OTLP payload.
+pub(crate) struct DecodedBatch(ExportLogsServiceRequest);
+
+impl DecodedBatch {
+    pub(crate) fn decode(payload: &[u8]) -> Result<Self, prost::DecodeError> {
+        ExportLogsServiceRequest::decode(payload).map(Self)
+    }
+
+    /// Every record with the attributes of the resource it was logged under.
+    pub(crate) fn records(&self) -> impl Iterator<Item=(&[KeyValue], &LogRecord)> {
+        self.0.resource_logs.iter().flat_map(|resource_logs| {
+            let resource = resource_logs.resource.as_ref().map(|resource| resource.attributes.as_slice()).unwrap_or_default();
+            resource_logs.scope_logs.iter()
+                .flat_map(|scope_logs| scope_logs.log_records.iter())
+                .map(move |record| (resource, record))
+        })
+    }
+}
+
+pub(crate) fn string_attribute<'a>(attributes: &'a [KeyValue], key: &str) -> Option<&'a str> {
+    attributes.iter()
+        .find(|attribute| attribute.key == key)
+        .and_then(|attribute| match attribute.value.as_ref()?.value.as_ref()? {
+            Value::StringValue(value) => Some(value.as_str()),
+            _ => None,
+        })
+}
+
+// syslog(3) severity for the OTLP severity ranges.
+pub(crate) fn syslog_severity(severity_number: i32) -> u8 {
+    match severity_number {
+        21.. => 2,
+        17..=20 => 3,
+        13..=16 => 4,
+        9..=12 => 6,
+        _ => 7,
+    }
+}
+
+pub(crate) fn body(record: &LogRecord) -> String {
+    record.body.as_ref().map(display).unwrap_or_default()
+}
+
+/// Plain text form of a value: strings as they are, arrays and maps JSON-like.
+pub(crate) fn display(value: &AnyValue) -> String {
+    let mut text = String::new();
+    write_value(&mut text, value, false);
+    text
+}
+
+fn write_value(text: &mut String, value: &AnyValue, nested: bool) {
+    match &value.value {
+        None => {}
+        Some(Value::StringValue(value)) if nested => {
+            let _ = write!(text, "{value:?}");
+        }
+        Some(Value::StringValue(value)) => text.push_str(value),
+        Some(Value::BoolValue(value)) => {
+            let _ = write!(text, "{value}");
+        }
+        Some(Value::IntValue(value)) => {
+            let _ = write!(text, "{value}");
+        }
+        Some(Value::DoubleValue(value)) => {
+            let _ = write!(text, "{value}");
+        }
+        Some(Value::BytesValue(value)) => text.push_str(&hex(value)),
+        Some(Value::ArrayValue(array)) => {
+            text.push('[');
+            for (index, value) in array.values.iter().enumerate() {
+                if index > 0 {
+                    text.push(',');
+                }
+                write_value(text, value, true);
+            }
+            text.push(']');
+        }
+        Some(Value::KvlistValue(list)) => {
+            text.push('{');
+            for (index, entry) in list.values.iter().enumerate() {
+                if index > 0 {
+                    text.push(',');
+                }
+                let _ = write!(text, "{:?}:", entry.key);
+                if let Some(value) = &entry.value {
+                    write_value(text, value, true);
+                }
+            }
+            text.push('}');
+        }
+    }
+}
+
+pub(crate) fn push_json_string(json: &mut String, value: &str) {
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
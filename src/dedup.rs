@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::clock::Clock;
+use crate::decode::{body, string_attribute};
+use crate::internal::attribute;
+use crate::opentelclient::any_value::Value::IntValue;
+use crate::opentelclient::LogRecord;
+
+const STACKTRACE_KEY: &str = "exception.stacktrace";
+
+struct Occurrence {
+    first_seen: Instant,
+    suppressed: u64,
+    record: LogRecord,
+}
+
+// Collapses identical errors (same `exception.stacktrace`, or same body for ERROR records
+// without one) within a window: the first one is exported right away, repeats are only
+// counted and reported once the window ends.
+pub(crate) struct Deduplicator {
+    window: Duration,
+    seen: Mutex<HashMap<u64, Occurrence>>,
+}
+
+impl Deduplicator {
+    pub(crate) fn new(window: Duration) -> Self {
+        Self { window, seen: Mutex::new(HashMap::new()) }
+    }
+
+    /// Whether `record` should be exported.
+    pub(crate) fn admit(&self, record: &LogRecord, clock: &dyn Clock) -> bool {
+        let Some(key) = key(record) else {
+            return true;
+        };
+        let now = clock.now();
+        let mut seen = self.seen.lock().unwrap();
+        match seen.get_mut(&key) {
+            Some(occurrence) if now.saturating_duration_since(occurrence.first_seen) < self.window => {
+                occurrence.suppressed += 1;
+                false
+            }
+            // Expired but not drained yet; the exporter reports it on its next pass.
+            Some(_) => true,
+            None => {
+                seen.insert(key, Occurrence { first_seen: now, suppressed: 0, record: record.clone() });
+                true
+            }
+        }
+    }
+
+    /// Summaries for every window that ended, one per error that repeated: a copy of
+    /// the first record with the number of repeats it stood for.
+    pub(crate) fn drain(&self, clock: &dyn Clock) -> Vec<LogRecord> {
+        let now = clock.now();
+        let mut summaries = Vec::new();
+        self.seen.lock().unwrap().retain(|_, occurrence| {
+            if now.saturating_duration_since(occurrence.first_seen) < self.window {
+                return true;
+            }
+            if occurrence.suppressed > 0 {
+                let mut summary = occurrence.record.clone();
+                summary.time_unix_nano = clock.now_unix_nano();
+                summary.observed_time_unix_nano = summary.time_unix_nano;
+                summary.attributes.push(attribute("telescope.dedup.suppressed", IntValue(occurrence.suppressed as i64)));
+                summary.attributes.push(attribute("telescope.dedup.window_ms", IntValue(self.window.as_millis() as i64)));
+                summaries.push(summary);
+            }
+            false
+        });
+        summaries
+    }
+}
+
+fn key(record: &LogRecord) -> Option<u64> {
+    let mut hasher = DefaultHasher::new();
+    match string_attribute(&record.attributes, STACKTRACE_KEY) {
+        Some(stacktrace) => stacktrace.hash(&mut hasher),
+        None if record.severity_number >= 17 => body(record).hash(&mut hasher),
+        None => return None,
+    }
+    Some(hasher.finish())
+}
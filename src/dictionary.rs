@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+
+use prost::Message;
+use tonic::metadata::MetadataMap;
+
+use crate::opentelclient::any_value::Value;
+use crate::opentelclient::{KeyValueList, LogRecord};
+
+// Sent on every export with the version of the dictionary the client holds (0 for none).
+// A server that knows the client's copy is stale answers with the current one: the
+// version in the same header, and the dictionary in the binary header below as an
+// encoded KeyValueList of attribute key -> (KeyValueList of value -> int id).
+pub(crate) const DICTIONARY_VERSION_HEADER: &str = "x-telescope-dictionary-version";
+const DICTIONARY_HEADER: &str = "x-telescope-dictionary-bin";
+
+// Attribute values the server assigned ids to. In a request carrying a dictionary version,
+// an int value of one of the dictionary's keys is always an id from that version.
+pub(crate) struct Dictionary {
+    pub(crate) version: u64,
+    ids: HashMap<String, HashMap<String, i64>>,
+}
+
+impl Dictionary {
+    pub(crate) fn from_metadata(metadata: &MetadataMap) -> Option<Self> {
+        let version = metadata.get(DICTIONARY_VERSION_HEADER)?.to_str().ok()?.trim().parse().ok()?;
+        let encoded = metadata.get_bin(DICTIONARY_HEADER)?.to_bytes().ok()?;
+        let keys = KeyValueList::decode(encoded).ok()?;
+        let ids = keys.values.into_iter().filter_map(|key| {
+            let Some(Value::KvlistValue(values)) = key.value?.value else {
+                return None;
+            };
+            let values = values.values.into_iter().filter_map(|value| match value.value?.value? {
+                Value::IntValue(id) => Some((value.key, id)),
+                _ => None,
+            }).collect();
+            Some((key.key, values))
+        }).collect();
+        Some(Self { version, ids })
+    }
+
+    // Replace the string values the dictionary knows by their ids.
+    pub(crate) fn compress(&self, records: &mut [LogRecord]) {
+        for attribute in records.iter_mut().flat_map(|record| record.attributes.iter_mut()) {
+            let Some(ids) = self.ids.get(&attribute.key) else {
+                continue;
+            };
+            let Some(value) = attribute.value.as_mut() else {
+                continue;
+            };
+            if let Some(Value::StringValue(string)) = &value.value {
+                if let Some(id) = ids.get(string) {
+                    value.value = Some(Value::IntValue(*id));
+                }
+            }
+        }
+    }
+}
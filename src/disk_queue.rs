@@ -0,0 +1,450 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use tonic::metadata::{MetadataMap, MetadataValue};
+use tower::{BoxError, Service, ServiceExt};
+
+use crate::clock::Clock;
+use crate::instrumentation;
+use crate::opentelclient::ExportLogsServiceResponse;
+use crate::service::{BoxExportService, BoxFuture, ExportRequest, REQUEST_ID_HEADER};
+
+// Every queued batch is one file, `<sequence>.batch`, written to a temporary name and
+// renamed into place so a crash never leaves a half-written batch behind.
+//
+// File layout (all integers big-endian):
+//
+//   magic        4 bytes  "TLSQ"
+//   version      u16      FORMAT_VERSION when written
+//   ...          version specific, see `DiskQueue::decode`
+//
+// Readers accept every version up to FORMAT_VERSION. When the layout changes, bump
+// FORMAT_VERSION, keep the old decoder and add it to `decode`; `DiskQueue::open`
+// rewrites batches left behind by older releases in the current format, and with a key
+// configured also encrypts current batches that were queued in plaintext. Batches from
+// a newer release are left untouched, so a downgrade doesn't lose them either. A batch
+// that fails its checksum, or can't be parsed, is renamed to `<sequence>.corrupt` and
+// skipped.
+const MAGIC: &[u8; 4] = b"TLSQ";
+const FORMAT_VERSION: u16 = 3;
+const HEADER_LEN: usize = 8;
+const FLAG_ENCRYPTED: u16 = 1;
+#[cfg(feature = "disk-queue-encryption")]
+const NONCE_LEN: usize = 12;
+const EXTENSION: &str = "batch";
+const CORRUPT_EXTENSION: &str = "corrupt";
+const REPLAY_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+// Supplies the AES-256 key, e.g. read from the environment or unwrapped through a KMS.
+// Called once when the layer is built.
+#[cfg(feature = "disk-queue-encryption")]
+pub(crate) type DiskQueueKeyProvider = Box<dyn FnOnce() -> Result<Vec<u8>, BoxError> + Send>;
+
+pub(crate) struct DiskQueue {
+    dir: PathBuf,
+    next_sequence: AtomicU64,
+    // Batches written since the queue was opened.
+    pushed: AtomicU64,
+    #[cfg(feature = "disk-queue-encryption")]
+    cipher: Option<aes_gcm::Aes256Gcm>,
+}
+
+impl DiskQueue {
+    pub(crate) fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Self {
+            dir,
+            next_sequence: AtomicU64::new(0),
+            pushed: AtomicU64::new(0),
+            #[cfg(feature = "disk-queue-encryption")]
+            cipher: None,
+        }.recover()
+    }
+
+    /// Like `open`, but batches are written encrypted with `key`. Plaintext batches
+    /// already on disk are still read.
+    #[cfg(feature = "disk-queue-encryption")]
+    pub(crate) fn open_encrypted(dir: impl Into<PathBuf>, key: &[u8]) -> io::Result<Self> {
+        use aes_gcm::KeyInit;
+
+        let cipher = aes_gcm::Aes256Gcm::new_from_slice(key)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "disk queue key must be 32 bytes"))?;
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Self { dir, next_sequence: AtomicU64::new(0), pushed: AtomicU64::new(0), cipher: Some(cipher) }.recover()
+    }
+
+    // Picks up the sequence after the last batch a previous run left behind.
+    fn recover(self) -> io::Result<Self> {
+        let mut last_sequence = None;
+        for (sequence, path) in self.batches()? {
+            last_sequence = Some(sequence);
+            self.migrate(&path)?;
+        }
+        self.next_sequence.store(last_sequence.map_or(0, |sequence| sequence + 1), Ordering::Relaxed);
+        Ok(self)
+    }
+
+    pub(crate) fn push(&self, request: &ExportRequest) -> io::Result<()> {
+        let sequence = self.next_sequence.fetch_add(1, Ordering::Relaxed);
+        let path = self.dir.join(format!("{sequence:020}.{EXTENSION}"));
+        write_atomically(&path, &self.encode(request)?)?;
+        self.pushed.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub(crate) fn pushed(&self) -> u64 {
+        self.pushed.load(Ordering::Relaxed)
+    }
+
+    /// The oldest batch this release can read, with its file. Batches that can't be
+    /// read are skipped, so one bad file never holds up the rest of the queue.
+    pub(crate) fn oldest(&self) -> io::Result<Option<(PathBuf, ExportRequest)>> {
+        for (_, path) in self.batches()? {
+            let Ok(bytes) = fs::read(&path) else {
+                continue;
+            };
+            match self.decode(&bytes) {
+                Ok(request) => return Ok(Some((path, request))),
+                Err(Unreadable::Unsupported) => continue,
+                Err(Unreadable::Corrupt) => {
+                    // Keep it for inspection, but out of the way of the batches behind it.
+                    instrumentation::disk_queue_batch_corrupted();
+                    let _ = fs::rename(&path, path.with_extension(CORRUPT_EXTENSION));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    pub(crate) fn stats(&self) -> io::Result<DiskQueueStats> {
+        let mut stats = DiskQueueStats { queued_batches: 0, queued_bytes: 0, corrupted_batches: 0 };
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            match entry.path().extension().and_then(|extension| extension.to_str()) {
+                Some(EXTENSION) => {
+                    stats.queued_batches += 1;
+                    stats.queued_bytes += entry.metadata()?.len();
+                }
+                Some(CORRUPT_EXTENSION) => stats.corrupted_batches += 1,
+                _ => {}
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Deletes every queued and corrupted batch, returning how many files were removed.
+    pub(crate) fn purge(&self) -> io::Result<u64> {
+        let mut removed = 0;
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if matches!(path.extension().and_then(|extension| extension.to_str()), Some(EXTENSION | CORRUPT_EXTENSION)) {
+                match fs::remove_file(&path) {
+                    Ok(()) => removed += 1,
+                    // Replayed in the meantime.
+                    Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+                    Err(error) => return Err(error),
+                }
+            }
+        }
+        Ok(removed)
+    }
+
+    pub(crate) fn remove(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    // Queued batch files in queue order.
+    fn batches(&self) -> io::Result<Vec<(u64, PathBuf)>> {
+        let mut batches = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some(EXTENSION) {
+                continue;
+            }
+            if let Some(sequence) = path.file_stem().and_then(|stem| stem.to_str()).and_then(|stem| stem.parse().ok()) {
+                batches.push((sequence, path));
+            }
+        }
+        batches.sort_unstable();
+        Ok(batches)
+    }
+
+    fn migrate(&self, path: &Path) -> io::Result<()> {
+        let bytes = fs::read(path)?;
+        let outdated = match version(&bytes) {
+            Some(version) if version < FORMAT_VERSION => true,
+            // Queued in plaintext before a key was configured.
+            Some(FORMAT_VERSION) => self.encrypts() && flags(&bytes).is_some_and(|flags| flags & FLAG_ENCRYPTED == 0),
+            _ => false,
+        };
+        if !outdated {
+            return Ok(());
+        }
+        match self.decode(&bytes) {
+            Ok(request) => write_atomically(path, &self.encode(&request)?),
+            Err(_) => Ok(()),
+        }
+    }
+
+    fn encrypts(&self) -> bool {
+        #[cfg(feature = "disk-queue-encryption")]
+        {
+            self.cipher.is_some()
+        }
+        #[cfg(not(feature = "disk-queue-encryption"))]
+        {
+            false
+        }
+    }
+
+    fn encode(&self, request: &ExportRequest) -> io::Result<Vec<u8>> {
+        let (header, body) = self.seal(encode_body(request))?;
+        let checksum = crc32(&[&header[..], &body].concat());
+        Ok([&header[..], &checksum.to_be_bytes(), &body].concat())
+    }
+
+    fn seal(&self, body: Vec<u8>) -> io::Result<([u8; HEADER_LEN], Vec<u8>)> {
+        #[cfg(feature = "disk-queue-encryption")]
+        if let Some(cipher) = &self.cipher {
+            use aes_gcm::aead::{Aead, Payload};
+
+            let header = header(FLAG_ENCRYPTED);
+            let nonce: [u8; NONCE_LEN] = rand::random();
+            let ciphertext = cipher
+                .encrypt(&nonce.into(), Payload { msg: &body, aad: &header })
+                .map_err(|_| io::Error::other("disk queue batch could not be encrypted"))?;
+            return Ok((header, [&nonce[..], &ciphertext].concat()));
+        }
+        Ok((header(0), body))
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<ExportRequest, Unreadable> {
+        match version(bytes).ok_or(Unreadable::Corrupt)? {
+            1 => decode_body(&bytes[6..]).ok_or(Unreadable::Corrupt),
+            2 => {
+                let (header, body) = bytes.split_at_checked(HEADER_LEN).ok_or(Unreadable::Corrupt)?;
+                self.open_sealed(header, body)
+            }
+            3 => self.decode_v3(bytes),
+            _ => Err(Unreadable::Unsupported),
+        }
+    }
+
+    // v3: flags (u16), CRC-32 of the whole file without the checksum (u32), then the
+    // body as in v2.
+    fn decode_v3(&self, bytes: &[u8]) -> Result<ExportRequest, Unreadable> {
+        let (header, rest) = bytes.split_at_checked(HEADER_LEN).ok_or(Unreadable::Corrupt)?;
+        let (checksum, body) = rest.split_at_checked(4).ok_or(Unreadable::Corrupt)?;
+        if crc32(&[header, body].concat()).to_be_bytes() != checksum {
+            return Err(Unreadable::Corrupt);
+        }
+        self.open_sealed(header, body)
+    }
+
+    // v2: flags (u16), then the v1 body. With FLAG_ENCRYPTED the body is sealed with
+    // AES-256-GCM: a 12 byte nonce followed by the ciphertext, with the header as
+    // associated data.
+    fn open_sealed(&self, header: &[u8], body: &[u8]) -> Result<ExportRequest, Unreadable> {
+        let flags = u16::from_be_bytes([header[6], header[7]]);
+        if flags & FLAG_ENCRYPTED == 0 {
+            return decode_body(body).ok_or(Unreadable::Corrupt);
+        }
+        #[cfg(feature = "disk-queue-encryption")]
+        if let Some(cipher) = &self.cipher {
+            use aes_gcm::aead::{Aead, Payload};
+
+            let (nonce, ciphertext) = body.split_at_checked(NONCE_LEN).ok_or(Unreadable::Corrupt)?;
+            // A batch sealed with another key is kept until that key comes back.
+            let body = cipher
+                .decrypt(nonce.into(), Payload { msg: ciphertext, aad: header })
+                .map_err(|_| Unreadable::Unsupported)?;
+            return decode_body(&body).ok_or(Unreadable::Corrupt);
+        }
+        Err(Unreadable::Unsupported)
+    }
+}
+
+/// Contents of the disk queue, from [`crate::TelescopeHandle::disk_queue_stats`].
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct DiskQueueStats {
+    /// Batches waiting to be replayed, including ones this release can't read.
+    pub queued_batches: u64,
+    pub queued_bytes: u64,
+    /// Batches that failed their checksum and were set aside as `<sequence>.corrupt`.
+    pub corrupted_batches: u64,
+}
+
+enum Unreadable {
+    // Written by a newer release, or encrypted with a key we don't have.
+    Unsupported,
+    Corrupt,
+}
+
+fn version(bytes: &[u8]) -> Option<u16> {
+    let header = bytes.strip_prefix(MAGIC)?;
+    Some(u16::from_be_bytes(header.get(..2)?.try_into().ok()?))
+}
+
+// Flags of a v2 or later batch.
+fn flags(bytes: &[u8]) -> Option<u16> {
+    Some(u16::from_be_bytes(bytes.get(6..HEADER_LEN)?.try_into().ok()?))
+}
+
+fn header(flags: u16) -> [u8; HEADER_LEN] {
+    let mut header = [0; HEADER_LEN];
+    header[..4].copy_from_slice(MAGIC);
+    header[4..6].copy_from_slice(&FORMAT_VERSION.to_be_bytes());
+    header[6..].copy_from_slice(&flags.to_be_bytes());
+    header
+}
+
+fn encode_body(request: &ExportRequest) -> Vec<u8> {
+    let request_id = request.request_id().unwrap_or_default().as_bytes();
+    let mut body = Vec::with_capacity(2 + request_id.len() + 4 + request.payload.len());
+    body.extend_from_slice(&(request_id.len() as u16).to_be_bytes());
+    body.extend_from_slice(request_id);
+    body.extend_from_slice(&(request.payload.len() as u32).to_be_bytes());
+    body.extend_from_slice(&request.payload);
+    body
+}
+
+// The v1 body, also used by v2: request id length (u16), request id, payload length
+// (u32), encoded ExportLogsServiceRequest.
+fn decode_body(bytes: &[u8]) -> Option<ExportRequest> {
+    let (request_id, rest) = take_prefixed(bytes, 2)?;
+    let (payload, rest) = take_prefixed(rest, 4)?;
+    if !rest.is_empty() {
+        return None;
+    }
+    let mut metadata = MetadataMap::new();
+    if let Ok(request_id) = MetadataValue::try_from(std::str::from_utf8(request_id).ok()?) {
+        metadata.insert(REQUEST_ID_HEADER, request_id);
+    }
+    Some(ExportRequest { payload: Bytes::copy_from_slice(payload), metadata })
+}
+
+// Splits off a big-endian length of `width` bytes and the data it announces.
+fn take_prefixed(bytes: &[u8], width: usize) -> Option<(&[u8], &[u8])> {
+    let (length, rest) = bytes.split_at_checked(width)?;
+    let length = length.iter().fold(0usize, |length, byte| length << 8 | *byte as usize);
+    rest.split_at_checked(length)
+}
+
+// CRC-32 (IEEE 802.3), the same checksum gzip and zip use.
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+fn write_atomically(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let temporary = path.with_extension("tmp");
+    fs::write(&temporary, bytes)?;
+    fs::rename(&temporary, path)
+}
+
+// Persists batches the destination failed instead of retrying them in memory. The
+// batch counts as exported for the exporter; `Replay` sends it later.
+#[derive(Clone)]
+pub(crate) struct DiskQueueService {
+    pub(crate) inner: BoxExportService,
+    pub(crate) queue: Arc<DiskQueue>,
+}
+
+impl Service<ExportRequest> for DiskQueueService {
+    type Response = ExportLogsServiceResponse;
+    type Error = BoxError;
+    type Future = BoxFuture<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: ExportRequest) -> Self::Future {
+        let export = self.inner.call(request.clone());
+        let queue = self.queue.clone();
+        Box::pin(async move {
+            match export.await {
+                Ok(response) => Ok(response),
+                Err(error) => {
+                    instrumentation::export_failed();
+                    match queue.push(&request) {
+                        Ok(()) => Ok(ExportLogsServiceResponse::default()),
+                        Err(_) => Err(error),
+                    }
+                }
+            }
+        })
+    }
+}
+
+// Sends queued batches, oldest first, whenever the exporter is idle. After a failed
+// replay it waits a while before trying again.
+pub(crate) struct Replay {
+    pub(crate) queue: Arc<DiskQueue>,
+    pub(crate) service: BoxExportService,
+    pub(crate) retry_at: Option<Instant>,
+}
+
+impl Replay {
+    /// Replays one batch if one is due; returns whether it did.
+    pub(crate) fn run(&mut self, rt: &tokio::runtime::Runtime, clock: &dyn Clock) -> bool {
+        if self.retry_at.is_some_and(|retry_at| clock.now() < retry_at) {
+            return false;
+        }
+        let Ok(Some((path, request))) = self.queue.oldest() else {
+            self.retry_at = Some(clock.now() + REPLAY_RETRY_INTERVAL);
+            return false;
+        };
+        let service = &mut self.service;
+        match rt.block_on(async { service.ready().await?.call(request).await }) {
+            Ok(_) => {
+                let _ = self.queue.remove(&path);
+                self.retry_at = None;
+                true
+            }
+            Err(_) => {
+                self.retry_at = Some(clock.now() + REPLAY_RETRY_INTERVAL);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "disk-queue-encryption"))]
+mod tests {
+    use std::fs;
+
+    use bytes::Bytes;
+    use tonic::metadata::MetadataMap;
+
+    use super::{flags, DiskQueue, FLAG_ENCRYPTED};
+    use crate::service::ExportRequest;
+
+    #[test]
+    fn plaintext_batches_are_encrypted_once_a_key_is_set() {
+        let dir = std::env::temp_dir().join(format!("telescope-{}-disk-queue-key", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let request = ExportRequest { payload: Bytes::from_static(b"batch"), metadata: MetadataMap::new() };
+        DiskQueue::open(&dir).unwrap().push(&request).unwrap();
+        let queue = DiskQueue::open_encrypted(&dir, &[7; 32]).unwrap();
+        let (path, replayed) = queue.oldest().unwrap().unwrap();
+        assert_eq!(replayed.payload, request.payload);
+        assert_eq!(flags(&fs::read(path).unwrap()), Some(FLAG_ENCRYPTED));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,53 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use tokio::runtime::Runtime;
+use tonic::metadata::MetadataMap;
+use tower::ServiceExt;
+
+use crate::batch::Queued;
+use crate::clock::Clock;
+use crate::queue::QueueReceiver;
+use crate::service::{BoxExportService, ExportRequest};
+
+const PROBE_INTERVAL: Duration = Duration::from_secs(1);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+
+// Holds the first records of an exporter until its destination answered an empty
+// export, so startup logs wait for the collector instead of being retried (and possibly
+// given up on) while it is still coming up. Stops holding once the destination is
+// reachable, the buffer is full or the layer shuts down; the held records are then
+// exported before anything queued after them.
+pub(crate) struct EarlyBuffer {
+    max_records: usize,
+    pub(crate) records: VecDeque<Queued>,
+    probe: BoxExportService,
+    next_probe: Option<Instant>,
+}
+
+impl EarlyBuffer {
+    pub(crate) fn new(max_records: usize, probe: BoxExportService) -> Self {
+        Self { max_records, records: VecDeque::new(), probe, next_probe: None }
+    }
+
+    // Take what is queued and check the destination; returns whether to keep holding.
+    pub(crate) fn hold(&mut self, rx: &QueueReceiver, rt: &Runtime, clock: &dyn Clock) -> bool {
+        while self.records.len() < self.max_records {
+            match rx.try_recv() {
+                Some(queued) => self.records.push_back(queued),
+                None => break,
+            }
+        }
+        if self.records.len() >= self.max_records {
+            return false;
+        }
+        if self.next_probe.is_some_and(|next_probe| clock.now() < next_probe) {
+            return true;
+        }
+        self.next_probe = Some(clock.now() + PROBE_INTERVAL);
+        let request = ExportRequest { payload: Bytes::new(), metadata: MetadataMap::new() };
+        let probe = self.probe.clone().oneshot(request);
+        !matches!(rt.block_on(async { tokio::time::timeout(PROBE_TIMEOUT, probe).await }), Ok(Ok(_)))
+    }
+}
@@ -0,0 +1,32 @@
+use bytes::{Bytes, BytesMut};
+
+use crate::batch::Batch;
+use crate::envelope::Envelope;
+use crate::exporter::ExporterConfig;
+
+// The encode buffer of arena mode, reused between batches (the exporter keeps the
+// batch's record storage). Each payload is split off the front of the buffer, so its
+// capacity comes back for the next batch once the payload is dropped, and nothing is
+// handed back to the allocator until the buffer is dropped or shrunk. Records,
+// attributes and strings are still allocated as usual.
+pub(crate) struct EncodeBuffer {
+    buf: BytesMut,
+}
+
+impl EncodeBuffer {
+    pub(crate) fn new() -> Self {
+        Self { buf: BytesMut::new() }
+    }
+
+    pub(crate) fn encode(&mut self, config: &ExporterConfig, envelope: &mut Envelope, batch: &Batch) -> Bytes {
+        let scope_logs_lens = batch.scope_logs_lens();
+        self.buf.reserve(batch.encoded_len(config, envelope, &scope_logs_lens));
+        batch.encode(config, envelope, &scope_logs_lens, &mut self.buf);
+        self.buf.split().freeze()
+    }
+
+    // Hands the storage back to the allocator, e.g. when the host runs short of memory.
+    pub(crate) fn shrink(&mut self) {
+        self.buf = BytesMut::new();
+    }
+}
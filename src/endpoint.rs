@@ -0,0 +1,21 @@
+// Endpoints are accepted the way collectors and OTLP exporters spell them and turned
+// into the http(s) urls tonic expects:
+//
+//   collector:4317             -> http://collector:4317
+//   grpc://collector:4317/     -> http://collector:4317
+//   grpcs://collector:4317     -> https://collector:4317
+//   HTTP://collector:4317      -> http://collector:4317
+//
+// Anything else is passed through for `TelescopeLayerBuilder::validate` to reject.
+pub(crate) fn normalize(endpoint: &str) -> String {
+    let endpoint = endpoint.trim().trim_end_matches('/');
+    let Some((scheme, rest)) = endpoint.split_once("://") else {
+        return format!("http://{endpoint}");
+    };
+    let scheme = match scheme.to_ascii_lowercase().as_str() {
+        "grpc" => "http".to_string(),
+        "grpcs" => "https".to_string(),
+        scheme => scheme.to_string(),
+    };
+    format!("{scheme}://{rest}")
+}
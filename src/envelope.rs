@@ -0,0 +1,92 @@
+use bytes::BufMut;
+use prost::encoding::{encode_key, encode_varint, encoded_len_varint, key_len, message, string, WireType};
+
+use crate::exporter::ExporterConfig;
+use crate::opentelclient::{LogRecord, Resource};
+use crate::resource::ResourceSnapshot;
+
+// ExportLogsServiceRequest field numbers.
+const RESOURCE_LOGS: u32 = 1;
+const RESOURCE: u32 = 1;
+const SCOPE_LOGS: u32 = 2;
+const LOG_RECORDS: u32 = 2;
+const SCHEMA_URL: u32 = 3;
+
+// The Resource part of every request is identical until a resource attribute changes,
+// so it is encoded once per resource snapshot and the records are spliced in behind it.
+// Produces exactly the bytes `ExportLogsServiceRequest::encode` would for a single
+// ResourceLogs/ScopeLogs.
+pub(crate) struct Envelope {
+    resource_version: u64,
+    resource_field: Vec<u8>,
+    schema_url_field: Vec<u8>,
+}
+
+impl Envelope {
+    pub(crate) fn new(config: &ExporterConfig, resource: &ResourceSnapshot) -> Self {
+        Self {
+            resource_version: resource.version,
+            resource_field: encode_resource(config, resource),
+            schema_url_field: encode_schema_url(resource),
+        }
+    }
+
+    pub(crate) fn refresh(&mut self, config: &ExporterConfig, resource: &ResourceSnapshot) {
+        if self.resource_version != resource.version {
+            *self = Self::new(config, resource);
+        }
+    }
+
+    // `scope_logs_len` of the records is passed in, so their sizes are summed up once
+    // for both `encoded_len` and `encode`.
+    pub(crate) fn encoded_len(&self, scope_logs_len: usize) -> usize {
+        let resource_logs_len = self.resource_logs_len(scope_logs_len);
+        key_len(RESOURCE_LOGS) + encoded_len_varint(resource_logs_len as u64) + resource_logs_len
+    }
+
+    pub(crate) fn encode(&self, records: &[LogRecord], scope_logs_len: usize, buf: &mut impl BufMut) {
+        encode_key(RESOURCE_LOGS, WireType::LengthDelimited, buf);
+        encode_varint(self.resource_logs_len(scope_logs_len) as u64, buf);
+        buf.put_slice(&self.resource_field);
+        encode_key(SCOPE_LOGS, WireType::LengthDelimited, buf);
+        encode_varint(scope_logs_len as u64, buf);
+        for record in records {
+            message::encode(LOG_RECORDS, record, buf);
+        }
+        buf.put_slice(&self.schema_url_field);
+    }
+
+    fn resource_logs_len(&self, scope_logs_len: usize) -> usize {
+        self.resource_field.len() + key_len(SCOPE_LOGS) + encoded_len_varint(scope_logs_len as u64) + scope_logs_len
+            + self.schema_url_field.len()
+    }
+}
+
+pub(crate) fn scope_logs_len(records: &[LogRecord]) -> usize {
+    records.iter().map(|record| message::encoded_len(LOG_RECORDS, record)).sum()
+}
+
+fn resource(config: &ExporterConfig, snapshot: &ResourceSnapshot) -> Resource {
+    let mut attributes = snapshot.attributes.clone();
+    let dropped_attributes_count = config.attribute_limits.apply(&mut attributes);
+    Resource {
+        attributes,
+        dropped_attributes_count,
+    }
+}
+
+fn encode_resource(config: &ExporterConfig, snapshot: &ResourceSnapshot) -> Vec<u8> {
+    let resource = resource(config, snapshot);
+    let mut buf = Vec::with_capacity(message::encoded_len(RESOURCE, &resource));
+    message::encode(RESOURCE, &resource, &mut buf);
+    buf
+}
+
+// Empty, like prost leaves out a default string, when no schema url is set.
+fn encode_schema_url(resource: &ResourceSnapshot) -> Vec<u8> {
+    let mut buf = Vec::new();
+    if !resource.schema_url.is_empty() {
+        string::encode(SCHEMA_URL, &resource.schema_url, &mut buf);
+    }
+    buf
+}
@@ -0,0 +1,84 @@
+use std::fmt;
+
+/// Every problem found in a [`crate::TelescopeLayerBuilder`] configuration, reported at
+/// once so they can all be fixed in one go.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigError {
+    pub problems: Vec<String>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid telescope configuration")?;
+        for problem in &self.problems {
+            write!(f, "\n  - {problem}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Why a layer, transport or pipeline could not be set up.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum TelescopeError {
+    /// The builder configuration is invalid.
+    Config(ConfigError),
+    /// An endpoint url could not be parsed.
+    InvalidUri { uri: String, message: String },
+    /// Connecting to the endpoint failed.
+    Transport(tonic::transport::Error),
+    /// A TLS certificate file could not be read.
+    Tls(std::io::Error),
+    /// The exporter thread or its runtime could not be started.
+    Exporter(std::io::Error),
+    /// The disk queue could not be opened, or its key could not be read.
+    DiskQueue(tower::BoxError),
+    /// The capture file could not be opened.
+    Capture(std::io::Error),
+    /// A pipeline source failed to start.
+    Source(std::io::Error),
+    /// A lazily connecting channel was created outside a tokio runtime, which its
+    /// connection task needs to be spawned on.
+    NoRuntime,
+}
+
+impl fmt::Display for TelescopeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TelescopeError::Config(error) => error.fmt(f),
+            TelescopeError::InvalidUri { uri, message } => write!(f, "invalid endpoint {uri:?}: {message}"),
+            TelescopeError::Transport(error) => write!(f, "could not connect: {error}"),
+            TelescopeError::Tls(error) => write!(f, "could not read a TLS certificate: {error}"),
+            TelescopeError::Exporter(error) => write!(f, "could not start the exporter: {error}"),
+            TelescopeError::DiskQueue(error) => write!(f, "could not open the disk queue: {error}"),
+            TelescopeError::Capture(error) => write!(f, "could not open the capture file: {error}"),
+            TelescopeError::Source(error) => write!(f, "could not start a source: {error}"),
+            TelescopeError::NoRuntime => write!(f, "a lazily connecting channel has to be created inside a tokio runtime"),
+        }
+    }
+}
+
+impl std::error::Error for TelescopeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TelescopeError::Config(error) => Some(error),
+            TelescopeError::InvalidUri { .. } | TelescopeError::NoRuntime => None,
+            TelescopeError::Transport(error) => Some(error),
+            TelescopeError::Exporter(error) | TelescopeError::Capture(error) | TelescopeError::Source(error)
+            | TelescopeError::Tls(error) => Some(error),
+            TelescopeError::DiskQueue(error) => Some(error.as_ref()),
+        }
+    }
+}
+
+impl From<ConfigError> for TelescopeError {
+    fn from(error: ConfigError) -> Self {
+        TelescopeError::Config(error)
+    }
+}
+
+pub(crate) fn invalid_uri(uri: &str, error: impl fmt::Display) -> TelescopeError {
+    TelescopeError::InvalidUri { uri: uri.to_string(), message: error.to_string() }
+}
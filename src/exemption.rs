@@ -0,0 +1,28 @@
+use tracing::Metadata;
+
+use crate::expr::RecordFilter;
+use crate::opentelclient::LogRecord;
+use crate::routing::target_matches;
+
+// Records that quotas, load shedding, server sampling and deduplication never drop, e.g. audit logs.
+// Targets are checked on the metadata alone; filters need the record, so it is built
+// before the sampling stages when any are configured.
+#[derive(Clone, Default)]
+pub(crate) struct SamplingExemptions {
+    pub(crate) targets: Vec<String>,
+    pub(crate) filters: Vec<RecordFilter>,
+}
+
+impl SamplingExemptions {
+    pub(crate) fn target_exempt(&self, metadata: &Metadata<'_>) -> bool {
+        self.targets.iter().any(|pattern| target_matches(pattern, metadata.target()))
+    }
+
+    pub(crate) fn needs_record(&self) -> bool {
+        !self.filters.is_empty()
+    }
+
+    pub(crate) fn record_exempt(&self, target: &str, record: &LogRecord) -> bool {
+        self.filters.iter().any(|filter| filter.matches(target, record))
+    }
+}
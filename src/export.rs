@@ -0,0 +1,72 @@
+use bytes::{BufMut, Bytes};
+use tonic::codec::{Codec, EncodeBuf, Encoder, ProstCodec};
+use tonic::codegen::{Body, StdError};
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::{GrpcMethod, Request, Response, Status};
+
+use crate::opentelclient::ExportLogsServiceResponse;
+
+const EXPORT_PATH: &str = "/opentelemetry.proto.collector.logs.v1.LogsService/Export";
+
+// Same RPC as the generated LogsServiceClient, but the request is an already encoded
+// ExportLogsServiceRequest so batches can be built (and retried) without re-encoding.
+#[derive(Debug, Clone)]
+pub(crate) struct ExportClient<T> {
+    inner: tonic::client::Grpc<T>,
+}
+
+impl<T> ExportClient<T>
+    where
+        T: tonic::client::GrpcService<tonic::body::BoxBody>,
+        T::Error: Into<StdError>,
+        T::ResponseBody: Body<Data=Bytes> + Send + 'static,
+        <T::ResponseBody as Body>::Error: Into<StdError> + Send,
+{
+    pub(crate) fn new(inner: T) -> Self {
+        Self { inner: tonic::client::Grpc::new(inner) }
+    }
+
+    pub(crate) async fn export(&mut self, mut request: Request<Bytes>) -> Result<Response<ExportLogsServiceResponse>, Status> {
+        self.inner
+            .ready()
+            .await
+            .map_err(|e| Status::unknown(format!("Service was not ready: {}", e.into())))?;
+        request.extensions_mut()
+            .insert(GrpcMethod::new("opentelemetry.proto.collector.logs.v1.LogsService", "Export"));
+        self.inner.unary(request, PathAndQuery::from_static(EXPORT_PATH), EncodedCodec).await
+    }
+}
+
+type ResponseCodec = ProstCodec<ExportLogsServiceResponse, ExportLogsServiceResponse>;
+
+#[derive(Debug, Clone, Default)]
+struct EncodedCodec;
+
+impl Codec for EncodedCodec {
+    type Encode = Bytes;
+    type Decode = ExportLogsServiceResponse;
+    type Encoder = EncodedEncoder;
+    type Decoder = <ResponseCodec as Codec>::Decoder;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        EncodedEncoder
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        ResponseCodec::default().decoder()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct EncodedEncoder;
+
+impl Encoder for EncodedEncoder {
+    type Item = Bytes;
+    type Error = Status;
+
+    fn encode(&mut self, item: Self::Item, buf: &mut EncodeBuf<'_>) -> Result<(), Self::Error> {
+        buf.put(item);
+        Ok(())
+    }
+}
+
@@ -0,0 +1,513 @@
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use bytes::Bytes;
+use prost::Message;
+use tokio::runtime::Runtime;
+use tonic::metadata::{MetadataMap, MetadataValue};
+use tower::{Service, ServiceExt};
+use tower::load_shed::LoadShed;
+use tower::retry::Retry;
+use tracing::{debug_span, Instrument};
+use tracing::field::Empty;
+
+use crate::admission::InFlight;
+use crate::encode_buffer::EncodeBuffer;
+use crate::batch::Batch;
+use crate::attributes::AttributeLimits;
+use crate::capabilities::{Capabilities, CAPABILITIES_REQUEST_HEADER};
+use crate::clock::{Clock, SystemClock};
+use crate::dedup::Deduplicator;
+use crate::disk_queue::{DiskQueue, DiskQueueService, Replay};
+use crate::dictionary::DICTIONARY_VERSION_HEADER;
+use crate::early::EarlyBuffer;
+use crate::ids::{IdGenerator, RandomIdGenerator};
+use crate::instrumentation;
+use crate::internal::batch_summary;
+use crate::envelope::Envelope;
+use crate::memory_pressure::{MemoryPressureMonitor, MemoryPressureSignal};
+use crate::queue::QueueReceiver;
+use crate::pacing::{BacklogPacer, process_jitter};
+use crate::resource::SharedResource;
+use crate::retry::{RetryExhausted, RetryPolicy};
+use crate::runtime_metrics::RuntimeMetrics;
+use crate::span_metrics::SpanStats;
+use crate::support::RecentRecords;
+use crate::stats::ExportStats;
+use crate::shutdown::{Shutdown, ShutdownReport};
+use crate::service::{BoxExportService, ExportLayerFn, ExportErrorCallback, ExportRequest, MetadataService, REQUEST_ID_HEADER, ExportRetryPolicy, StaticHeaders};
+use crate::sink::{FallbackService, SinkService};
+use crate::trace_context::{hex, TraceContext};
+use crate::usage::{UsageClient, UsagePoller};
+
+const FLUSH_INTERVAL: Duration = Duration::from_millis(1000);
+const COALESCING_WINDOW: Duration = Duration::from_millis(100);
+const CAPABILITIES_PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+const MAX_BATCH_RECORDS: usize = 1000;
+// A batch is sent as soon as it holds this many records, without waiting for the flush
+// interval.
+const FLUSH_BATCH_RECORDS: usize = 100;
+
+thread_local! {
+    static INTERNAL_THREAD: Cell<bool> = const { Cell::new(false) };
+}
+
+// Events emitted by the pipeline itself (our own spans, tonic, hyper, h2) must never be
+// fed back into the layer, or every export would produce more records to export.
+pub(crate) fn is_internal_thread() -> bool {
+    INTERNAL_THREAD.with(|internal| internal.get())
+}
+
+fn mark_internal_thread() {
+    INTERNAL_THREAD.with(|internal| internal.set(true));
+}
+
+#[derive(Clone)]
+pub(crate) struct ExporterConfig {
+    pub(crate) service_name: String,
+    pub(crate) resource: Arc<SharedResource>,
+    pub(crate) arena_mode: bool,
+    pub(crate) thread_name: Option<String>,
+    pub(crate) thread_niceness: Option<i32>,
+    pub(crate) thread_core: Option<usize>,
+    pub(crate) propagate_trace_context: bool,
+    pub(crate) backlog_records_per_sec: Option<u32>,
+    pub(crate) backlog_initial_delay: Option<Duration>,
+    pub(crate) flush_jitter: Duration,
+    pub(crate) coalescing_window: Duration,
+    pub(crate) early_buffer: Option<usize>,
+    pub(crate) max_batch_bytes: Option<usize>,
+    pub(crate) quota_polling: Option<(UsageClient, Duration)>,
+    pub(crate) retry: RetryPolicy,
+    pub(crate) capabilities: Option<Arc<Capabilities>>,
+    pub(crate) capabilities_probe: bool,
+    pub(crate) recent: Option<Arc<RecentRecords>>,
+    pub(crate) attribute_dictionary: bool,
+    pub(crate) attribute_limits: AttributeLimits,
+    pub(crate) batch_summary: bool,
+    pub(crate) clock: Arc<dyn Clock>,
+    pub(crate) id_generator: Arc<dyn IdGenerator>,
+    pub(crate) export_layers: Vec<ExportLayerFn>,
+    pub(crate) metadata: Arc<StaticHeaders>,
+    pub(crate) in_flight: InFlight,
+    pub(crate) load_shed: bool,
+    pub(crate) span_summaries: Option<(Arc<SpanStats>, Duration)>,
+    pub(crate) runtime_metrics: Option<(Duration, Option<tokio::runtime::Handle>)>,
+    pub(crate) dedup: Option<Arc<Deduplicator>>,
+    pub(crate) stats: Option<Arc<ExportStats>>,
+    pub(crate) error_callback: Option<ExportErrorCallback>,
+    pub(crate) disk_queue: Option<Arc<DiskQueue>>,
+    pub(crate) fallback: Option<SinkService>,
+    pub(crate) shutdown: Arc<Shutdown>,
+    pub(crate) memory_pressure: Option<MemoryPressureSignal>,
+}
+
+impl ExporterConfig {
+    pub(crate) fn new(service_name: String) -> Self {
+        Self {
+            resource: Arc::new(SharedResource::new(&service_name)),
+            service_name,
+            arena_mode: false,
+            thread_name: None,
+            thread_niceness: None,
+            thread_core: None,
+            propagate_trace_context: false,
+            backlog_records_per_sec: None,
+            backlog_initial_delay: None,
+            flush_jitter: Duration::ZERO,
+            coalescing_window: COALESCING_WINDOW,
+            early_buffer: None,
+            max_batch_bytes: None,
+            quota_polling: None,
+            retry: RetryPolicy::default(),
+            capabilities: None,
+            recent: None,
+            capabilities_probe: false,
+            attribute_dictionary: false,
+            attribute_limits: AttributeLimits::default(),
+            batch_summary: false,
+            clock: Arc::new(SystemClock),
+            id_generator: Arc::new(RandomIdGenerator),
+            export_layers: Vec::new(),
+            metadata: Arc::default(),
+            in_flight: InFlight::default(),
+            load_shed: false,
+            span_summaries: None,
+            runtime_metrics: None,
+            dedup: None,
+            stats: None,
+            error_callback: None,
+            disk_queue: None,
+            fallback: None,
+            shutdown: Arc::new(Shutdown::default()),
+            memory_pressure: None,
+        }
+    }
+
+    // The destination wrapped in the export middleware, and the static metadata headers
+    // around that.
+    pub(crate) fn export_stack(&self, destination: BoxExportService) -> BoxExportService {
+        let mut service = self.export_layers.iter().fold(destination, |service, layer| layer(service));
+        if self.load_shed {
+            service = BoxExportService::new(LoadShed::new(service));
+        }
+        if self.metadata.is_empty() {
+            return service;
+        }
+        BoxExportService::new(MetadataService { inner: service, headers: self.metadata.clone() })
+    }
+}
+
+// The runtime an exporter thread drives its requests (and lazily connected channels) on.
+pub(crate) fn exporter_runtime() -> io::Result<Runtime> {
+    tokio::runtime::Builder::new_multi_thread()
+        .thread_name("telescope-flusher")
+        .on_thread_start(mark_internal_thread)
+        .enable_all()
+        .build()
+}
+
+pub(crate) fn start_logging_thread(rx: QueueReceiver, destination: BoxExportService, config: ExporterConfig, rt: Runtime) -> io::Result<()> {
+    let thread_name = config.thread_name.clone().unwrap_or_else(|| "telescope-exporter".to_string());
+    let shutdown = config.shutdown.clone();
+    shutdown.register_exporter();
+    let spawned = thread::Builder::new().name(thread_name).spawn(move || {
+        mark_internal_thread();
+        configure_current_thread(&config);
+        let mut buffer = Batch::with_capacity(MAX_BATCH_RECORDS);
+        let mut encode_buffer = config.arena_mode.then(EncodeBuffer::new);
+        let flush_interval = FLUSH_INTERVAL + process_jitter(&config.service_name, config.flush_jitter);
+        let clock = config.clock.clone();
+        let mut last_send = clock.now();
+        let mut last_span_summary = clock.now();
+        let mut runtime_metrics = config.runtime_metrics.clone()
+            .map(|(interval, runtime)| RuntimeMetrics::new(interval, runtime, clock.now()));
+        let mut memory_pressure = config.memory_pressure.clone().map(MemoryPressureMonitor::new);
+        let mut envelope = Envelope::new(&config, &config.resource.current());
+        let pacer = Arc::new(Mutex::new(BacklogPacer::new(clock.clone(), config.backlog_records_per_sec, config.backlog_initial_delay)));
+        let mut service = config.export_stack(destination);
+        if config.capabilities_probe && config.capabilities.is_some() {
+            let mut metadata = MetadataMap::new();
+            metadata.insert(CAPABILITIES_REQUEST_HEADER, MetadataValue::from_static("1"));
+            if config.attribute_dictionary {
+                metadata.insert(DICTIONARY_VERSION_HEADER, MetadataValue::from(0u64));
+            }
+            let probe = service.clone().oneshot(ExportRequest { payload: Bytes::new(), metadata });
+            let _ = rt.block_on(async { tokio::time::timeout(CAPABILITIES_PROBE_TIMEOUT, probe).await });
+        }
+        let mut usage = config.quota_polling.clone()
+            .map(|(client, interval)| UsagePoller::new(client, interval, config.service_name.clone()));
+        let mut early = config.early_buffer.map(|max_records| EarlyBuffer::new(max_records, service.clone()));
+        let mut held = VecDeque::new();
+        if let Some(fallback) = &config.fallback {
+            service = BoxExportService::new(FallbackService { primary: service, fallback: fallback.clone() });
+        }
+        let mut replay = config.disk_queue.as_ref().map(|queue| Replay {
+            queue: queue.clone(),
+            service: service.clone(),
+            retry_at: None,
+        });
+        if let Some(queue) = &config.disk_queue {
+            service = BoxExportService::new(DiskQueueService { inner: service, queue: queue.clone() });
+        }
+        // Batches whose retries ran out, with their record counts, to try again later.
+        let mut requeued = VecDeque::new();
+        // Records in `buffer` that came from the queue, and so count as in flight; the
+        // exporter's own records don't.
+        let mut buffered_from_queue = 0;
+        let mut service = Retry::new(ExportRetryPolicy {
+            policy: config.retry,
+            attempts: 0,
+            clock: clock.clone(),
+            pacer: pacer.clone(),
+            error_callback: config.error_callback.clone(),
+            shutdown: config.shutdown.clone(),
+        }, service);
+        let mut report = ShutdownReport::default();
+        loop {
+            let shutting_down = config.shutdown.deadline().is_some();
+            // Records the exporter produces itself carry the current resource.
+            let mut internal = Vec::new();
+            if let Some((stats, interval)) = &config.span_summaries {
+                if clock.now().saturating_duration_since(last_span_summary) >= *interval {
+                    internal.extend(stats.drain(clock.as_ref()));
+                    last_span_summary = clock.now();
+                }
+            }
+            // Connection events and runtime metrics are kept for support bundles too;
+            // summaries aren't, as they carry what the application logged.
+            let mut diagnostics = Vec::new();
+            if let Some(runtime_metrics) = runtime_metrics.as_mut() {
+                diagnostics.extend(runtime_metrics.sample(clock.as_ref()));
+            }
+            if let Some(stats) = &config.stats {
+                diagnostics.extend(stats.drain_events());
+            }
+            if let Some(recent) = config.recent.as_ref().filter(|_| !diagnostics.is_empty()) {
+                recent.on_internal(&diagnostics);
+            }
+            internal.extend(diagnostics);
+            if let Some(dedup) = &config.dedup {
+                internal.extend(dedup.drain(clock.as_ref()));
+            }
+            if !internal.is_empty() {
+                let resource = config.resource.current();
+                for record in internal {
+                    buffer.push(record, &resource);
+                }
+            }
+            if let Some(waiting) = early.as_mut() {
+                if !shutting_down && waiting.hold(&rx, &rt, clock.as_ref()) {
+                    clock.sleep(config.coalescing_window);
+                    continue;
+                }
+                held = std::mem::take(&mut waiting.records);
+                early = None;
+            }
+            // Capped to what the server accepts, if it said so.
+            let max_batch = config.capabilities.as_ref()
+                .and_then(|capabilities| capabilities.snapshot().max_batch_records)
+                .map_or(MAX_BATCH_RECORDS, |max| max.min(MAX_BATCH_RECORDS));
+            let mut full = false;
+            while let Some(queued) = held.pop_front().or_else(|| rx.try_recv()) {
+                // A record too large on its own still goes out, in a batch of one.
+                if config.max_batch_bytes.is_some_and(|max_bytes| {
+                    !buffer.is_empty() && buffer.record_bytes() + queued.record.encoded_len() > max_bytes
+                }) {
+                    held.push_front(queued);
+                    full = true;
+                    break;
+                }
+                buffer.push_queued(queued);
+                buffered_from_queue += 1;
+                if buffer.len() >= max_batch {
+                    full = true;
+                    break;
+                }
+            }
+
+            if shutting_down && ((buffer.is_empty() && requeued.is_empty()) || config.shutdown.remaining(clock.as_ref()) == Some(Duration::ZERO)) {
+                let queued = held.len() + rx.drain();
+                config.in_flight.complete(buffered_from_queue + queued);
+                report.dropped += (buffer.len() + queued) as u64;
+                report.dropped += requeued.iter().map(|(_, records)| *records as u64).sum::<u64>();
+                config.shutdown.exporter_finished(report);
+                return;
+            }
+
+            // Under memory pressure, hold on to as little as possible: send whatever is
+            // buffered right away and give the batch storage back afterwards.
+            let under_pressure = memory_pressure.as_mut()
+                .is_some_and(|memory_pressure| memory_pressure.under_pressure(clock.as_ref()));
+            if let Some(usage) = usage.as_mut() {
+                usage.poll(&rt, clock.as_ref(), config.stats.as_deref());
+            }
+            // Out of quota: leave the records queued until the window resets.
+            let throttled = !shutting_down && usage.as_ref().is_some_and(|usage| usage.exhausted(clock.as_ref()));
+            if throttled {
+                clock.sleep(config.coalescing_window);
+            } else if (shutting_down && !buffer.is_empty()) || (under_pressure && !buffer.is_empty()) || full || buffer.len() >= FLUSH_BATCH_RECORDS.min(max_batch)
+                || clock.now().saturating_duration_since(last_send) >= flush_interval {
+                if !shutting_down {
+                    pacer.lock().unwrap().pace(buffer.len(), full);
+                }
+                if let Some(recent) = &config.recent {
+                    recent.on_batch(&buffer.records);
+                }
+                if config.batch_summary {
+                    let summary = batch_summary(clock.as_ref(), &buffer.records);
+                    buffer.push(summary, &config.resource.current());
+                }
+                // The version the ids are from, or 0 to ask for a dictionary.
+                let dictionary_version = config.attribute_dictionary.then(|| {
+                    match config.capabilities.as_ref().and_then(|capabilities| capabilities.dictionary()) {
+                        Some(dictionary) => {
+                            dictionary.compress(&mut buffer.records);
+                            dictionary.version
+                        }
+                        None => 0,
+                    }
+                });
+                let records = buffer.len();
+                let request_id = hex(&rand::random::<[u8; 8]>());
+                let span = debug_span!("telescope.export", records, request_id, trace_id = Empty, span_id = Empty);
+                let trace_context = config.propagate_trace_context.then(|| {
+                    let context = TraceContext::generate(config.id_generator.as_ref());
+                    span.record("trace_id", hex(&context.trace_id));
+                    span.record("span_id", hex(&context.span_id));
+                    context
+                });
+                let payload = span.in_scope(|| match encode_buffer.as_mut() {
+                    Some(encode_buffer) => encode_buffer.encode(&config, &mut envelope, &buffer),
+                    None => encode_batch(&config, &mut envelope, &buffer),
+                });
+                if encode_buffer.is_some() {
+                    buffer.clear();
+                } else {
+                    buffer = Batch::default();
+                }
+
+                let mut metadata = MetadataMap::new();
+                if let Ok(request_id) = MetadataValue::try_from(request_id) {
+                    metadata.insert(REQUEST_ID_HEADER, request_id);
+                }
+                if let Some(context) = &trace_context {
+                    context.inject(&mut metadata);
+                }
+                if let Some(version) = dictionary_version {
+                    metadata.insert(DICTIONARY_VERSION_HEADER, MetadataValue::from(version));
+                }
+                let request = ExportRequest { payload: payload.clone(), metadata };
+
+                let persisted = persisted_batches(&config);
+                let export = async {
+                    service.ready().await?.call(request.clone()).await
+                };
+                // The retry policy only gives up if the middleware stack itself fails, or
+                // when retrying would overrun the shutdown deadline.
+                let export = config.shutdown.bounded(clock.as_ref(), export);
+                let exported = rt.block_on(export.instrument(span.clone())).is_ok();
+                if exported {
+                    instrumentation::batch_exported(records, payload.len());
+                    if let Some(usage) = usage.as_mut() {
+                        usage.on_exported(records, clock.as_ref(), config.stats.as_deref());
+                    }
+                } else if !shutting_down {
+                    // Only a policy with a limited number of attempts gives up outside
+                    // shutdown.
+                    retries_exhausted(&config, &mut requeued, request.clone(), records);
+                }
+                if shutting_down {
+                    let records = records as u64;
+                    if persisted_batches(&config) > persisted {
+                        report.persisted_to_disk += records;
+                    } else if exported {
+                        report.exported += records;
+                    } else if config.disk_queue.as_ref().is_some_and(|queue| queue.push(&request).is_ok()) {
+                        // Cut off by the deadline before the disk queue could take it.
+                        report.persisted_to_disk += records;
+                    } else {
+                        report.dropped += records;
+                    }
+                }
+                config.in_flight.complete(std::mem::take(&mut buffered_from_queue));
+                if under_pressure {
+                    instrumentation::memory_pressure_flush();
+                    buffer = Batch::default();
+                    if let Some(encode_buffer) = encode_buffer.as_mut() {
+                        encode_buffer.shrink();
+                    }
+                }
+                last_send = clock.now();
+            } else if let Some((request, records)) = requeued.pop_front() {
+                let export = async {
+                    service.ready().await?.call(request.clone()).await
+                };
+                let exported = rt.block_on(config.shutdown.bounded(clock.as_ref(), export)).is_ok();
+                if exported {
+                    instrumentation::batch_exported(records, request.payload.len());
+                }
+                match (shutting_down, exported) {
+                    (true, true) => report.exported += records as u64,
+                    (true, false) => report.dropped += records as u64,
+                    (false, true) => {}
+                    (false, false) => requeue(&config, &mut requeued, request, records),
+                }
+            } else if replay.as_mut().is_some_and(|replay| replay.run(&rt, clock.as_ref())) {
+                // Keep draining the disk queue while the endpoint takes it.
+            } else {
+                // Let records pile up in the queue, to be drained together on the next
+                // round, instead of waking up for every one of them.
+                clock.sleep(config.coalescing_window);
+            }
+        }
+    });
+    if spawned.is_err() {
+        shutdown.exporter_finished(ShutdownReport::default());
+    }
+    spawned.map(|_| ())
+}
+
+fn retries_exhausted(config: &ExporterConfig, requeued: &mut VecDeque<(ExportRequest, usize)>, request: ExportRequest, records: usize) {
+    if let Some(stats) = &config.stats {
+        stats.on_retries_exhausted();
+    }
+    requeue(config, requeued, request, records);
+}
+
+// Also used for requeued batches failing again, which were counted as exhausted already.
+fn requeue(config: &ExporterConfig, requeued: &mut VecDeque<(ExportRequest, usize)>, request: ExportRequest, records: usize) {
+    if let RetryExhausted::Requeue { max_batches } = config.retry.on_exhausted {
+        if requeued.len() >= max_batches {
+            if let Some((_, evicted)) = requeued.pop_front() {
+                if let Some(stats) = &config.stats {
+                    stats.on_records_dropped(evicted as u64);
+                }
+                instrumentation::records_dropped(evicted);
+            }
+        }
+        requeued.push_back((request, records));
+    }
+}
+
+fn persisted_batches(config: &ExporterConfig) -> u64 {
+    config.disk_queue.as_ref().map_or(0, |queue| queue.pushed())
+}
+
+fn configure_current_thread(config: &ExporterConfig) {
+    if let Some(core) = config.thread_core {
+        core_affinity::set_for_current(core_affinity::CoreId { id: core });
+    }
+    #[cfg(target_os = "linux")]
+    if let Some(niceness) = config.thread_niceness {
+        // On Linux a `who` of 0 with PRIO_PROCESS only affects the calling thread.
+        unsafe {
+            libc::setpriority(libc::PRIO_PROCESS, 0, niceness);
+        }
+    }
+}
+
+fn encode_batch(config: &ExporterConfig, envelope: &mut Envelope, batch: &Batch) -> Bytes {
+    let scope_logs_lens = batch.scope_logs_lens();
+    let mut buf = Vec::with_capacity(batch.encoded_len(config, envelope, &scope_logs_lens));
+    batch.encode(config, envelope, &scope_logs_lens, &mut buf);
+    buf.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::sync::Arc;
+
+    use tonic::metadata::MetadataMap;
+
+    use super::{requeue, retries_exhausted, ExporterConfig};
+    use crate::retry::RetryExhausted;
+    use crate::service::ExportRequest;
+    use crate::stats::ExportStats;
+
+    #[test]
+    fn requeued_batches_are_exhausted_once_and_evictions_dropped() {
+        let mut config = ExporterConfig::new("test".to_string());
+        config.retry.on_exhausted = RetryExhausted::Requeue { max_batches: 2 };
+        let stats = Arc::new(ExportStats::new(config.clock.clone(), false));
+        config.stats = Some(stats.clone());
+        let request = || ExportRequest { payload: Default::default(), metadata: MetadataMap::new() };
+        let mut requeued = VecDeque::new();
+        retries_exhausted(&config, &mut requeued, request(), 3);
+        retries_exhausted(&config, &mut requeued, request(), 5);
+        // The first batch fails again and goes to the back.
+        let (failed, records) = requeued.pop_front().unwrap();
+        requeue(&config, &mut requeued, failed, records);
+        retries_exhausted(&config, &mut requeued, request(), 7);
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.retries_exhausted, 3);
+        assert_eq!(snapshot.records_dropped, 5);
+        assert_eq!(requeued.iter().map(|(_, records)| *records).collect::<Vec<_>>(), [3, 7]);
+    }
+}
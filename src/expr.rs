@@ -0,0 +1,466 @@
+use std::fmt;
+
+use crate::decode::display;
+use crate::opentelclient::any_value::Value;
+use crate::opentelclient::{AnyValue, LogRecord};
+
+/// A boolean expression over a record, compiled once and evaluated for every record:
+///
+/// ```text
+/// severity >= WARN || attributes["customer_tier"] == "enterprise"
+/// target contains "payments" && !(body contains "healthcheck")
+/// ```
+///
+/// Operands are `severity` (compared against `TRACE`, `DEBUG`, `INFO`, `WARN`, `ERROR`,
+/// `FATAL` or a severity number), `body`, `target`, `attributes["key"]`, string and
+/// number literals, `true` and `false`. Operators are `==`, `!=`, `<`, `<=`, `>`, `>=`,
+/// `contains`, `starts_with`, `!`, `&&` and `||`, with parentheses for grouping. A
+/// missing attribute compares unequal to everything, and on its own is false.
+#[derive(Clone, Debug)]
+pub struct RecordFilter {
+    source: String,
+    expr: Expr,
+}
+
+/// Why a [`RecordFilter`] expression didn't compile.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FilterParseError {
+    /// Byte offset into the expression.
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for FilterParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for FilterParseError {}
+
+impl RecordFilter {
+    pub fn parse(source: &str) -> Result<Self, FilterParseError> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, position: 0, end: source.len() };
+        let expr = parser.or()?;
+        if let Some((position, _)) = parser.tokens.get(parser.position) {
+            return Err(FilterParseError { position: *position, message: "unexpected input".to_string() });
+        }
+        Ok(Self { source: source.to_string(), expr })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    pub(crate) fn matches(&self, target: &str, record: &LogRecord) -> bool {
+        self.expr.eval(target, record).truthy()
+    }
+}
+
+impl fmt::Display for RecordFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+    StartsWith,
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Literal(Operand),
+    Severity,
+    Body,
+    Target,
+    Attribute(String),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Compare(Box<Expr>, Op, Box<Expr>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Operand {
+    Missing,
+    Bool(bool),
+    Number(f64),
+    String(String),
+}
+
+impl Operand {
+    fn truthy(&self) -> bool {
+        match self {
+            Operand::Missing => false,
+            Operand::Bool(value) => *value,
+            _ => true,
+        }
+    }
+}
+
+impl Expr {
+    fn eval(&self, target: &str, record: &LogRecord) -> Operand {
+        match self {
+            Expr::Literal(value) => value.clone(),
+            Expr::Severity => Operand::Number(record.severity_number as f64),
+            Expr::Body => match record.body.as_ref().and_then(|body| body.value.as_ref()) {
+                Some(value) => operand(value),
+                None => Operand::Missing,
+            },
+            Expr::Target => Operand::String(target.to_string()),
+            Expr::Attribute(key) => record.attributes.iter()
+                .find(|attribute| attribute.key == *key)
+                .and_then(|attribute| attribute.value.as_ref()?.value.as_ref())
+                .map_or(Operand::Missing, operand),
+            Expr::Not(expr) => Operand::Bool(!expr.eval(target, record).truthy()),
+            Expr::And(left, right) => Operand::Bool(left.eval(target, record).truthy() && right.eval(target, record).truthy()),
+            Expr::Or(left, right) => Operand::Bool(left.eval(target, record).truthy() || right.eval(target, record).truthy()),
+            Expr::Compare(left, op, right) => Operand::Bool(compare(&left.eval(target, record), *op, &right.eval(target, record))),
+        }
+    }
+}
+
+fn operand(value: &Value) -> Operand {
+    match value {
+        Value::StringValue(value) => Operand::String(value.clone()),
+        Value::BoolValue(value) => Operand::Bool(*value),
+        Value::IntValue(value) => Operand::Number(*value as f64),
+        Value::DoubleValue(value) => Operand::Number(*value),
+        value => Operand::String(display(&AnyValue { value: Some(value.clone()) })),
+    }
+}
+
+fn compare(left: &Operand, op: Op, right: &Operand) -> bool {
+    match (left, right) {
+        (Operand::Missing, _) | (_, Operand::Missing) => op == Op::Ne,
+        (Operand::Number(left), Operand::Number(right)) => ordered(left.partial_cmp(right), op),
+        (Operand::String(left), Operand::String(right)) => match op {
+            Op::Contains => left.contains(right.as_str()),
+            Op::StartsWith => left.starts_with(right.as_str()),
+            op => ordered(Some(left.cmp(right)), op),
+        },
+        (Operand::Bool(left), Operand::Bool(right)) => ordered(Some(left.cmp(right)), op),
+        _ => op == Op::Ne,
+    }
+}
+
+fn ordered(ordering: Option<std::cmp::Ordering>, op: Op) -> bool {
+    let Some(ordering) = ordering else {
+        return op == Op::Ne;
+    };
+    match op {
+        Op::Eq => ordering.is_eq(),
+        Op::Ne => ordering.is_ne(),
+        Op::Lt => ordering.is_lt(),
+        Op::Le => ordering.is_le(),
+        Op::Gt => ordering.is_gt(),
+        Op::Ge => ordering.is_ge(),
+        Op::Contains | Op::StartsWith => false,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    String(String),
+    Number(f64),
+    Op(Op),
+    Not,
+    And,
+    Or,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+}
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, FilterParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(position, c)) = chars.peek() {
+        let error = |message: &str| FilterParseError { position, message: message.to_string() };
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let token = match c {
+            '(' | ')' | '[' | ']' => {
+                chars.next();
+                match c {
+                    '(' => Token::LParen,
+                    ')' => Token::RParen,
+                    '[' => Token::LBracket,
+                    _ => Token::RBracket,
+                }
+            }
+            '"' => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, c)) => value.push(c),
+                            None => return Err(error("unterminated string")),
+                        },
+                        Some((_, c)) => value.push(c),
+                        None => return Err(error("unterminated string")),
+                    }
+                }
+                Token::String(value)
+            }
+            '=' | '!' | '<' | '>' | '&' | '|' => {
+                chars.next();
+                let next = chars.peek().map(|(_, c)| *c);
+                let (token, two) = match (c, next) {
+                    ('=', Some('=')) => (Token::Op(Op::Eq), true),
+                    ('!', Some('=')) => (Token::Op(Op::Ne), true),
+                    ('<', Some('=')) => (Token::Op(Op::Le), true),
+                    ('>', Some('=')) => (Token::Op(Op::Ge), true),
+                    ('&', Some('&')) => (Token::And, true),
+                    ('|', Some('|')) => (Token::Or, true),
+                    ('!', _) => (Token::Not, false),
+                    ('<', _) => (Token::Op(Op::Lt), false),
+                    ('>', _) => (Token::Op(Op::Gt), false),
+                    _ => return Err(error("unknown operator")),
+                };
+                if two {
+                    chars.next();
+                }
+                token
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                let mut number = String::new();
+                while let Some(&(_, c)) = chars.peek() {
+                    if !(c.is_ascii_digit() || c == '.' || (c == '-' && number.is_empty())) {
+                        break;
+                    }
+                    number.push(c);
+                    chars.next();
+                }
+                Token::Number(number.parse().map_err(|_| error("invalid number"))?)
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut ident = String::new();
+                while let Some(&(_, c)) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    ident.push(c);
+                    chars.next();
+                }
+                match ident.as_str() {
+                    "contains" => Token::Op(Op::Contains),
+                    "starts_with" => Token::Op(Op::StartsWith),
+                    _ => Token::Ident(ident),
+                }
+            }
+            _ => return Err(error("unexpected character")),
+        };
+        tokens.push((position, token));
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(_, token)| token)
+    }
+
+    fn next(&mut self) -> Result<Token, FilterParseError> {
+        let token = self.tokens.get(self.position).map(|(_, token)| token.clone())
+            .ok_or_else(|| self.error("unexpected end of expression"))?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn error(&self, message: &str) -> FilterParseError {
+        let position = self.tokens.get(self.position).map_or(self.end, |(position, _)| *position);
+        FilterParseError { position, message: message.to_string() }
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), FilterParseError> {
+        if self.peek() != Some(&expected) {
+            return Err(self.error(&format!("expected {expected:?}")));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn or(&mut self) -> Result<Expr, FilterParseError> {
+        let mut expr = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.position += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, FilterParseError> {
+        let mut expr = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.position += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, FilterParseError> {
+        if self.peek() == Some(&Token::Not) {
+            self.position += 1;
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        let left = self.primary()?;
+        match self.peek() {
+            Some(&Token::Op(op)) => {
+                self.position += 1;
+                Ok(Expr::Compare(Box::new(left), op, Box::new(self.primary()?)))
+            }
+            _ => Ok(left),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, FilterParseError> {
+        let position = self.position;
+        Ok(match self.next()? {
+            Token::LParen => {
+                let expr = self.or()?;
+                self.expect(Token::RParen)?;
+                expr
+            }
+            Token::String(value) => Expr::Literal(Operand::String(value)),
+            Token::Number(value) => Expr::Literal(Operand::Number(value)),
+            Token::Ident(ident) => match ident.as_str() {
+                "true" => Expr::Literal(Operand::Bool(true)),
+                "false" => Expr::Literal(Operand::Bool(false)),
+                "severity" => Expr::Severity,
+                "body" => Expr::Body,
+                "target" => Expr::Target,
+                "TRACE" => Expr::Literal(Operand::Number(1.0)),
+                "DEBUG" => Expr::Literal(Operand::Number(5.0)),
+                "INFO" => Expr::Literal(Operand::Number(9.0)),
+                "WARN" => Expr::Literal(Operand::Number(13.0)),
+                "ERROR" => Expr::Literal(Operand::Number(17.0)),
+                "FATAL" => Expr::Literal(Operand::Number(21.0)),
+                "attributes" => {
+                    self.expect(Token::LBracket)?;
+                    let Token::String(key) = self.next()? else {
+                        self.position -= 1;
+                        return Err(self.error("expected a quoted attribute key"));
+                    };
+                    self.expect(Token::RBracket)?;
+                    Expr::Attribute(key)
+                }
+                _ => {
+                    self.position = position;
+                    return Err(self.error(&format!("unknown identifier `{ident}`")));
+                }
+            },
+            _ => {
+                self.position = position;
+                return Err(self.error("expected a value"));
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FilterParseError, RecordFilter};
+    use crate::internal::attribute;
+    use crate::opentelclient::any_value::Value::{IntValue, StringValue};
+    use crate::opentelclient::{AnyValue, LogRecord};
+
+    fn record() -> LogRecord {
+        LogRecord {
+            severity_number: 13,
+            body: Some(AnyValue { value: Some(StringValue("GET /healthcheck".to_string())) }),
+            attributes: vec![
+                attribute("customer_tier", StringValue("enterprise".to_string())),
+                attribute("status", IntValue(503)),
+            ],
+            ..LogRecord::default()
+        }
+    }
+
+    fn matches(source: &str) -> bool {
+        RecordFilter::parse(source).unwrap().matches("payments::api", &record())
+    }
+
+    fn error(source: &str) -> (usize, String) {
+        let FilterParseError { position, message } = RecordFilter::parse(source).unwrap_err();
+        (position, message)
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert!(matches("true || false && false"));
+        assert!(!matches("(true || false) && false"));
+        assert!(matches("false && false || true"));
+    }
+
+    #[test]
+    fn not_applies_to_the_next_operand() {
+        assert!(matches("!false && true"));
+        assert!(!matches("!(false || true)"));
+        assert!(matches("!true || true"));
+        assert!(matches("!!true"));
+        assert!(!matches("!(body contains \"healthcheck\")"));
+        assert!(matches("target starts_with \"payments\" && !(target contains \"billing\")"));
+    }
+
+    #[test]
+    fn missing_attributes_compare_unequal() {
+        assert!(!matches("attributes[\"region\"]"));
+        assert!(matches("!attributes[\"region\"]"));
+        assert!(!matches("attributes[\"region\"] == \"eu\""));
+        assert!(matches("attributes[\"region\"] != \"eu\""));
+        assert!(!matches("attributes[\"region\"] < 5"));
+        assert!(!matches("attributes[\"region\"] >= 5"));
+        assert!(!matches("attributes[\"region\"] contains \"e\""));
+        assert!(matches("attributes[\"customer_tier\"] == \"enterprise\""));
+        assert!(matches("attributes[\"status\"] >= 500"));
+        // Values of different types are unequal rather than converted.
+        assert!(matches("attributes[\"status\"] != \"503\""));
+    }
+
+    #[test]
+    fn severity_names_are_severity_numbers() {
+        assert!(matches("severity == WARN"));
+        assert!(matches("severity == 13"));
+        assert!(matches("severity >= INFO && severity < ERROR"));
+        assert!(!matches("severity > WARN"));
+        assert!(matches("severity >= 9.5"));
+        assert!(matches("WARN == 13 && TRACE < DEBUG && ERROR < FATAL"));
+    }
+
+    #[test]
+    fn malformed_input_is_rejected_with_its_position() {
+        assert_eq!(error("severity >="), (11, "unexpected end of expression".to_string()));
+        assert_eq!(error("severity = WARN"), (9, "unknown operator".to_string()));
+        assert_eq!(error("body == \"abc"), (8, "unterminated string".to_string()));
+        assert_eq!(error("attributes[key]"), (11, "expected a quoted attribute key".to_string()));
+        assert_eq!(error("(true || false"), (14, "expected RParen".to_string()));
+        assert_eq!(error("level >= WARN"), (0, "unknown identifier `level`".to_string()));
+        assert_eq!(error("true false"), (5, "unexpected input".to_string()));
+        assert_eq!(error("severity >= 1.2.3"), (12, "invalid number".to_string()));
+        assert_eq!(error("severity >= #"), (12, "unexpected character".to_string()));
+        assert_eq!(error("&& true"), (0, "expected a value".to_string()));
+        assert_eq!(error(""), (0, "unexpected end of expression".to_string()));
+    }
+}
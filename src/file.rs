@@ -0,0 +1,106 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use tower::BoxError;
+
+use crate::service::ExportRequest;
+use crate::sink::Sink;
+
+/// Appends every batch to a local file as a length-prefixed `ExportLogsServiceRequest`
+/// (4 byte big-endian length, then the protobuf message), the framing the OpenTelemetry
+/// collector's file exporter uses for `format: proto`.
+///
+/// The file is rotated once it would grow past the size limit: `logs.otlp` becomes
+/// `logs.otlp.1`, `logs.otlp.1` becomes `logs.otlp.2` and so on, and files beyond the
+/// retention limit are deleted.
+pub struct FileSink {
+    path: PathBuf,
+    max_file_size: u64,
+    max_files: usize,
+    file: Option<File>,
+    written: u64,
+}
+
+impl FileSink {
+    /// 100 MiB per file, keeping 5 rotated files by default.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            max_file_size: 100 * 1024 * 1024,
+            max_files: 5,
+            file: None,
+            written: 0,
+        }
+    }
+
+    pub fn with_max_file_size(mut self, bytes: u64) -> Self {
+        self.max_file_size = bytes;
+        self
+    }
+
+    /// Number of rotated files kept next to the active one. 0 truncates the active
+    /// file instead of rotating it.
+    pub fn with_max_files(mut self, files: usize) -> Self {
+        self.max_files = files;
+        self
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file = None;
+        if self.max_files == 0 {
+            return fs::remove_file(&self.path).or_else(ignore_not_found);
+        }
+        fs::remove_file(rotated(&self.path, self.max_files)).or_else(ignore_not_found)?;
+        for index in (1..self.max_files).rev() {
+            fs::rename(rotated(&self.path, index), rotated(&self.path, index + 1)).or_else(ignore_not_found)?;
+        }
+        fs::rename(&self.path, rotated(&self.path, 1)).or_else(ignore_not_found)
+    }
+
+    fn open(&mut self) -> io::Result<&mut File> {
+        let file = match self.file.take() {
+            Some(file) => file,
+            None => {
+                let file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+                self.written = file.metadata()?.len();
+                file
+            }
+        };
+        Ok(self.file.insert(file))
+    }
+}
+
+impl Sink for FileSink {
+    fn export(&mut self, request: &ExportRequest) -> Result<(), BoxError> {
+        let frame_len = 4 + request.payload.len() as u64;
+        self.open()?;
+        if self.written > 0 && self.written + frame_len > self.max_file_size {
+            self.rotate()?;
+        }
+        let file = self.open()?;
+        let mut frame = Vec::with_capacity(frame_len as usize);
+        frame.extend_from_slice(&(request.payload.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&request.payload);
+        if let Err(error) = file.write_all(&frame).and_then(|_| file.flush()) {
+            // Start over from a fresh handle and the real file length next time.
+            self.file = None;
+            return Err(error.into());
+        }
+        self.written += frame_len;
+        Ok(())
+    }
+}
+
+fn rotated(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{index}"));
+    PathBuf::from(name)
+}
+
+fn ignore_not_found(error: io::Error) -> io::Result<()> {
+    match error.kind() {
+        io::ErrorKind::NotFound => Ok(()),
+        _ => Err(error),
+    }
+}
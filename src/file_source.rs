@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+
+use crate::clock::Clock;
+use crate::follow::{glob, line_record, FileIdentity, FollowedFile, POLL_INTERVAL};
+use crate::internal::attribute;
+use crate::opentelclient::any_value::Value::StringValue;
+use crate::opentelclient::KeyValue;
+use crate::routing::RecordSender;
+
+/// Ships plain log files of applications that don't use tracing: every line appended to
+/// a file matching one of the patterns becomes an INFO record with `log.file.path` and
+/// `log.file.name` attributes, exported through the layer's pipeline. Get one from
+/// [`crate::TelescopeLayer::file_tail_source`].
+///
+/// New files matching a pattern are picked up while running. Rotated files are read to
+/// the end before the new file is followed, and with a checkpoint file a restart
+/// continues where the previous run stopped instead of reading everything again.
+pub struct FileTailSource {
+    sender: RecordSender,
+    clock: Arc<dyn Clock>,
+    patterns: Vec<String>,
+    checkpoint_file: Option<PathBuf>,
+    target: String,
+    attributes: Vec<KeyValue>,
+}
+
+impl FileTailSource {
+    pub(crate) fn new(sender: RecordSender, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            sender,
+            clock,
+            patterns: Vec::new(),
+            checkpoint_file: None,
+            target: "file".to_string(),
+            attributes: Vec::new(),
+        }
+    }
+
+    /// Follow the files matching `pattern`; `*` matches any run of characters within a
+    /// path component, e.g. `/var/log/legacy-app/*.log`.
+    pub fn with_pattern(mut self, pattern: impl Into<String>) -> Self {
+        self.patterns.push(pattern.into());
+        self
+    }
+
+    /// Remember the read position of every file in `path`. Without it every matching
+    /// file is read from the start on each run.
+    pub fn with_checkpoint_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.checkpoint_file = Some(path.into());
+        self
+    }
+
+    /// Target used for routing and record filters, `file` by default.
+    pub fn with_target(mut self, target: impl Into<String>) -> Self {
+        self.target = target.into();
+        self
+    }
+
+    /// Add an attribute to every record.
+    pub fn with_attribute(mut self, key: &str, value: impl Into<String>) -> Self {
+        self.attributes.push(attribute(key, StringValue(value.into())));
+        self
+    }
+
+    /// Start following on a background thread.
+    pub fn start(self) -> io::Result<()> {
+        let checkpoints = match &self.checkpoint_file {
+            Some(path) => read_checkpoints(path)?,
+            None => HashMap::new(),
+        };
+        thread::Builder::new().name("telescope-file-source".to_string()).spawn(move || {
+            let mut state = TailState { source: self, files: HashMap::new(), checkpoints };
+            loop {
+                state.poll();
+                thread::sleep(POLL_INTERVAL);
+            }
+        })?;
+        Ok(())
+    }
+
+    fn send(&self, file: &FollowedFile, line: &str) {
+        let mut attributes = Vec::with_capacity(self.attributes.len() + 2);
+        attributes.push(attribute("log.file.path", StringValue(file.path.to_string_lossy().into_owned())));
+        if let Some(name) = file.path.file_name() {
+            attributes.push(attribute("log.file.name", StringValue(name.to_string_lossy().into_owned())));
+        }
+        attributes.extend(self.attributes.iter().cloned());
+        let now = self.clock.now_unix_nano();
+        self.sender.send(&self.target, line_record(now, now, line.to_string(), attributes));
+    }
+}
+
+struct TailState {
+    source: FileTailSource,
+    files: HashMap<PathBuf, FollowedFile>,
+    // Read positions by file identity, so a file renamed by rotation is recognized
+    // under its new name.
+    checkpoints: HashMap<FileIdentity, u64>,
+}
+
+impl TailState {
+    fn poll(&mut self) {
+        let matched: Vec<(PathBuf, FileIdentity)> = self.source.patterns.iter()
+            .flat_map(|pattern| glob(pattern))
+            .filter_map(|path| Some((path.clone(), FileIdentity::of(&path, &fs::metadata(&path).ok()?))))
+            .collect();
+        for (path, identity) in &matched {
+            // A file just rotated away from a path we follow is picked up under its new
+            // name once that path has moved on to the new file.
+            if self.files.contains_key(path) || self.files.values().any(|file| file.identity == *identity) {
+                continue;
+            }
+            let position = self.checkpoints.get(identity).copied().unwrap_or(0);
+            if let Ok(file) = FollowedFile::open(path.clone(), position) {
+                self.files.insert(path.clone(), file);
+            }
+        }
+
+        let mut checkpoints = self.checkpoints.clone();
+        let source = &self.source;
+        for file in self.files.values_mut() {
+            let mut lines = Vec::new();
+            file.read_lines(|line| lines.push(line.to_string()));
+            for line in lines {
+                source.send(file, &line);
+            }
+            checkpoints.insert(file.identity, file.position);
+            file.reopen_if_rotated();
+            checkpoints.insert(file.identity, file.position);
+        }
+        // Files that are gone have been read to their end above.
+        self.files.retain(|path, _| path.exists());
+        checkpoints.retain(|identity, _| {
+            matched.iter().any(|(_, matched)| matched == identity) || self.files.values().any(|file| file.identity == *identity)
+        });
+
+        if checkpoints != self.checkpoints {
+            if let Some(path) = &self.source.checkpoint_file {
+                let _ = write_checkpoints(path, &checkpoints);
+            }
+            self.checkpoints = checkpoints;
+        }
+    }
+}
+
+// One line per file: `<device> <inode> <position>`.
+fn read_checkpoints(path: &Path) -> io::Result<HashMap<FileIdentity, u64>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(error) => return Err(error),
+    };
+    Ok(text.lines()
+        .filter_map(|line| {
+            let mut fields = line.split(' ');
+            let device = fields.next()?.parse().ok()?;
+            let inode = fields.next()?.parse().ok()?;
+            let position = fields.next()?.parse().ok()?;
+            Some((FileIdentity { device, inode }, position))
+        })
+        .collect())
+}
+
+fn write_checkpoints(path: &Path, checkpoints: &HashMap<FileIdentity, u64>) -> io::Result<()> {
+    let text: String = checkpoints.iter()
+        .map(|(identity, position)| format!("{} {} {}\n", identity.device, identity.inode, position))
+        .collect();
+    let temporary = path.with_extension("tmp");
+    fs::write(&temporary, text)?;
+    fs::rename(&temporary, path)
+}
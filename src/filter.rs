@@ -0,0 +1,131 @@
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use tracing::Metadata;
+use tracing::level_filters::LevelFilter;
+
+use crate::routing::target_matches;
+
+// Export level per target, changed at runtime through the handle. The most specific
+// matching target wins; everything else is exported from the default level (INFO unless
+// configured otherwise) up.
+pub(crate) struct DynamicFilter {
+    default: AtomicU8,
+    has_overrides: AtomicBool,
+    // Longest pattern first, so the first match is the most specific one.
+    overrides: RwLock<Vec<(String, LevelFilter)>>,
+}
+
+// One `EnvFilter`-style directive: `my_crate::db=debug` for a target (and its children)
+// or a bare `warn` for the default level.
+pub(crate) fn parse_directive(directive: &str) -> Result<(Option<String>, LevelFilter), String> {
+    let (target, level) = match directive.split_once('=') {
+        Some((target, level)) => (Some(target.trim()), level.trim()),
+        None => (None, directive.trim()),
+    };
+    if target.is_some_and(str::is_empty) {
+        return Err(format!("filter directive {directive:?} has no target"));
+    }
+    let level = level.parse::<LevelFilter>()
+        .map_err(|_| format!("filter directive {directive:?} has an unknown level"))?;
+    Ok((target.map(str::to_string), level))
+}
+
+impl DynamicFilter {
+    pub(crate) fn new(default: LevelFilter) -> Self {
+        Self {
+            default: AtomicU8::new(encode(default)),
+            has_overrides: AtomicBool::new(false),
+            overrides: RwLock::new(Vec::new()),
+        }
+    }
+
+    pub(crate) fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        let default = decode(self.default.load(Ordering::Relaxed));
+        if !self.has_overrides.load(Ordering::Relaxed) {
+            return default >= *metadata.level();
+        }
+        let overrides = self.overrides.read().unwrap();
+        let level = overrides.iter()
+            .find(|(pattern, _)| target_matches(pattern, metadata.target()))
+            .map_or(default, |(_, level)| *level);
+        level >= *metadata.level()
+    }
+
+    pub(crate) fn default_level(&self) -> LevelFilter {
+        decode(self.default.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn set_default(&self, level: LevelFilter) {
+        self.default.store(encode(level), Ordering::Relaxed);
+    }
+
+    pub(crate) fn target_levels(&self) -> Vec<(String, LevelFilter)> {
+        self.overrides.read().unwrap().clone()
+    }
+
+    pub(crate) fn set(&self, target: String, level: LevelFilter) {
+        let mut overrides = self.overrides.write().unwrap();
+        overrides.retain(|(pattern, _)| *pattern != target);
+        overrides.push((target, level));
+        overrides.sort_by_key(|(pattern, _)| std::cmp::Reverse(pattern.len()));
+        self.has_overrides.store(true, Ordering::Relaxed);
+    }
+
+    pub(crate) fn remove(&self, target: &str) {
+        let mut overrides = self.overrides.write().unwrap();
+        overrides.retain(|(pattern, _)| pattern != target);
+        self.has_overrides.store(!overrides.is_empty(), Ordering::Relaxed);
+    }
+}
+
+fn encode(level: LevelFilter) -> u8 {
+    match level {
+        LevelFilter::OFF => 0,
+        LevelFilter::ERROR => 1,
+        LevelFilter::WARN => 2,
+        LevelFilter::INFO => 3,
+        LevelFilter::DEBUG => 4,
+        _ => 5,
+    }
+}
+
+fn decode(level: u8) -> LevelFilter {
+    match level {
+        0 => LevelFilter::OFF,
+        1 => LevelFilter::ERROR,
+        2 => LevelFilter::WARN,
+        3 => LevelFilter::INFO,
+        4 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    }
+}
+
+/// Cheap, cloneable handle for changing which levels a running layer exports, e.g. to
+/// bump it to DEBUG while diagnosing an incident. Changes apply to the next event.
+#[derive(Clone)]
+pub struct FilterHandle {
+    pub(crate) filter: Arc<DynamicFilter>,
+}
+
+impl FilterHandle {
+    /// Export records from `level` up for every target without a level of its own.
+    pub fn set_level(&self, level: impl Into<LevelFilter>) {
+        self.filter.set_default(level.into());
+    }
+
+    /// The level targets without a level of their own are exported from.
+    pub fn level(&self) -> LevelFilter {
+        self.filter.default_level()
+    }
+
+    /// See [`crate::TelescopeHandle::set_target_level`].
+    pub fn set_target_level(&self, target: impl Into<String>, level: impl Into<LevelFilter>) {
+        self.filter.set(target.into(), level.into());
+    }
+
+    /// Go back to the default level for `target`.
+    pub fn reset_target_level(&self, target: &str) {
+        self.filter.remove(target);
+    }
+}
@@ -0,0 +1,55 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::opentelclient::LogRecord;
+
+// The last DEBUG/TRACE records of one trace, kept on its root span and dropped with it
+// unless an ERROR shows up in the same trace first.
+struct FlightRecorder(VecDeque<LogRecord>);
+
+thread_local! {
+    // Records logged outside of any span, per thread.
+    static THREAD_RECORDER: RefCell<VecDeque<LogRecord>> = const { RefCell::new(VecDeque::new()) };
+}
+
+pub(crate) fn record<S>(ctx: &Context<'_, S>, event: &Event<'_>, capacity: usize, record: LogRecord)
+    where S: Subscriber + for<'a> LookupSpan<'a>
+{
+    let push = |records: &mut VecDeque<LogRecord>| {
+        if records.len() == capacity {
+            records.pop_front();
+        }
+        records.push_back(record);
+    };
+    match ctx.event_scope(event).and_then(|scope| scope.from_root().next()) {
+        Some(root) => {
+            let mut extensions = root.extensions_mut();
+            match extensions.get_mut::<FlightRecorder>() {
+                Some(recorder) => push(&mut recorder.0),
+                None => {
+                    let mut records = VecDeque::with_capacity(capacity);
+                    push(&mut records);
+                    extensions.insert(FlightRecorder(records));
+                }
+            }
+        }
+        None => THREAD_RECORDER.with(|records| push(&mut records.borrow_mut())),
+    }
+}
+
+// The recorded lead-up to an error, oldest first.
+pub(crate) fn take<S>(ctx: &Context<'_, S>, event: &Event<'_>) -> Vec<LogRecord>
+    where S: Subscriber + for<'a> LookupSpan<'a>
+{
+    match ctx.event_scope(event).and_then(|scope| scope.from_root().next()) {
+        Some(root) => root.extensions_mut()
+            .get_mut::<FlightRecorder>()
+            .map(|recorder| recorder.0.drain(..).collect())
+            .unwrap_or_default(),
+        None => THREAD_RECORDER.with(|records| records.borrow_mut().drain(..).collect()),
+    }
+}
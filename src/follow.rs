@@ -0,0 +1,162 @@
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use crate::opentelclient::any_value::Value::StringValue;
+use crate::opentelclient::{AnyValue, KeyValue, LogRecord};
+
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// Reads the lines appended to a file, `tail -F` style: `read_lines` hands out what was
+// written since the last call, and `reopen_if_rotated` starts over on a new file once
+// the old one was replaced or truncated. A line is only handed out once its newline has
+// been written.
+pub(crate) struct FollowedFile {
+    pub(crate) path: PathBuf,
+    reader: BufReader<File>,
+    pub(crate) identity: FileIdentity,
+    // Offset of the end of the last complete line.
+    pub(crate) position: u64,
+    line: Vec<u8>,
+}
+
+impl FollowedFile {
+    pub(crate) fn open(path: PathBuf, position: u64) -> io::Result<Self> {
+        let mut file = File::open(&path)?;
+        let identity = FileIdentity::of(&path, &file.metadata()?);
+        let position = if position <= file.metadata()?.len() { position } else { 0 };
+        file.seek(SeekFrom::Start(position))?;
+        Ok(Self { path, reader: BufReader::new(file), identity, position, line: Vec::new() })
+    }
+
+    // Bytes that aren't valid UTF-8 are replaced, so one such line doesn't stop the file.
+    pub(crate) fn read_lines(&mut self, mut on_line: impl FnMut(&str)) {
+        loop {
+            match self.reader.read_until(b'\n', &mut self.line) {
+                Ok(0) | Err(_) => return,
+                Ok(_) if self.line.ends_with(b"\n") => {
+                    self.position += self.line.len() as u64;
+                    let end = self.line.iter().rposition(|byte| !matches!(byte, b'\n' | b'\r')).map_or(0, |last| last + 1);
+                    on_line(&String::from_utf8_lossy(&self.line[..end]));
+                    self.line.clear();
+                }
+                // A partial line; the rest is read on top of it.
+                Ok(_) => {}
+            }
+        }
+    }
+
+    // Call after `read_lines` has drained the current file, so nothing written to it
+    // before the rotation is lost.
+    pub(crate) fn reopen_if_rotated(&mut self) {
+        let Ok(metadata) = fs::metadata(&self.path) else {
+            return;
+        };
+        let rotated = FileIdentity::of(&self.path, &metadata) != self.identity;
+        if !rotated && metadata.len() >= self.position + self.line.len() as u64 {
+            return;
+        }
+        if let Ok(reopened) = Self::open(self.path.clone(), 0) {
+            *self = reopened;
+        }
+    }
+}
+
+// Device and inode on unix, where a rotated file is recognized even if the new one has
+// grown past the old position. Elsewhere a hash of the path, so files (and their
+// checkpoints) are still told apart, but only truncation is noticed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct FileIdentity {
+    pub(crate) device: u64,
+    pub(crate) inode: u64,
+}
+
+impl FileIdentity {
+    #[cfg(unix)]
+    pub(crate) fn of(_path: &Path, metadata: &fs::Metadata) -> Self {
+        use std::os::unix::fs::MetadataExt;
+
+        Self { device: metadata.dev(), inode: metadata.ino() }
+    }
+
+    // FNV-1a, which unlike `DefaultHasher` is the same in every release, as checkpoints
+    // outlive the process.
+    #[cfg(not(unix))]
+    pub(crate) fn of(path: &Path, _metadata: &fs::Metadata) -> Self {
+        let path = path.to_string_lossy();
+        let hash = path.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3));
+        Self { device: 0, inode: hash }
+    }
+}
+
+// Follows a single file on its own thread, from its start.
+pub(crate) fn follow(path: PathBuf, name: &str, mut on_line: impl FnMut(&str) + Send + 'static) -> io::Result<()> {
+    let mut file = FollowedFile::open(path, 0)?;
+    thread::Builder::new().name(name.to_string()).spawn(move || loop {
+        file.read_lines(&mut on_line);
+        thread::sleep(POLL_INTERVAL);
+        file.reopen_if_rotated();
+    })?;
+    Ok(())
+}
+
+// `*` matches any run of characters within one path component, so
+// `/var/log/*/app-*.log` finds the logs in every directory below `/var/log`.
+pub(crate) fn glob(pattern: &str) -> Vec<PathBuf> {
+    let pattern = Path::new(pattern);
+    let mut matches = vec![PathBuf::new()];
+    for component in pattern.components() {
+        let component = component.as_os_str().to_string_lossy();
+        if !component.contains('*') {
+            matches.iter_mut().for_each(|path| path.push(component.as_ref()));
+            continue;
+        }
+        matches = matches.iter()
+            .filter_map(|dir| fs::read_dir(if dir.as_os_str().is_empty() { Path::new(".") } else { dir }).ok())
+            .flatten()
+            .filter_map(Result::ok)
+            .filter(|entry| crate::span_fields::glob_matches(&component, &entry.file_name().to_string_lossy()))
+            .map(|entry| entry.path())
+            .collect();
+    }
+    matches.retain(|path| path.is_file());
+    matches.sort();
+    matches
+}
+
+// An INFO record for a line read from outside tracing (log files, container output).
+pub(crate) fn line_record(time_unix_nano: u64, observed_time_unix_nano: u64, line: String, attributes: Vec<KeyValue>) -> LogRecord {
+    LogRecord {
+        time_unix_nano,
+        observed_time_unix_nano,
+        severity_number: 9,
+        severity_text: "INFO".to_string(),
+        body: Some(AnyValue { value: Some(StringValue(line)) }),
+        attributes,
+        dropped_attributes_count: 0,
+        flags: 0,
+        trace_id: vec![],
+        span_id: vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::FollowedFile;
+
+    #[test]
+    fn invalid_utf8_does_not_stop_reading() {
+        let path = std::env::temp_dir().join(format!("telescope-{}-follow.log", std::process::id()));
+        fs::write(&path, b"caf\xe9\r\nnext\npartial").unwrap();
+        let mut file = FollowedFile::open(path.clone(), 0).unwrap();
+        let mut lines = Vec::new();
+        file.read_lines(|line| lines.push(line.to_string()));
+        assert_eq!(lines, ["caf\u{fffd}", "next"]);
+        assert_eq!(file.position, 11);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,219 @@
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::net::{TcpStream, UdpSocket};
+
+use flate2::Compression;
+use flate2::write::{GzEncoder, ZlibEncoder};
+use tower::BoxError;
+
+use crate::decode::{body, display, DecodedBatch, push_json_string, string_attribute, syslog_severity};
+use crate::opentelclient::{AnyValue, KeyValue, LogRecord};
+use crate::opentelclient::any_value::Value;
+use crate::service::ExportRequest;
+use crate::sink::Sink;
+use crate::trace_context::hex;
+
+const CHUNK_MAGIC: [u8; 2] = [0x1e, 0x0f];
+const CHUNK_HEADER_LEN: usize = 12;
+const MAX_CHUNKS: usize = 128;
+
+/// Compression of GELF UDP datagrams. TCP messages are never compressed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GelfCompression {
+    None,
+    Gzip,
+    Zlib,
+}
+
+enum Connection {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+}
+
+/// Sends every record to Graylog as a GELF 1.1 message, for dual-writing while
+/// migrating. Attributes become additional `_` fields, the trace context `_trace_id`
+/// and `_span_id`.
+///
+/// Over UDP messages are compressed (gzip by default) and split into GELF chunks when
+/// they exceed the chunk size; over TCP they are null-byte delimited. Add it as a route
+/// with [`crate::TelescopeLayerBuilder::with_route_sink`] or as the destination of a
+/// second layer.
+pub struct GelfSink {
+    address: String,
+    tcp: bool,
+    connection: Option<Connection>,
+    compression: GelfCompression,
+    chunk_size: usize,
+    host: Option<String>,
+}
+
+impl GelfSink {
+    pub fn udp(address: impl Into<String>) -> Self {
+        Self::new(address.into(), false)
+    }
+
+    pub fn tcp(address: impl Into<String>) -> Self {
+        Self::new(address.into(), true)
+    }
+
+    fn new(address: String, tcp: bool) -> Self {
+        Self {
+            address,
+            tcp,
+            connection: None,
+            compression: GelfCompression::Gzip,
+            chunk_size: 8192,
+            host: None,
+        }
+    }
+
+    pub fn with_compression(mut self, compression: GelfCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Maximum UDP datagram size including the chunk header, 8192 by default. Use 1420
+    /// when the messages cross a WAN.
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(CHUNK_HEADER_LEN + 1);
+        self
+    }
+
+    /// `host` of every message. Defaults to the `host.name` resource attribute, then the
+    /// service name.
+    pub fn with_host(mut self, host: impl Into<String>) -> Self {
+        self.host = Some(host.into());
+        self
+    }
+
+    fn send(&mut self, message: String) -> io::Result<()> {
+        let mut connection = match self.connection.take() {
+            Some(connection) => connection,
+            None if self.tcp => Connection::Tcp(TcpStream::connect(&self.address)?),
+            None => {
+                let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+                socket.connect(&self.address)?;
+                Connection::Udp(socket)
+            }
+        };
+        let result = match &mut connection {
+            Connection::Tcp(stream) => {
+                let mut frame = message.into_bytes();
+                frame.push(0);
+                stream.write_all(&frame).and_then(|_| stream.flush())
+            }
+            Connection::Udp(socket) => compress(self.compression, message.as_bytes())
+                .and_then(|payload| send_chunked(socket, &payload, self.chunk_size)),
+        };
+        // Reconnect on the next message after a failure.
+        if result.is_ok() {
+            self.connection = Some(connection);
+        }
+        result
+    }
+
+    fn message(&self, resource: &[KeyValue], record: &LogRecord) -> String {
+        let host = self.host.as_deref()
+            .or_else(|| string_attribute(resource, "host.name"))
+            .or_else(|| string_attribute(resource, "service.name"))
+            .unwrap_or("unknown");
+        let body = body(record);
+        let mut json = String::from("{\"version\":\"1.1\",\"host\":");
+        push_json_string(&mut json, host);
+        json.push_str(",\"short_message\":");
+        push_json_string(&mut json, body.lines().next().unwrap_or_default());
+        if body.contains('\n') {
+            json.push_str(",\"full_message\":");
+            push_json_string(&mut json, &body);
+        }
+        let _ = write!(json, ",\"timestamp\":{}.{:06}", record.time_unix_nano / 1_000_000_000, record.time_unix_nano % 1_000_000_000 / 1000);
+        let _ = write!(json, ",\"level\":{}", syslog_severity(record.severity_number));
+        if let Some(service_name) = string_attribute(resource, "service.name") {
+            json.push_str(",\"_service_name\":");
+            push_json_string(&mut json, service_name);
+        }
+        if !record.trace_id.is_empty() {
+            let _ = write!(json, ",\"_trace_id\":\"{}\",\"_span_id\":\"{}\"", hex(&record.trace_id), hex(&record.span_id));
+        }
+        for attribute in &record.attributes {
+            if let (Some(name), Some(value)) = (field_name(&attribute.key), &attribute.value) {
+                json.push(',');
+                push_json_string(&mut json, &name);
+                json.push(':');
+                push_field_value(&mut json, value);
+            }
+        }
+        json.push('}');
+        json
+    }
+}
+
+impl Sink for GelfSink {
+    fn export(&mut self, request: &ExportRequest) -> Result<(), BoxError> {
+        let batch = DecodedBatch::decode(&request.payload)?;
+        for (resource, record) in batch.records() {
+            let message = self.message(resource, record);
+            self.send(message)?;
+        }
+        Ok(())
+    }
+}
+
+fn compress(compression: GelfCompression, message: &[u8]) -> io::Result<Vec<u8>> {
+    match compression {
+        GelfCompression::None => Ok(message.to_vec()),
+        GelfCompression::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(message)?;
+            encoder.finish()
+        }
+        GelfCompression::Zlib => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(message)?;
+            encoder.finish()
+        }
+    }
+}
+
+fn send_chunked(socket: &UdpSocket, payload: &[u8], chunk_size: usize) -> io::Result<()> {
+    if payload.len() <= chunk_size {
+        return socket.send(payload).map(|_| ());
+    }
+    let chunks: Vec<&[u8]> = payload.chunks(chunk_size - CHUNK_HEADER_LEN).collect();
+    if chunks.len() > MAX_CHUNKS {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "GELF message exceeds 128 chunks"));
+    }
+    let message_id: [u8; 8] = rand::random();
+    let mut datagram = Vec::with_capacity(chunk_size);
+    for (sequence, chunk) in chunks.iter().enumerate() {
+        datagram.clear();
+        datagram.extend_from_slice(&CHUNK_MAGIC);
+        datagram.extend_from_slice(&message_id);
+        datagram.push(sequence as u8);
+        datagram.push(chunks.len() as u8);
+        datagram.extend_from_slice(chunk);
+        socket.send(&datagram)?;
+    }
+    Ok(())
+}
+
+// Additional field names match `^[\w.\-]*$` behind the leading underscore; `_id` is
+// reserved by Graylog.
+fn field_name(key: &str) -> Option<String> {
+    let name: String = key.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-') { c } else { '_' })
+        .collect();
+    (!name.is_empty() && name != "id").then(|| format!("_{name}"))
+}
+
+fn push_field_value(json: &mut String, value: &AnyValue) {
+    match &value.value {
+        Some(Value::IntValue(value)) => {
+            let _ = write!(json, "{value}");
+        }
+        Some(Value::DoubleValue(value)) if value.is_finite() => {
+            let _ = write!(json, "{value}");
+        }
+        _ => push_json_string(json, &display(value)),
+    }
+}
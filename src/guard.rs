@@ -0,0 +1,65 @@
+use crate::opentelclient::LogRecord;
+
+// Largest OTLP SeverityNumber (FATAL4).
+const MAX_SEVERITY_NUMBER: i32 = 24;
+
+// What processors may not break in a record, checked against the record as it came in:
+// the observed time stays what the crate set, a timestamp isn't cleared, ids keep their
+// OTLP lengths and the severity stays a valid SeverityNumber. Strict processors are
+// checked after every one and panic, so a misbehaving processor fails its tests;
+// otherwise the record is repaired once the chain is through.
+pub(crate) struct RecordGuard {
+    time_unix_nano: u64,
+    observed_time_unix_nano: u64,
+    severity_number: i32,
+}
+
+impl RecordGuard {
+    pub(crate) fn new(record: &LogRecord) -> Self {
+        Self {
+            time_unix_nano: record.time_unix_nano,
+            observed_time_unix_nano: record.observed_time_unix_nano,
+            severity_number: record.severity_number,
+        }
+    }
+
+    pub(crate) fn check(&self, record: &LogRecord) -> Result<(), String> {
+        if record.observed_time_unix_nano != self.observed_time_unix_nano {
+            return Err("observed time was changed".to_string());
+        }
+        if record.time_unix_nano == 0 && self.time_unix_nano != 0 {
+            return Err("timestamp was cleared".to_string());
+        }
+        if !matches!(record.trace_id.len(), 0 | 16) {
+            return Err(format!("trace id is {} bytes instead of 16", record.trace_id.len()));
+        }
+        if !matches!(record.span_id.len(), 0 | 8) {
+            return Err(format!("span id is {} bytes instead of 8", record.span_id.len()));
+        }
+        if !(0..=MAX_SEVERITY_NUMBER).contains(&record.severity_number) {
+            return Err(format!("severity number {} is out of range", record.severity_number));
+        }
+        Ok(())
+    }
+
+    // Puts back what was there before the processors ran; ids of the wrong length can't
+    // be fixed and are dropped.
+    pub(crate) fn repair(&self, record: &mut LogRecord) {
+        if self.check(record).is_ok() {
+            return;
+        }
+        record.observed_time_unix_nano = self.observed_time_unix_nano;
+        if record.time_unix_nano == 0 {
+            record.time_unix_nano = self.time_unix_nano;
+        }
+        if !matches!(record.trace_id.len(), 0 | 16) {
+            record.trace_id.clear();
+        }
+        if !matches!(record.span_id.len(), 0 | 8) {
+            record.span_id.clear();
+        }
+        if !(0..=MAX_SEVERITY_NUMBER).contains(&record.severity_number) {
+            record.severity_number = self.severity_number;
+        }
+    }
+}
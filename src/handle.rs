@@ -0,0 +1,182 @@
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bytes::Bytes;
+use tonic::metadata::MetadataMap;
+use tower::{BoxError, ServiceExt};
+use tracing::level_filters::LevelFilter;
+
+use crate::capabilities::{Capabilities, ServerCapabilities};
+use crate::clock::Clock;
+use crate::config::TelescopeConfig;
+use crate::decode::display;
+use crate::disk_queue::{DiskQueue, DiskQueueStats};
+use crate::filter::{DynamicFilter, FilterHandle};
+use crate::opentelclient::any_value::Value::StringValue;
+use crate::opentelclient::LogRecord;
+use crate::resource::SharedResource;
+use crate::routing::RecordSender;
+use crate::service::{BoxExportService, ExportRequest};
+use crate::shutdown::{Shutdown, ShutdownReport};
+use crate::stats::{ExportStats, TelescopeStats};
+use crate::support::{RecentRecords, SupportBundle};
+
+/// Cheap, cloneable handle for changing a running layer.
+#[derive(Clone)]
+pub struct TelescopeHandle {
+    pub(crate) resource: Arc<SharedResource>,
+    pub(crate) filter: Arc<DynamicFilter>,
+    pub(crate) stats: Arc<ExportStats>,
+    pub(crate) disk_queue: Option<Arc<DiskQueue>>,
+    pub(crate) sender: RecordSender,
+    pub(crate) clock: Arc<dyn Clock>,
+    pub(crate) shutdown: Arc<Shutdown>,
+    pub(crate) capabilities: Arc<Capabilities>,
+    // What the layer was built with; the resource and levels are filled in when asked.
+    pub(crate) config: Arc<TelescopeConfig>,
+    pub(crate) recent: Arc<RecentRecords>,
+    // The main destination with the export layers, but without fallback, disk queue or
+    // retries, so a failed probe is reported as such.
+    pub(crate) probe: Arc<Mutex<BoxExportService>>,
+}
+
+impl TelescopeHandle {
+    /// Set (or replace) a resource attribute such as `deployment.environment`. Batches
+    /// exported from now on carry the new value; `service.name` can be replaced as well.
+    pub fn set_resource_attribute(&self, key: impl Into<String>, value: impl Into<String>) {
+        self.resource.set(key.into(), StringValue(value.into()));
+    }
+
+    pub fn remove_resource_attribute(&self, key: &str) {
+        self.resource.remove(key);
+    }
+
+    /// Replace the schema url (`ResourceLogs.schema_url`) of batches exported from now
+    /// on; an empty string leaves it out.
+    pub fn set_schema_url(&self, schema_url: impl Into<String>) {
+        self.resource.set_schema_url(schema_url.into());
+    }
+
+    /// Export records of `target` (and its children) from `level` up, e.g.
+    /// `set_target_level("my_app::payments", Level::DEBUG)` while debugging an incident.
+    /// The most specific target wins. Records still have to pass any filter installed in
+    /// front of the layer.
+    pub fn set_target_level(&self, target: impl Into<String>, level: impl Into<LevelFilter>) {
+        self.filter.set(target.into(), level.into());
+    }
+
+    /// Go back to the default level for `target`.
+    pub fn reset_target_level(&self, target: &str) {
+        self.filter.remove(target);
+    }
+
+    /// A handle that can only change levels, to pass to code that shouldn't get to shut
+    /// the layer down or touch its resource.
+    pub fn filter_handle(&self) -> FilterHandle {
+        FilterHandle { filter: self.filter.clone() }
+    }
+
+    /// Connection state and export counters of the main endpoint.
+    pub fn stats(&self) -> TelescopeStats {
+        self.stats.snapshot()
+    }
+
+    /// The configuration the layer runs with right now, including resource attributes
+    /// and levels changed through this handle. See [`TelescopeConfig::to_json`].
+    pub fn current_config(&self) -> TelescopeConfig {
+        let resource = self.resource.current();
+        TelescopeConfig {
+            resource_attributes: resource.attributes.iter()
+                .map(|attribute| (attribute.key.clone(), attribute.value.as_ref().map(display).unwrap_or_default()))
+                .collect(),
+            schema_url: resource.schema_url.clone(),
+            level: self.filter.default_level(),
+            target_levels: self.filter.target_levels(),
+            ..TelescopeConfig::clone(&self.config)
+        }
+    }
+
+    /// Write a zip to attach to bug reports: the configuration (see
+    /// [`Self::current_config`]), stats, server capabilities and disk queue state, recent
+    /// connection events and runtime metrics, and a few records of every recent batch.
+    /// Those records are redacted down to their timestamps, severity, trace context,
+    /// attribute keys and value types, so no logged values end up in the bundle.
+    pub fn support_bundle(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let bundle = SupportBundle {
+            config: self.current_config().to_json(),
+            stats: self.stats(),
+            capabilities: self.server_capabilities(),
+            disk_queue: self.disk_queue_stats(),
+        };
+        bundle.write(path.as_ref(), &self.recent)
+    }
+
+    /// What the main endpoint announced about itself so far.
+    pub fn server_capabilities(&self) -> ServerCapabilities {
+        self.capabilities.snapshot()
+    }
+
+    /// Batches waiting in the disk queue, or `None` without
+    /// [`crate::TelescopeLayerBuilder::with_disk_queue`].
+    pub fn disk_queue_stats(&self) -> Option<io::Result<DiskQueueStats>> {
+        self.disk_queue.as_ref().map(|queue| queue.stats())
+    }
+
+    /// Drop everything in the disk queue, including corrupted batches, and return the
+    /// number of batches removed.
+    pub fn purge_disk_queue(&self) -> io::Result<u64> {
+        self.disk_queue.as_ref().map_or(Ok(0), |queue| queue.purge())
+    }
+
+    /// Export historical records, e.g. when migrating from another log system, keeping
+    /// their `time_unix_nano`. Records skip the level filter but still go through
+    /// processors, the record filter and routes (as target `backfill`). At most
+    /// `records_per_sec` records are queued per second so live logging keeps flowing;
+    /// blocks until every record was handed to the pipeline and returns how many were.
+    pub fn backfill(&self, records: impl IntoIterator<Item=LogRecord>, records_per_sec: u32) -> usize {
+        let interval = Duration::from_secs(1) / records_per_sec.max(1);
+        let mut next = self.clock.now();
+        let mut queued = 0;
+        for mut record in records {
+            let now = self.clock.now();
+            if now < next {
+                self.clock.sleep(next - now);
+            }
+            next = next.max(now) + interval;
+            if record.observed_time_unix_nano == 0 {
+                record.observed_time_unix_nano = self.clock.now_unix_nano();
+            }
+            self.sender.send("backfill", record);
+            queued += 1;
+        }
+        queued
+    }
+
+    /// Flush everything queued and stop exporting, within `deadline` (e.g. what is left
+    /// of the container's termination grace period). Per-request timeouts and retries
+    /// are cut short so the final flush never runs past it. Blocks until every exporter
+    /// finished and reports what happened to the records it held. Records logged
+    /// afterwards are dropped.
+    pub fn shutdown(&self, deadline: Duration) -> ShutdownReport {
+        let started = self.clock.now();
+        // Exporters check the deadline between attempts; give them a moment past it to
+        // report.
+        let mut report = self.shutdown.shutdown(started + deadline, deadline + Duration::from_millis(500), &self.sender.in_flight);
+        report.elapsed = self.clock.now().saturating_duration_since(started);
+        report
+    }
+
+    /// Connect to the main endpoint and send it one empty export, waiting at most
+    /// `timeout`. Services that must not take traffic before logging works can await
+    /// this during startup.
+    pub async fn ready(&self, timeout: Duration) -> Result<(), BoxError> {
+        let probe = self.probe.lock().unwrap().clone();
+        let request = ExportRequest { payload: Bytes::new(), metadata: MetadataMap::new() };
+        match tokio::time::timeout(timeout, probe.oneshot(request)).await {
+            Ok(result) => result.map(|_| ()),
+            Err(_) => Err(format!("endpoint not ready within {timeout:?}").into()),
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use tonic::{Request, Response, Status};
+
+use crate::auth::AuthChannel;
+use crate::export::ExportClient;
+use crate::opentelclient::ExportLogsServiceResponse;
+
+pub(crate) const BATCH_ID_HEADER: &str = "x-telescope-batch-id";
+
+// A second endpoint that gets a copy of the batch when the primary is slow to answer.
+// Both copies carry the same batch id header so the server can drop the duplicate.
+#[derive(Clone)]
+pub(crate) struct Hedge {
+    pub(crate) client: ExportClient<AuthChannel>,
+    pub(crate) after: Duration,
+}
+
+impl Hedge {
+    pub(crate) async fn export<F>(&mut self, primary: &mut ExportClient<AuthChannel>, request: F) -> Result<Response<ExportLogsServiceResponse>, Status>
+        where F: Fn() -> Request<Bytes>
+    {
+        let primary = primary.export(request());
+        tokio::pin!(primary);
+        tokio::select! {
+            result = &mut primary => return result,
+            _ = tokio::time::sleep(self.after) => {}
+        }
+
+        let secondary = self.client.export(request());
+        tokio::pin!(secondary);
+        tokio::select! {
+            result = &mut primary => match result {
+                Ok(response) => Ok(response),
+                Err(_) => secondary.await,
+            },
+            result = &mut secondary => match result {
+                Ok(response) => Ok(response),
+                Err(_) => primary.await,
+            },
+        }
+    }
+}
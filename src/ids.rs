@@ -0,0 +1,75 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::clock::{Clock, SystemClock};
+
+/// Source of trace and span ids for contexts the layer synthesizes itself (generated
+/// root trace ids, export RPC traces). Ids parsed from an incoming `traceparent` are
+/// never replaced.
+pub trait IdGenerator: Send + Sync + 'static {
+    fn trace_id(&self) -> [u8; 16];
+
+    fn span_id(&self) -> [u8; 8];
+}
+
+/// Uniformly random ids, the default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RandomIdGenerator;
+
+impl IdGenerator for RandomIdGenerator {
+    fn trace_id(&self) -> [u8; 16] {
+        rand::random()
+    }
+
+    fn span_id(&self) -> [u8; 8] {
+        rand::random()
+    }
+}
+
+/// ULID-layout trace ids: a 48 bit millisecond timestamp followed by 80 random bits, so
+/// ids sort by creation time and land close together in the server's index. Span ids
+/// stay random.
+#[derive(Clone, Debug)]
+pub struct UlidIdGenerator<C = SystemClock> {
+    clock: C,
+}
+
+impl<C: Clock> UlidIdGenerator<C> {
+    pub fn new(clock: C) -> Self {
+        Self { clock }
+    }
+}
+
+impl Default for UlidIdGenerator {
+    fn default() -> Self {
+        Self::new(SystemClock)
+    }
+}
+
+impl<C: Clock> IdGenerator for UlidIdGenerator<C> {
+    fn trace_id(&self) -> [u8; 16] {
+        let millis = self.clock.now_unix_nano() / 1_000_000;
+        let mut id: [u8; 16] = rand::random();
+        id[..6].copy_from_slice(&millis.to_be_bytes()[2..]);
+        id
+    }
+
+    fn span_id(&self) -> [u8; 8] {
+        rand::random()
+    }
+}
+
+/// Counts up from 1, for tests that assert on ids.
+#[derive(Debug, Default)]
+pub struct SequentialIdGenerator {
+    next: AtomicU64,
+}
+
+impl IdGenerator for SequentialIdGenerator {
+    fn trace_id(&self) -> [u8; 16] {
+        (self.next.fetch_add(1, Ordering::Relaxed) as u128 + 1).to_be_bytes()
+    }
+
+    fn span_id(&self) -> [u8; 8] {
+        (self.next.fetch_add(1, Ordering::Relaxed) + 1).to_be_bytes()
+    }
+}
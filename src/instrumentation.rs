@@ -0,0 +1,95 @@
+// Internal counters, reported through the `metrics` facade when the `metrics` feature
+// is enabled so they end up in whatever recorder the application installed. Without
+// the feature these compile to nothing.
+
+#[cfg(feature = "metrics")]
+pub(crate) fn batch_exported(records: usize, bytes: usize) {
+    metrics::counter!("telescope_batches_exported_total").increment(1);
+    metrics::counter!("telescope_records_exported_total").increment(records as u64);
+    metrics::counter!("telescope_bytes_exported_total").increment(bytes as u64);
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn export_failed() {
+    metrics::counter!("telescope_export_failures_total").increment(1);
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn record_suppressed() {
+    metrics::counter!("telescope_records_suppressed_total").increment(1);
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn record_shed() {
+    metrics::counter!("telescope_records_shed_total").increment(1);
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn record_sampled_out() {
+    metrics::counter!("telescope_records_sampled_out_total").increment(1);
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn records_dropped(count: usize) {
+    metrics::counter!("telescope_records_dropped_total").increment(count as u64);
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn record_deduplicated() {
+    metrics::counter!("telescope_records_deduplicated_total").increment(1);
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn disk_queue_batch_corrupted() {
+    metrics::counter!("telescope_disk_queue_corrupted_total").increment(1);
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn memory_pressure_flush() {
+    metrics::counter!("telescope_memory_pressure_flushes_total").increment(1);
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn timestamp_clamped() {
+    metrics::counter!("telescope_timestamps_clamped_total").increment(1);
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn attributes_dropped(count: u32) {
+    if count > 0 {
+        metrics::counter!("telescope_attributes_dropped_total").increment(count as u64);
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn batch_exported(_records: usize, _bytes: usize) {}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn export_failed() {}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn record_suppressed() {}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn record_shed() {}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn record_sampled_out() {}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn records_dropped(_count: usize) {}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn record_deduplicated() {}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn disk_queue_batch_corrupted() {}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn memory_pressure_flush() {}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn timestamp_clamped() {}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn attributes_dropped(_count: u32) {}
@@ -0,0 +1,44 @@
+use crate::clock::Clock;
+use crate::opentelclient::{AnyValue, KeyValue, LogRecord};
+use crate::opentelclient::any_value::Value;
+use crate::opentelclient::any_value::Value::{BoolValue, IntValue, StringValue};
+
+// Every record the pipeline produces about itself carries this attribute, so it can be
+// told apart from (and filtered away from) application logs on the server.
+pub(crate) const INTERNAL_KEY: &str = "telescope.internal";
+
+pub(crate) fn attribute(key: &str, value: Value) -> KeyValue {
+    KeyValue {
+        key: key.to_string(),
+        value: Some(AnyValue { value: Some(value) }),
+    }
+}
+
+pub(crate) fn internal_record(clock: &dyn Clock, body: String, mut attributes: Vec<KeyValue>) -> LogRecord {
+    let unix_nano = clock.now_unix_nano();
+    attributes.push(attribute(INTERNAL_KEY, BoolValue(true)));
+    LogRecord {
+        time_unix_nano: unix_nano,
+        observed_time_unix_nano: unix_nano,
+        severity_number: 9,
+        severity_text: "INFO".to_string(),
+        body: Some(AnyValue { value: Some(StringValue(body)) }),
+        attributes,
+        dropped_attributes_count: 0,
+        flags: 0,
+        trace_id: vec![],
+        span_id: vec![],
+    }
+}
+
+pub(crate) fn batch_summary(clock: &dyn Clock, records: &[LogRecord]) -> LogRecord {
+    let min_time = records.iter().map(|record| record.time_unix_nano).min().unwrap_or_default();
+    let max_time = records.iter().map(|record| record.time_unix_nano).max().unwrap_or_default();
+    let dropped_attributes: u64 = records.iter().map(|record| record.dropped_attributes_count as u64).sum();
+    internal_record(clock, "telescope batch summary".to_string(), vec![
+        attribute("telescope.batch.records", IntValue(records.len() as i64)),
+        attribute("telescope.batch.min_time_unix_nano", IntValue(min_time as i64)),
+        attribute("telescope.batch.max_time_unix_nano", IntValue(max_time as i64)),
+        attribute("telescope.batch.dropped_attributes", IntValue(dropped_attributes as i64)),
+    ])
+}
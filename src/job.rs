@@ -0,0 +1,93 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+
+use crate::context::TelescopeContext;
+
+/// Stable `job.id` / `job.attempt` correlation for long-running jobs. Every record
+/// logged by a future or stream wrapped by the job carries both attributes; the attempt
+/// is bumped by [`JobContext::retry`] so retries of the same job stay grouped.
+#[derive(Clone, Debug)]
+pub struct JobContext {
+    id: String,
+    attempt: Arc<AtomicU32>,
+    base: TelescopeContext,
+}
+
+impl JobContext {
+    /// Starts at attempt 1 and extends the context currently in scope, if any.
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            attempt: Arc::new(AtomicU32::new(1)),
+            base: TelescopeContext::current().unwrap_or_default(),
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn attempt(&self) -> u32 {
+        self.attempt.load(Ordering::Relaxed)
+    }
+
+    pub fn next_attempt(&self) -> u32 {
+        self.attempt.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    pub fn context(&self) -> TelescopeContext {
+        self.base.clone()
+            .with("job.id", self.id.clone())
+            .with("job.attempt", self.attempt().to_string())
+    }
+
+    pub async fn instrument<F: Future>(&self, future: F) -> F::Output {
+        self.context().scope(future).await
+    }
+
+    /// Wrap a stream so every poll runs with the job context, picking up the current
+    /// attempt each time.
+    pub fn instrument_stream<S: Stream>(&self, stream: S) -> JobStream<S> {
+        JobStream {
+            inner: Box::pin(stream),
+            job: self.clone(),
+        }
+    }
+
+    /// Run `attempt` until it succeeds or `max_attempts` is reached, bumping
+    /// `job.attempt` before each retry.
+    pub async fn retry<F, Fut, T, E>(&self, max_attempts: u32, mut attempt: F) -> Result<T, E>
+        where
+            F: FnMut(u32) -> Fut,
+            Fut: Future<Output=Result<T, E>>,
+    {
+        loop {
+            let current = self.attempt();
+            match self.instrument(attempt(current)).await {
+                Err(_) if current < max_attempts => {
+                    self.next_attempt();
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+pub struct JobStream<S> {
+    inner: Pin<Box<S>>,
+    job: JobContext,
+}
+
+impl<S: Stream> Stream for JobStream<S> {
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let context = self.job.context();
+        context.in_scope(|| self.inner.as_mut().poll_next(cx))
+    }
+}
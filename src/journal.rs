@@ -0,0 +1,110 @@
+use std::io;
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+
+use tower::BoxError;
+
+use crate::decode::{body, display, DecodedBatch, string_attribute, syslog_severity};
+use crate::opentelclient::LogRecord;
+use crate::service::ExportRequest;
+use crate::sink::Sink;
+use crate::trace_context::hex;
+
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Writes every record to the systemd journal over its native protocol, with the body
+/// as `MESSAGE`, the severity as `PRIORITY` and each attribute as its own field
+/// (`http.status` becomes `HTTP_STATUS`). Meant as a fallback sink, so `journalctl`
+/// still has the logs while the collector is unreachable.
+///
+/// Records are sent as single datagrams; one that exceeds the socket's datagram size
+/// limit fails the batch.
+pub struct JournalSink {
+    path: PathBuf,
+    socket: Option<UnixDatagram>,
+}
+
+impl JournalSink {
+    pub fn new() -> Self {
+        Self::with_socket(JOURNAL_SOCKET)
+    }
+
+    /// Send to a journal socket other than `/run/systemd/journal/socket`.
+    pub fn with_socket(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), socket: None }
+    }
+
+    fn socket(&mut self) -> io::Result<&UnixDatagram> {
+        let socket = match self.socket.take() {
+            Some(socket) => socket,
+            None => {
+                let socket = UnixDatagram::unbound()?;
+                socket.connect(&self.path)?;
+                socket
+            }
+        };
+        Ok(self.socket.insert(socket))
+    }
+}
+
+impl Default for JournalSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sink for JournalSink {
+    fn export(&mut self, request: &ExportRequest) -> Result<(), BoxError> {
+        let batch = DecodedBatch::decode(&request.payload)?;
+        for (resource, record) in batch.records() {
+            let entry = entry(string_attribute(resource, "service.name"), record);
+            if let Err(error) = self.socket()?.send(&entry) {
+                self.socket = None;
+                return Err(error.into());
+            }
+        }
+        Ok(())
+    }
+}
+
+fn entry(service_name: Option<&str>, record: &LogRecord) -> Vec<u8> {
+    let mut entry = Vec::new();
+    push_field(&mut entry, "MESSAGE", &body(record));
+    push_field(&mut entry, "PRIORITY", &syslog_severity(record.severity_number).to_string());
+    if let Some(service_name) = service_name {
+        push_field(&mut entry, "SYSLOG_IDENTIFIER", service_name);
+    }
+    if !record.trace_id.is_empty() {
+        push_field(&mut entry, "TRACE_ID", &hex(&record.trace_id));
+        push_field(&mut entry, "SPAN_ID", &hex(&record.span_id));
+    }
+    for attribute in &record.attributes {
+        if let (Some(name), Some(value)) = (field_name(&attribute.key), &attribute.value) {
+            push_field(&mut entry, &name, &display(value));
+        }
+    }
+    entry
+}
+
+// Journal field names are upper case letters, digits and underscores, must not start
+// with a digit and may not start with an underscore (those are trusted fields).
+fn field_name(key: &str) -> Option<String> {
+    let name: String = key.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .skip_while(|c| *c == '_' || c.is_ascii_digit())
+        .take(64)
+        .collect();
+    (!name.is_empty()).then_some(name)
+}
+
+fn push_field(entry: &mut Vec<u8>, name: &str, value: &str) {
+    entry.extend_from_slice(name.as_bytes());
+    if value.contains('\n') {
+        entry.push(b'\n');
+        entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        entry.push(b'=');
+    }
+    entry.extend_from_slice(value.as_bytes());
+    entry.push(b'\n');
+}
@@ -1,148 +1,666 @@
-use std::collections::HashMap;
-use std::sync::mpsc::{Receiver, sync_channel, SyncSender};
-use std::thread;
-use std::time::{Duration, Instant, SystemTime};
-
-use tonic::Request;
-use tonic::transport::Channel;
-use tracing::{Event, Level, Subscriber};
+//! A `tracing` layer exporting events as OpenTelemetry (OTLP) logs over gRPC, see
+//! [`TelescopeLayerBuilder`].
+//!
+//! # The `release-max-level-info` feature
+//!
+//! This feature turns on tracing's `release_max_level_info`, which sets the static max
+//! level of the whole application, not just of this layer: in release builds, DEBUG and
+//! TRACE callsites are compiled out of every crate in the dependency graph, so no other
+//! layer (a console `fmt` layer, say) sees them either, and no runtime filter can bring
+//! them back. Cargo unifies features, so it applies as soon as any crate in the build
+//! enables it. Leave it off in libraries and enable it only in a binary that means to
+//! drop those levels everywhere.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tonic::transport::{Channel, Endpoint};
+use tracing::{Event, Level, Metadata, Subscriber};
 use tracing::field::Field;
+use tracing::level_filters::LevelFilter;
+use tracing::span::{Attributes, Id, Record};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::opentelclient::{AnyValue, KeyValue, LogRecord};
+use crate::attributes::AttributeLimits;
+use crate::opentelclient::any_value::Value::{BoolValue, DoubleValue, IntValue, StringValue};
+use crate::trace_context::{TraceContext, TraceparentVisitor};
 
-use crate::opentelclient::{AnyValue, ExportLogsServiceRequest, KeyValue, LogRecord, Resource, ResourceLogs, ScopeLogs};
-use crate::opentelclient::any_value::Value::{IntValue, StringValue};
-use crate::opentelclient::logs_service_client::LogsServiceClient;
+pub use crate::builder::TelescopeLayerBuilder;
+pub use crate::capabilities::ServerCapabilities;
+pub use crate::capture::{CapturedEvent, CapturedValue};
+pub use crate::config::{TelescopeConfig, TlsSettings};
+pub use crate::clock::{Clock, CoarseClock, ManualClock, MonotonicClock, SystemClock};
+pub use crate::container::{ContainerLogBridge, ContainerLogError, ContainerLogFormat};
+pub use crate::context::TelescopeContext;
+pub use crate::disk_queue::DiskQueueStats;
+pub use crate::error::{ConfigError, TelescopeError};
+pub use crate::expr::{FilterParseError, RecordFilter};
+pub use crate::file::FileSink;
+pub use crate::file_source::FileTailSource;
+pub use crate::filter::FilterHandle;
+#[cfg(feature = "gelf")]
+pub use crate::gelf::{GelfCompression, GelfSink};
+pub use crate::handle::TelescopeHandle;
+pub use crate::ids::{IdGenerator, RandomIdGenerator, SequentialIdGenerator, UlidIdGenerator};
+pub use crate::job::{JobContext, JobStream};
+#[cfg(target_os = "linux")]
+pub use crate::journal::JournalSink;
+#[cfg(feature = "loki")]
+pub use crate::loki::LokiSink;
+#[cfg(feature = "metrics")]
+pub use crate::metric_rules::MetricRule;
+pub use crate::normalize::KeyNormalizer;
+pub use crate::pipeline::{Pipeline, PipelineBuilder, Processor, Source};
+pub use crate::queue::OverflowPolicy;
+pub use crate::resolver::Resolver;
+pub use crate::retry::{RetryExhausted, RetryPolicy};
+pub use crate::sender::TelescopeSender;
+pub use crate::service::{BoxExportService, ExportFailure, ExportRequest};
+pub use crate::shutdown::ShutdownReport;
+pub use crate::sink::Sink;
+pub use crate::span_fields::SpanFieldConflict;
+pub use crate::span_metrics::SpanMetrics;
+pub use crate::stats::{ConnectionState, IngestionQuota, TelescopeStats};
+pub use crate::sticky::StickyDebug;
+pub use crate::tail::TailBuffering;
+pub use crate::transport::TelescopeTransport;
+#[cfg(feature = "syslog")]
+pub use crate::syslog::SyslogSink;
+#[cfg(feature = "syslog-listener")]
+pub use crate::syslog_listener::SyslogListener;
 
-mod opentelclient;
+mod admission;
+mod attributes;
+mod auth;
+mod batch;
+mod builder;
+mod capabilities;
+mod capture;
+mod config;
+mod clock;
+mod container;
+mod context;
+mod decode;
+mod dedup;
+mod dictionary;
+mod disk_queue;
+mod early;
+mod encode_buffer;
+mod endpoint;
+mod envelope;
+mod error;
+mod export;
+mod exporter;
+mod exemption;
+mod expr;
+mod file;
+mod file_source;
+mod flight_recorder;
+mod follow;
+mod filter;
+#[cfg(feature = "gelf")]
+mod gelf;
+mod guard;
+mod handle;
+mod hedge;
+mod ids;
+mod instrumentation;
+mod internal;
+mod job;
+#[cfg(target_os = "linux")]
+mod journal;
+mod links;
+#[cfg(feature = "loki")]
+mod loki;
+mod memory_pressure;
+#[cfg(feature = "metrics")]
+mod metric_rules;
+mod normalize;
+#[allow(clippy::enum_variant_names)]
+pub mod opentelclient;
+mod pacing;
+mod pipeline;
+mod queue;
+mod quota;
+mod resource;
+mod resolver;
+mod retry;
+mod routing;
+mod runtime_metrics;
+mod sender;
+mod sequence;
+mod server_sampling;
+mod service;
+mod shutdown;
+mod sink;
+mod span_fields;
+mod span_metrics;
+mod stats;
+mod support;
+mod sticky;
+mod tail;
+#[cfg(feature = "syslog")]
+mod syslog;
+#[cfg(feature = "syslog-listener")]
+mod syslog_listener;
+mod timestamp;
+mod trace_context;
+mod transport;
+mod usage;
 
 pub struct TelescopeLayer {
-    tx: SyncSender<LogRecord>,
+    sender: routing::RecordSender,
+    attribute_limits: AttributeLimits,
+    generate_trace_ids: bool,
+    message_in_attributes: bool,
+    timestamp_sequence: Option<sequence::TimestampSequence>,
+    logs: bool,
+    clock: Arc<dyn Clock>,
+    id_generator: Arc<dyn IdGenerator>,
+    quotas: quota::Quotas,
+    handle: TelescopeHandle,
+    filter: Arc<filter::DynamicFilter>,
+    load_shedding: Option<admission::LoadShedding>,
+    server_sampling: Option<Arc<capabilities::Capabilities>>,
+    in_flight: admission::InFlight,
+    span_metrics: Option<SpanMetrics>,
+    span_stats: Arc<span_metrics::SpanStats>,
+    span_fields: Option<span_fields::SpanFieldRules>,
+    sticky_debug: Option<sticky::StickyDebugState>,
+    flight_recorder: Option<usize>,
+    tail_buffering: Option<TailBuffering>,
+    dedup: Option<Arc<dedup::Deduplicator>>,
+    key_normalizer: Option<KeyNormalizer>,
+    exemptions: exemption::SamplingExemptions,
+    capture: Option<capture::CaptureWriter>,
+    owner: Arc<sender::Owner>,
 }
 
 impl TelescopeLayer {
+    /// Like [`Self::try_new`].
+    ///
+    /// # Panics
+    ///
+    /// If `url` is invalid or the endpoint can't be connected to.
     pub async fn new(service_name: String, url: String) -> Self {
-        let url_leak = Box::leak(url.into_boxed_str());
-        let (tx, rx) = sync_channel(1000);
-
-        start_logging_thread(rx, LogsServiceClient::new(
-            Channel::from_static(url_leak)
-                .connect()
-                .await
-                .unwrap()), service_name.clone());
-        Self {
-            tx
+        Self::builder(service_name, url).build().await
+    }
+
+    /// Connect to the collector at `url`, failing with a [`TelescopeError`] instead of
+    /// panicking if the url is invalid or the connection can't be made.
+    pub async fn try_new(service_name: String, url: String) -> Result<Self, TelescopeError> {
+        Self::builder(service_name, url).try_build().await
+    }
+
+    /// Create the layer without an async runtime and without waiting for the collector,
+    /// which is connected in the background; see
+    /// [`TelescopeLayerBuilder::try_build_lazy`].
+    pub fn new_lazy(service_name: String, url: String) -> Result<Self, TelescopeError> {
+        Self::builder(service_name, url).try_build_lazy()
+    }
+
+    /// Export over a channel built by the caller (custom connector, UDS, in-memory
+    /// transport, tower middleware) instead of connecting to a URL.
+    ///
+    /// # Panics
+    ///
+    /// If the exporter can't be started; see [`Self::try_with_channel`].
+    pub async fn with_channel(service_name: String, channel: Channel) -> Self {
+        Self::builder_with_channel(service_name, channel).build().await
+    }
+
+    /// Like [`Self::with_channel`], returning an error instead of panicking.
+    pub async fn try_with_channel(service_name: String, channel: Channel) -> Result<Self, TelescopeError> {
+        Self::builder_with_channel(service_name, channel).try_build().await
+    }
+
+    /// `url` is accepted the way collectors spell endpoints: `http://collector:4317`,
+    /// `grpc://collector:4317` or just `collector:4317` are plaintext, `https://` and
+    /// `grpcs://` use TLS. TLS needs the `tls` feature; without it such endpoints are
+    /// rejected by [`TelescopeLayerBuilder::validate`].
+    pub fn builder(service_name: String, url: String) -> TelescopeLayerBuilder {
+        TelescopeLayerBuilder::new(service_name, url)
+    }
+
+    pub fn builder_with_channel(service_name: String, channel: Channel) -> TelescopeLayerBuilder {
+        TelescopeLayerBuilder::with_channel(service_name, channel)
+    }
+
+    /// Export to an endpoint configured by the caller (timeouts, keep-alive, TLS, user
+    /// agent, ...), which the layer connects like an url: eagerly in
+    /// [`TelescopeLayerBuilder::try_build`], in the background otherwise. Unlike
+    /// [`Self::builder_with_channel`] this needs no async runtime to set up.
+    ///
+    /// With [`TelescopeLayerBuilder::with_resolver`] or
+    /// [`TelescopeLayerBuilder::with_resolved_address`], the endpoint is connected
+    /// through the builder's resolver and address racing instead, which doesn't apply the
+    /// endpoint's TCP keep-alive and always sets `TCP_NODELAY`. Only that way is
+    /// [`TelescopeStats::peer_address`] reported.
+    pub fn builder_with_endpoint(service_name: String, endpoint: Endpoint) -> TelescopeLayerBuilder {
+        TelescopeLayerBuilder::with_endpoint(service_name, endpoint)
+    }
+
+    /// Export over a [`TelescopeTransport`] shared with other layers.
+    pub fn builder_with_transport(service_name: String, transport: TelescopeTransport) -> TelescopeLayerBuilder {
+        TelescopeLayerBuilder::with_transport(service_name, transport)
+    }
+
+    /// Export to a [`Sink`] such as a [`FileSink`] instead of a collector.
+    pub fn builder_with_sink(service_name: String, sink: impl Sink) -> TelescopeLayerBuilder {
+        TelescopeLayerBuilder::with_sink(service_name, sink)
+    }
+
+    /// A layer with its own levels, quotas, sampling and resource (starting out as just
+    /// `service.name`) that exports through `sender`'s pipeline: its exporter threads,
+    /// connection, routes, mirrors, processors and record filter. Lets several
+    /// subscriber stacks in one process share one connection and one exporter thread.
+    ///
+    /// Exporter settings (batching, export layers, disk queue, ...) come from the
+    /// pipeline; [`TelescopeLayerBuilder::validate`] rejects the ones that would need
+    /// an exporter of their own. The layer keeps the pipeline running like a sender, and
+    /// shutting down through its handle stops the whole pipeline.
+    pub fn builder_with_sender(service_name: String, sender: TelescopeSender) -> TelescopeLayerBuilder {
+        TelescopeLayerBuilder::with_sender(service_name, sender)
+    }
+
+    pub fn handle(&self) -> TelescopeHandle {
+        self.handle.clone()
+    }
+
+    /// See [`TelescopeHandle::filter_handle`].
+    pub fn filter_handle(&self) -> FilterHandle {
+        self.handle.filter_handle()
+    }
+
+    /// A [`TelescopeSender`] feeding this layer's pipeline, which keeps exporting for as
+    /// long as any sender is alive, even after the layer was dropped.
+    pub fn sender(&self) -> TelescopeSender {
+        TelescopeSender { handle: self.handle.clone(), owner: self.owner.clone() }
+    }
+
+    /// See [`TelescopeHandle::ready`].
+    pub async fn ready(&self, timeout: Duration) -> Result<(), tower::BoxError> {
+        self.handle.ready(timeout).await
+    }
+
+    /// Feed container log lines (Docker `json-file` or CRI) into this layer's pipeline.
+    pub fn container_log_bridge(&self, format: ContainerLogFormat) -> ContainerLogBridge {
+        ContainerLogBridge::new(self.sender.clone(), self.clock.clone(), format)
+    }
+
+    /// Ship plain log files through this layer's pipeline.
+    pub fn file_tail_source(&self) -> FileTailSource {
+        FileTailSource::new(self.sender.clone(), self.clock.clone())
+    }
+
+    /// Receive syslog from network devices and export it through this layer's pipeline.
+    #[cfg(feature = "syslog-listener")]
+    pub fn syslog_listener(&self) -> SyslogListener {
+        SyslogListener::new(self.sender.clone(), self.clock.clone())
+    }
+
+    /// Convert a captured event the way this layer converts live events (message
+    /// handling, key normalization, attribute limits), keeping the captured timestamp.
+    pub fn convert_captured(&self, event: &CapturedEvent) -> LogRecord {
+        let mut visitor = FieldVisitor::new(event.file.as_deref(), event.line);
+        for (name, value) in &event.fields {
+            visitor.push_captured(name, value);
         }
+        let mut record = self.convert(event.level, visitor, |_| {});
+        record.time_unix_nano = event.time_unix_nano;
+        record
     }
-}
 
-impl<S: Subscriber> tracing_subscriber::Layer<S> for TelescopeLayer {
-    fn on_event(&self, event: &Event<'_>, _ctx: tracing_subscriber::layer::Context<'_, S>) {
-        if event.metadata().level() == &Level::INFO
-            || event.metadata().level() == &Level::WARN
-            || event.metadata().level() == &Level::ERROR {
-            let mut visitor = FieldVisitor {
-                values: HashMap::new(),
-            };
-            event.record(&mut visitor);
-
-            let unix_nano = SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap()
-                .as_nanos() as u64;
-
-            let body = visitor.values["message"].to_string();
-
-            let record = LogRecord {
-                time_unix_nano: unix_nano,
-                observed_time_unix_nano: unix_nano,
-                severity_number: match event.metadata().level() {
-                    &Level::TRACE => 1,
-                    &Level::DEBUG => 5,
-                    &Level::INFO => 9,
-                    &Level::WARN => 13,
-                    &Level::ERROR => 17,
-                },
-                severity_text: event.metadata().level().to_string().clone(),
-                body: Some(AnyValue {
-                    value: Some(StringValue(body.clone())),
-                }),
-                attributes: vec![KeyValue {
-                    key: "file".to_string(),
-                    value:  event.metadata().file().map(|file| AnyValue{ value: Some(StringValue(file.to_string()))})
-                }, KeyValue {
-                    key: "line".to_string(),
-                    value:  event.metadata().line().map(|line| AnyValue{value:Some(IntValue(line as i64))})
-                }],
-                dropped_attributes_count: 0,
-                flags: 0,
-                trace_id: vec![],
-                span_id: vec![],
-            };
-            self.tx.send(record).unwrap();
+    /// Send captured events through this layer's processors, record filter, routes and
+    /// mirrors, as if they had just been logged. Returns the number of events replayed.
+    pub fn replay_capture(&self, events: impl IntoIterator<Item=CapturedEvent>) -> usize {
+        let mut replayed = 0;
+        for event in events {
+            let record = self.convert_captured(&event);
+            self.send(&event.target, record);
+            replayed += 1;
         }
+        replayed
     }
 }
 
-fn start_logging_thread(rx: Receiver<LogRecord>, mut client: LogsServiceClient<Channel>, service_name: String) {
-    thread::spawn(move || {
-        let mut buffer = Vec::with_capacity(1000);
-        let mut last_send = Instant::now();
-        let rt = tokio::runtime::Runtime::new().unwrap();
-        loop {
-            while let Ok(record) = rx.try_recv() {
-                buffer.push(record);
-                if buffer.len() == 1000 {
-                    break;
+impl<S> tracing_subscriber::Layer<S> for TelescopeLayer
+    where S: Subscriber + for<'a> LookupSpan<'a>
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: tracing_subscriber::layer::Context<'_, S>) {
+        // The exporter's own spans get no timing or buffering, so closing them can't queue
+        // records on the exporter thread; see `on_event`.
+        if exporter::is_internal_thread() {
+            return;
+        }
+        let mut visitor = TraceparentVisitor::default();
+        attrs.record(&mut visitor);
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        if self.span_metrics.is_some() {
+            span.extensions_mut().insert(span_metrics::SpanTiming(self.clock.now()));
+        }
+        if self.tail_buffering.as_ref().is_some_and(|tail_buffering| tail_buffering.matches(span.name())) {
+            span.extensions_mut().insert(tail::TailBuffer::new(self.clock.now()));
+        }
+        if let Some(rules) = &self.span_fields {
+            let mut fields = Vec::new();
+            attrs.record(&mut span_fields::SpanFieldVisitor { span_name: span.name(), rules, fields: &mut fields });
+            span.extensions_mut().insert(span_fields::SpanFields(fields));
+        }
+        let context = match visitor.0 {
+            Some(context) => Some(context),
+            None if self.generate_trace_ids && span.parent().is_none() => Some(TraceContext::generate(self.id_generator.as_ref())),
+            None => None,
+        };
+        if let Some(context) = context {
+            span.extensions_mut().insert(context);
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: tracing_subscriber::layer::Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut visitor = TraceparentVisitor::default();
+        values.record(&mut visitor);
+        if let Some(context) = visitor.0 {
+            span.extensions_mut().replace(context);
+        }
+        if let Some(rules) = &self.span_fields {
+            if let Some(fields) = span.extensions_mut().get_mut::<span_fields::SpanFields>() {
+                values.record(&mut span_fields::SpanFieldVisitor { span_name: span.name(), rules, fields: &mut fields.0 });
+            }
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: tracing_subscriber::layer::Context<'_, S>) {
+        if exporter::is_internal_thread() {
+            return;
+        }
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        if let Some(tail_buffering) = &self.tail_buffering {
+            if let Some(buffer) = span.extensions_mut().remove::<tail::TailBuffer>() {
+                for (target, record) in buffer.finish(tail_buffering, self.clock.now()) {
+                    self.send(target, record);
+                }
+            }
+        }
+        let Some(span_metrics) = self.span_metrics else {
+            return;
+        };
+        let Some(started) = span.extensions().get::<span_metrics::SpanTiming>().map(|timing| timing.0) else {
+            return;
+        };
+        let duration = self.clock.now().saturating_duration_since(started);
+        match span_metrics {
+            SpanMetrics::SpanEndRecords => {
+                let mut record = span_metrics::span_end_record(self.clock.as_ref(), span.name(), duration);
+                if let Some(context) = span.scope().find_map(|span| span.extensions().get::<TraceContext>().copied()) {
+                    context.stamp(&mut record);
+                }
+                self.sender.send_unrouted(record);
+            }
+            SpanMetrics::Summary(_) => self.span_stats.record(span.name(), duration),
+        }
+    }
+
+    fn on_follows_from(&self, span: &Id, follows: &Id, ctx: tracing_subscriber::layer::Context<'_, S>) {
+        links::record_follows_from(span, follows, &ctx);
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: tracing_subscriber::layer::Context<'_, S>) {
+        if !self.logs || exporter::is_internal_thread() {
+            return;
+        }
+        let metadata = event.metadata();
+        if let Some(capture) = &self.capture {
+            capture.capture(event, self.clock.now_unix_nano());
+        }
+        if !self.filter.enabled(metadata) && !self.sticky_debug_active(metadata) {
+            match &self.tail_buffering {
+                Some(tail_buffering) if tail::has_room(&ctx, event, tail_buffering.max_records()) => {
+                    tail::buffer(&ctx, event, tail_buffering.max_records(), false, self.record(event, &ctx));
                 }
+                _ => self.record_lead_up(event, &ctx),
+            }
+            return;
+        }
+        if metadata.level() == &Level::ERROR {
+            if let Some(sticky_debug) = &self.sticky_debug {
+                for record in sticky_debug.on_error(metadata.target(), self.clock.as_ref()) {
+                    self.send(metadata.target(), record);
+                }
+            }
+            if self.flight_recorder.is_some() {
+                for record in flight_recorder::take(&ctx, event) {
+                    self.send(metadata.target(), record);
+                }
+            }
+        }
+        let mut exempt = self.exemptions.target_exempt(metadata);
+        let mut early_record = None;
+        if !exempt && self.exemptions.needs_record() {
+            let record = self.record(event, &ctx);
+            exempt = self.exemptions.record_exempt(metadata.target(), &record);
+            early_record = Some(record);
+        }
+        if !self.quotas.is_empty() && !exempt {
+            let (allowed, summary) = self.quotas.admit(metadata.level(), self.clock.as_ref());
+            if let Some(summary) = summary {
+                self.sender.send_unrouted(summary);
             }
+            if !allowed {
+                instrumentation::record_suppressed();
+                return;
+            }
+        }
+        if let Some(load_shedding) = self.load_shedding.as_ref().filter(|_| !exempt) {
+            if !load_shedding.admit(&self.in_flight, metadata.level()) {
+                instrumentation::record_shed();
+                return;
+            }
+        }
+        if let Some(capabilities) = self.server_sampling.as_ref().filter(|_| !exempt) {
+            if !capabilities.sampling.admit(metadata.level(), self.clock.as_ref()) {
+                instrumentation::record_sampled_out();
+                return;
+            }
+        }
+        let mut record = early_record.unwrap_or_else(|| self.record(event, &ctx));
+        if let Some(dedup) = self.dedup.as_ref().filter(|_| !exempt) {
+            if !dedup.admit(&record, self.clock.as_ref()) {
+                instrumentation::record_deduplicated();
+                return;
+            }
+        }
+        if let Some(tail_buffering) = &self.tail_buffering {
+            match tail::buffer(&ctx, event, tail_buffering.max_records(), true, record) {
+                Some(unbuffered) => record = unbuffered,
+                None => return,
+            }
+        }
+        self.send(metadata.target(), record);
+    }
+}
 
-            if buffer.len() >= 100 || last_send.elapsed().as_millis() >= 1000 {
-                loop {
-                    let logs = ResourceLogs {
-                        resource: Some(Resource {
-                            attributes: vec![KeyValue {
-                                key: "service.name".to_string(),
-                                value: Some(AnyValue {
-                                    value: Some(StringValue(service_name.clone())),
-                                }),
-                            }],
-                            dropped_attributes_count: 0,
-                        }),
-                        scope_logs: vec![ScopeLogs {
-                            scope: None,
-                            log_records: buffer.drain(..).collect(),
-                            schema_url: "".to_string(),
-                        }],
-                        schema_url: "".to_string(),
-                    };
-
-                    let request = Request::new(ExportLogsServiceRequest {
-                        resource_logs: vec![logs],
-                    });
-
-                    match rt.block_on(async { client.export(request).await }) {
-                        Ok(_) => break, // If request succeeded, the loop is broken
-                        Err(_) => {
-                            thread::sleep(Duration::from_secs(1));
-                        }
+impl TelescopeLayer {
+    fn record<S>(&self, event: &Event<'_>, ctx: &tracing_subscriber::layer::Context<'_, S>) -> LogRecord
+        where S: Subscriber + for<'a> LookupSpan<'a>
+    {
+        let metadata = event.metadata();
+        let mut visitor = FieldVisitor::new(metadata.file(), metadata.line());
+        event.record(&mut visitor);
+        let mut record = self.convert(*metadata.level(), visitor, |attributes| {
+            if let Some(context) = TelescopeContext::current() {
+                for attribute in context.attributes() {
+                    if !attributes.iter().any(|existing| existing.key == attribute.key) {
+                        attributes.push(attribute.clone());
                     }
                 }
-                last_send = Instant::now();
-            } else {
-                // Allow thread to sleep for a while before next check
-                thread::sleep(Duration::from_millis(100));
             }
+            if let Some(rules) = &self.span_fields {
+                span_fields::inherit(ctx, event, rules, attributes);
+            }
+            attributes.extend(links::links_attribute(ctx, event));
+        });
+        if let Some(context) = trace_context::current(ctx, event) {
+            context.stamp(&mut record);
+        }
+        if let Some(sequence) = &self.timestamp_sequence {
+            sequence.stamp(&mut record);
+        }
+        record
+    }
+
+    // The part of the conversion shared by live and captured events; `contextual` adds
+    // what only a live event has (context attributes, span fields, links).
+    fn convert(&self, level: Level, visitor: FieldVisitor, contextual: impl FnOnce(&mut Vec<KeyValue>)) -> LogRecord {
+        let body = visitor.message.unwrap_or_default();
+        let mut attributes = visitor.attributes;
+        if self.message_in_attributes {
+            attributes.push(KeyValue {
+                key: "message".to_string(),
+                value: Some(AnyValue { value: Some(StringValue(body.clone())) }),
+            });
+        }
+        contextual(&mut attributes);
+        if let Some(normalizer) = &self.key_normalizer {
+            normalizer.apply(&mut attributes);
+        }
+        let dropped_attributes_count = self.attribute_limits.apply(&mut attributes);
+        instrumentation::attributes_dropped(dropped_attributes_count);
+
+        let unix_nano = self.clock.now_unix_nano();
+
+        LogRecord {
+            time_unix_nano: unix_nano,
+            observed_time_unix_nano: unix_nano,
+            severity_number: match level {
+                Level::TRACE => 1,
+                Level::DEBUG => 5,
+                Level::INFO => 9,
+                Level::WARN => 13,
+                Level::ERROR => 17,
+            },
+            severity_text: level.to_string(),
+            body: Some(AnyValue {
+                value: Some(StringValue(body)),
+            }),
+            attributes,
+            dropped_attributes_count,
+            flags: 0,
+            trace_id: vec![],
+            span_id: vec![],
         }
-    });
+    }
+
+    fn sticky_debug_active(&self, metadata: &Metadata<'_>) -> bool {
+        self.sticky_debug.as_ref().is_some_and(|sticky_debug| {
+            LevelFilter::DEBUG >= *metadata.level() && sticky_debug.is_active(metadata.target(), self.clock.now())
+        })
+    }
+
+    // Keep a DEBUG/TRACE record that isn't exported in case an error follows it.
+    fn record_lead_up<S>(&self, event: &Event<'_>, ctx: &tracing_subscriber::layer::Context<'_, S>)
+        where S: Subscriber + for<'a> LookupSpan<'a>
+    {
+        let metadata = event.metadata();
+        let sticky_debug = self.sticky_debug.as_ref()
+            .filter(|sticky_debug| sticky_debug.buffers_records() && LevelFilter::DEBUG >= *metadata.level());
+        let flight_recorder = self.flight_recorder.filter(|_| *metadata.level() >= Level::DEBUG);
+        if sticky_debug.is_none() && flight_recorder.is_none() {
+            return;
+        }
+        let record = self.record(event, ctx);
+        match (sticky_debug, flight_recorder) {
+            (Some(sticky_debug), Some(capacity)) => {
+                sticky_debug.buffer(metadata.target(), record.clone());
+                flight_recorder::record(ctx, event, capacity, record);
+            }
+            (Some(sticky_debug), None) => sticky_debug.buffer(metadata.target(), record),
+            (None, Some(capacity)) => flight_recorder::record(ctx, event, capacity, record),
+            (None, None) => {}
+        }
+    }
+
+    fn send(&self, target: &str, record: LogRecord) {
+        self.sender.send(target, record);
+    }
 }
 
 struct FieldVisitor {
-    values: HashMap<String, String>,
+    message: Option<String>,
+    attributes: Vec<KeyValue>,
+}
+
+impl FieldVisitor {
+    fn new(file: Option<&str>, line: Option<u32>) -> Self {
+        Self {
+            message: None,
+            attributes: vec![KeyValue {
+                key: "file".to_string(),
+                value:  file.map(|file| AnyValue{ value: Some(StringValue(file.to_string()))})
+            }, KeyValue {
+                key: "line".to_string(),
+                value:  line.map(|line| AnyValue{value:Some(IntValue(line as i64))})
+            }],
+        }
+    }
+
+    fn push(&mut self, name: &str, value: opentelclient::any_value::Value) {
+        self.attributes.push(KeyValue {
+            key: name.to_string(),
+            value: Some(AnyValue { value: Some(value) }),
+        });
+    }
+
+    fn push_text(&mut self, name: &str, text: String) {
+        if name == "message" {
+            self.message = Some(text);
+        } else {
+            self.push(name, StringValue(text));
+        }
+    }
+
+    fn push_captured(&mut self, name: &str, value: &CapturedValue) {
+        match value {
+            CapturedValue::F64(value) => self.push(name, DoubleValue(*value)),
+            CapturedValue::I64(value) => self.push(name, IntValue(*value)),
+            CapturedValue::U64(value) => self.push(name, IntValue(*value as i64)),
+            CapturedValue::Bool(value) => self.push(name, BoolValue(*value)),
+            CapturedValue::Str(text) | CapturedValue::Debug(text) => self.push_text(name, text.clone()),
+        }
+    }
 }
 
 impl tracing_core::field::Visit for FieldVisitor {
     // record primitives
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.push(field.name(), DoubleValue(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.push(field.name(), IntValue(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.push(field.name(), IntValue(value as i64));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.push(field.name(), BoolValue(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.push_text(field.name(), value.to_string());
+    }
+
     fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
-        self.values
-            .insert(field.name().to_string(), format!("{:?}", value));
+        self.push_text(field.name(), format!("{:?}", value));
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,65 @@
+use tracing::{Event, Subscriber};
+use tracing::span::Id;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::opentelclient::{AnyValue, ArrayValue, KeyValue, KeyValueList};
+use crate::opentelclient::any_value::Value::{ArrayValue as ArrayValueValue, IntValue, KvlistValue, StringValue};
+use crate::trace_context::{hex, TraceContext};
+
+pub(crate) const SPAN_LINKS_KEY: &str = "span.links";
+
+struct SpanLinks(Vec<AnyValue>);
+
+pub(crate) fn record_follows_from<S>(span: &Id, follows: &Id, ctx: &Context<'_, S>)
+    where S: Subscriber + for<'a> LookupSpan<'a>
+{
+    let (Some(span), Some(follows)) = (ctx.span(span), ctx.span(follows)) else {
+        return;
+    };
+    let link = match follows.extensions().get::<TraceContext>() {
+        Some(context) => vec![
+            string_value("trace_id", hex(&context.trace_id)),
+            string_value("span_id", hex(&context.span_id)),
+        ],
+        None => vec![
+            string_value("span.name", follows.name().to_string()),
+            KeyValue {
+                key: "span.id".to_string(),
+                value: Some(AnyValue { value: Some(IntValue(follows.id().into_u64() as i64)) }),
+            },
+        ],
+    };
+    let mut extensions = span.extensions_mut();
+    match extensions.get_mut::<SpanLinks>() {
+        Some(links) => links.0.push(kvlist(link)),
+        None => extensions.insert(SpanLinks(vec![kvlist(link)])),
+    }
+}
+
+// Links of every span enclosing the event, innermost first.
+pub(crate) fn links_attribute<S>(ctx: &Context<'_, S>, event: &Event<'_>) -> Option<KeyValue>
+    where S: Subscriber + for<'a> LookupSpan<'a>
+{
+    let values: Vec<AnyValue> = ctx.event_scope(event)?
+        .flat_map(|span| span.extensions().get::<SpanLinks>().map(|links| links.0.clone()).unwrap_or_default())
+        .collect();
+    if values.is_empty() {
+        return None;
+    }
+    Some(KeyValue {
+        key: SPAN_LINKS_KEY.to_string(),
+        value: Some(AnyValue { value: Some(ArrayValueValue(ArrayValue { values })) }),
+    })
+}
+
+fn kvlist(values: Vec<KeyValue>) -> AnyValue {
+    AnyValue { value: Some(KvlistValue(KeyValueList { values })) }
+}
+
+fn string_value(key: &str, value: String) -> KeyValue {
+    KeyValue {
+        key: key.to_string(),
+        value: Some(AnyValue { value: Some(StringValue(value)) }),
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use tower::BoxError;
+
+use crate::decode::{body, display, DecodedBatch, push_json_string, string_attribute};
+use crate::opentelclient::{KeyValue, LogRecord};
+use crate::service::ExportRequest;
+use crate::sink::Sink;
+use crate::trace_context::hex;
+
+type Labels = BTreeMap<String, String>;
+
+/// Pushes batches to Grafana Loki's JSON push API (`/loki/api/v1/push`), gzip
+/// compressed. Every record is labelled with `service_name` and `level`, plus one label
+/// per attribute added with [`LokiSink::with_label`]; the remaining attributes are
+/// appended to the line as logfmt, or sent as structured metadata (Loki 3) with
+/// [`LokiSink::with_structured_metadata`].
+///
+/// Only plain `http://` endpoints are supported; put a local agent or proxy in front of
+/// a TLS endpoint.
+pub struct LokiSink {
+    host: String,
+    path: String,
+    labels: Vec<(String, String)>,
+    structured_metadata: bool,
+    tenant: Option<String>,
+    timeout: Duration,
+}
+
+impl LokiSink {
+    /// `url` is the Loki base url, e.g. `http://loki:3100`.
+    pub fn new(url: impl AsRef<str>) -> Self {
+        let url = url.as_ref();
+        let url = url.strip_prefix("http://").unwrap_or(url).trim_end_matches('/');
+        let (host, prefix) = url.split_once('/').map_or((url, ""), |(host, prefix)| (host, prefix));
+        let prefix = if prefix.is_empty() { String::new() } else { format!("/{prefix}") };
+        Self {
+            host: host.to_string(),
+            path: format!("{prefix}/loki/api/v1/push"),
+            labels: Vec::new(),
+            structured_metadata: false,
+            tenant: None,
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Turn the attribute `key` into a stream label. Keep these to low-cardinality
+    /// attributes; every distinct label set is its own stream in Loki.
+    pub fn with_label(mut self, key: impl Into<String>) -> Self {
+        let key = key.into();
+        self.labels.push((label_name(&key), key));
+        self
+    }
+
+    /// Send non-label attributes and the trace context as structured metadata instead of
+    /// appending them to the line. Needs Loki 3 or later.
+    pub fn with_structured_metadata(mut self, enabled: bool) -> Self {
+        self.structured_metadata = enabled;
+        self
+    }
+
+    /// Tenant sent as `X-Scope-OrgID` to a multi-tenant Loki.
+    pub fn with_tenant(mut self, tenant: impl Into<String>) -> Self {
+        self.tenant = Some(tenant.into());
+        self
+    }
+
+    /// Connect, write and read timeout of a push, 10 seconds by default.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn labels(&self, resource: &[KeyValue], record: &LogRecord) -> Labels {
+        let mut labels = Labels::new();
+        if let Some(service_name) = string_attribute(resource, "service.name") {
+            labels.insert("service_name".to_string(), service_name.to_string());
+        }
+        labels.insert("level".to_string(), record.severity_text.to_lowercase());
+        for (label, key) in &self.labels {
+            if let Some(value) = record.attributes.iter().find(|attribute| attribute.key == *key).and_then(|attribute| attribute.value.as_ref()) {
+                labels.insert(label.clone(), display(value));
+            }
+        }
+        labels
+    }
+
+    fn entry(&self, record: &LogRecord) -> String {
+        let mut metadata: Vec<(String, String)> = Vec::new();
+        if !record.trace_id.is_empty() {
+            metadata.push(("trace_id".to_string(), hex(&record.trace_id)));
+            metadata.push(("span_id".to_string(), hex(&record.span_id)));
+        }
+        for attribute in &record.attributes {
+            if self.labels.iter().any(|(_, key)| *key == attribute.key) {
+                continue;
+            }
+            if let Some(value) = &attribute.value {
+                metadata.push((attribute.key.clone(), display(value)));
+            }
+        }
+
+        let mut line = body(record);
+        let mut entry = format!("[\"{}\",", record.time_unix_nano);
+        if self.structured_metadata {
+            push_json_string(&mut entry, &line);
+            entry.push_str(",{");
+            for (index, (key, value)) in metadata.iter().enumerate() {
+                if index > 0 {
+                    entry.push(',');
+                }
+                push_json_string(&mut entry, key);
+                entry.push(':');
+                push_json_string(&mut entry, value);
+            }
+            entry.push('}');
+        } else {
+            for (key, value) in &metadata {
+                if value.contains([' ', '"', '=']) || value.is_empty() {
+                    let _ = write!(line, " {key}={value:?}");
+                } else {
+                    let _ = write!(line, " {key}={value}");
+                }
+            }
+            push_json_string(&mut entry, &line);
+        }
+        entry.push(']');
+        entry
+    }
+
+    fn push(&self, body: &[u8]) -> Result<(), BoxError> {
+        let address = std::net::ToSocketAddrs::to_socket_addrs(&self.host)?
+            .next()
+            .ok_or_else(|| format!("could not resolve {}", self.host))?;
+        let mut stream = TcpStream::connect_timeout(&address, self.timeout)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+
+        let mut head = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\nConnection: close\r\n",
+            self.path, self.host, body.len(),
+        );
+        if let Some(tenant) = &self.tenant {
+            let _ = write!(head, "X-Scope-OrgID: {tenant}\r\n");
+        }
+        head.push_str("\r\n");
+        stream.write_all(head.as_bytes())?;
+        stream.write_all(body)?;
+        stream.flush()?;
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response)?;
+        let response = String::from_utf8_lossy(&response);
+        let status = response.split(' ').nth(1).and_then(|status| status.parse::<u16>().ok()).unwrap_or(0);
+        if !(200..300).contains(&status) {
+            let body = response.split_once("\r\n\r\n").map_or("", |(_, body)| body);
+            return Err(format!("loki push failed with status {status}: {}", body.trim()).into());
+        }
+        Ok(())
+    }
+}
+
+impl Sink for LokiSink {
+    fn export(&mut self, request: &ExportRequest) -> Result<(), BoxError> {
+        let batch = DecodedBatch::decode(&request.payload)?;
+        let mut streams: BTreeMap<Labels, Vec<String>> = BTreeMap::new();
+        for (resource, record) in batch.records() {
+            streams.entry(self.labels(resource, record)).or_default().push(self.entry(record));
+        }
+        if streams.is_empty() {
+            return Ok(());
+        }
+
+        let mut json = String::from("{\"streams\":[");
+        for (index, (labels, entries)) in streams.iter().enumerate() {
+            if index > 0 {
+                json.push(',');
+            }
+            json.push_str("{\"stream\":{");
+            for (index, (label, value)) in labels.iter().enumerate() {
+                if index > 0 {
+                    json.push(',');
+                }
+                push_json_string(&mut json, label);
+                json.push(':');
+                push_json_string(&mut json, value);
+            }
+            json.push_str("},\"values\":[");
+            json.push_str(&entries.join(","));
+            json.push_str("]}");
+        }
+        json.push_str("]}");
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(json.as_bytes())?;
+        self.push(&encoder.finish()?)
+    }
+}
+
+// Label names match `[a-zA-Z_][a-zA-Z0-9_]*`.
+fn label_name(key: &str) -> String {
+    let mut name: String = key.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
+    name
+}
@@ -0,0 +1,58 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::clock::Clock;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+#[cfg(target_os = "linux")]
+const CGROUP_MEMORY_PRESSURE: &str = "/sys/fs/cgroup/memory.pressure";
+
+#[derive(Clone)]
+pub(crate) enum MemoryPressureSignal {
+    /// Percentage of time some task stalled on memory over the last 10 seconds, from
+    /// cgroup v2 PSI, above which the host counts as under pressure.
+    #[cfg(target_os = "linux")]
+    CgroupPsi(f64),
+    Callback(Arc<dyn Fn() -> bool + Send + Sync>),
+}
+
+// Samples the signal at most once per CHECK_INTERVAL; the exporter asks on every loop.
+pub(crate) struct MemoryPressureMonitor {
+    signal: MemoryPressureSignal,
+    next_check: Option<Instant>,
+    under_pressure: bool,
+}
+
+impl MemoryPressureMonitor {
+    pub(crate) fn new(signal: MemoryPressureSignal) -> Self {
+        Self { signal, next_check: None, under_pressure: false }
+    }
+
+    pub(crate) fn under_pressure(&mut self, clock: &dyn Clock) -> bool {
+        let now = clock.now();
+        if self.next_check.is_some_and(|next_check| now < next_check) {
+            return self.under_pressure;
+        }
+        self.next_check = Some(now + CHECK_INTERVAL);
+        self.under_pressure = match &self.signal {
+            #[cfg(target_os = "linux")]
+            MemoryPressureSignal::CgroupPsi(threshold) => std::fs::read_to_string(CGROUP_MEMORY_PRESSURE)
+                .ok()
+                .and_then(|psi| some_avg10(&psi))
+                .is_some_and(|avg10| avg10 > *threshold),
+            MemoryPressureSignal::Callback(callback) => callback(),
+        };
+        self.under_pressure
+    }
+}
+
+// "some avg10=1.23 avg60=0.50 avg300=0.10 total=12345"
+#[cfg(target_os = "linux")]
+fn some_avg10(psi: &str) -> Option<f64> {
+    psi.lines()
+        .find_map(|line| line.strip_prefix("some "))?
+        .split_whitespace()
+        .find_map(|field| field.strip_prefix("avg10="))?
+        .parse()
+        .ok()
+}
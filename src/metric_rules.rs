@@ -0,0 +1,108 @@
+use metrics::Label;
+
+use crate::decode::display;
+use crate::expr::RecordFilter;
+use crate::opentelclient::any_value::Value;
+use crate::opentelclient::LogRecord;
+use crate::pipeline::Processor;
+
+enum Kind {
+    Counter,
+    // Attribute holding the observed value.
+    Histogram(String),
+}
+
+/// Turns matching records into a counter or histogram in the application's `metrics`
+/// recorder, e.g. the count of 5xx responses by route, so aggregates don't need every
+/// raw record. Add it with [`crate::TelescopeLayerBuilder::with_processor`].
+///
+/// ```text
+/// MetricRule::counter("http_server_errors_total", RecordFilter::parse(r#"attributes["status"] >= 500"#)?)
+///     .with_label("route")
+///     .dropping_records()
+/// ```
+pub struct MetricRule {
+    name: String,
+    filter: RecordFilter,
+    kind: Kind,
+    labels: Vec<String>,
+    target_label: bool,
+    drop: bool,
+}
+
+impl MetricRule {
+    /// Count the records matching `filter`.
+    pub fn counter(name: impl Into<String>, filter: RecordFilter) -> Self {
+        Self::new(name.into(), filter, Kind::Counter)
+    }
+
+    /// Record the numeric attribute `value_attribute` of the records matching `filter`.
+    /// Records without it are skipped.
+    pub fn histogram(name: impl Into<String>, filter: RecordFilter, value_attribute: impl Into<String>) -> Self {
+        Self::new(name.into(), filter, Kind::Histogram(value_attribute.into()))
+    }
+
+    fn new(name: String, filter: RecordFilter, kind: Kind) -> Self {
+        Self { name, filter, kind, labels: Vec::new(), target_label: false, drop: false }
+    }
+
+    /// Label the metric with the value of the record's `attribute` (empty when missing).
+    pub fn with_label(mut self, attribute: impl Into<String>) -> Self {
+        self.labels.push(attribute.into());
+        self
+    }
+
+    /// Label the metric with the record's target.
+    pub fn with_target_label(mut self) -> Self {
+        self.target_label = true;
+        self
+    }
+
+    /// Drop the matching records once they are counted instead of exporting them too.
+    pub fn dropping_records(mut self) -> Self {
+        self.drop = true;
+        self
+    }
+
+    fn labels(&self, target: &str, record: &LogRecord) -> Vec<Label> {
+        let mut labels: Vec<Label> = self.labels.iter()
+            .map(|key| {
+                let value = record.attributes.iter()
+                    .find(|attribute| attribute.key == *key)
+                    .and_then(|attribute| attribute.value.as_ref())
+                    .map(display)
+                    .unwrap_or_default();
+                Label::new(key.clone(), value)
+            })
+            .collect();
+        if self.target_label {
+            labels.push(Label::new("target", target.to_string()));
+        }
+        labels
+    }
+}
+
+impl Processor for MetricRule {
+    fn process(&self, target: &str, record: &mut LogRecord) -> bool {
+        if !self.filter.matches(target, record) {
+            return true;
+        }
+        match &self.kind {
+            Kind::Counter => metrics::counter!(self.name.clone(), self.labels(target, record)).increment(1),
+            Kind::Histogram(key) => {
+                let value = record.attributes.iter()
+                    .find(|attribute| attribute.key == *key)
+                    .and_then(|attribute| match attribute.value.as_ref()?.value.as_ref()? {
+                        Value::IntValue(value) => Some(*value as f64),
+                        Value::DoubleValue(value) => Some(*value),
+                        Value::StringValue(value) => value.parse().ok(),
+                        _ => None,
+                    });
+                if let Some(value) = value {
+                    metrics::histogram!(self.name.clone(), self.labels(target, record)).record(value);
+                }
+            }
+        }
+        !self.drop
+    }
+}
@@ -0,0 +1,87 @@
+use crate::opentelclient::KeyValue;
+
+/// Rewrites attribute keys to OpenTelemetry style, dot separated snake_case, so
+/// `userId`, `user-id` and `UserID` all end up as `user_id` and queries work the same
+/// across teams. Dots keep separating namespaces: `http.statusCode` becomes
+/// `http.status_code`.
+#[derive(Clone, Debug)]
+pub struct KeyNormalizer {
+    hyphens_as_dots: bool,
+    exempt_prefixes: Vec<String>,
+}
+
+impl KeyNormalizer {
+    pub fn new() -> Self {
+        Self {
+            hyphens_as_dots: false,
+            exempt_prefixes: Vec::new(),
+        }
+    }
+
+    /// Treat `-` as a namespace separator (`http-request.method` becomes
+    /// `http.request.method`) instead of a word separator (`http_request.method`).
+    pub fn with_hyphens_as_dots(mut self) -> Self {
+        self.hyphens_as_dots = true;
+        self
+    }
+
+    /// Leave keys starting with `prefix` as they are, e.g. for attributes another system
+    /// relies on verbatim.
+    pub fn with_exempt_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.exempt_prefixes.push(prefix.into());
+        self
+    }
+
+    pub(crate) fn apply(&self, attributes: &mut [KeyValue]) {
+        for attribute in attributes {
+            if !self.exempt_prefixes.iter().any(|prefix| attribute.key.starts_with(prefix.as_str())) {
+                attribute.key = self.normalize(&attribute.key);
+            }
+        }
+    }
+
+    fn normalize(&self, key: &str) -> String {
+        let mut normalized = String::with_capacity(key.len() + 4);
+        let chars: Vec<char> = key.chars().collect();
+        for (index, &char) in chars.iter().enumerate() {
+            match char {
+                '.' => push_separator(&mut normalized, '.'),
+                '-' if self.hyphens_as_dots => push_separator(&mut normalized, '.'),
+                '-' | '_' | ' ' => push_separator(&mut normalized, '_'),
+                char if char.is_uppercase() => {
+                    // A word starts at an uppercase letter after a lowercase letter or
+                    // digit (`userId`), or at the last capital of an acronym (`HTTPServer`).
+                    let previous = index.checked_sub(1).map(|index| chars[index]);
+                    let next = chars.get(index + 1);
+                    let starts_word = previous.is_some_and(|previous| previous.is_lowercase() || previous.is_ascii_digit())
+                        || previous.is_some_and(char::is_uppercase) && next.is_some_and(|next| next.is_lowercase());
+                    if starts_word {
+                        push_separator(&mut normalized, '_');
+                    }
+                    normalized.extend(char.to_lowercase());
+                }
+                char => normalized.push(char),
+            }
+        }
+        normalized.trim_end_matches(['_', '.']).to_string()
+    }
+}
+
+impl Default for KeyNormalizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Separators never start a segment or follow another one; a dot wins over an underscore.
+fn push_separator(normalized: &mut String, separator: char) {
+    match normalized.chars().last() {
+        None | Some('.') => {}
+        Some('_') if separator == '.' => {
+            normalized.pop();
+            normalized.push('.');
+        }
+        Some('_') => {}
+        Some(_) => normalized.push(separator),
+    }
+}
@@ -571,6 +571,7 @@ pub mod logs_service_client {
 }
 
 /// Generated server implementations.
+#[cfg(feature = "server")]
 pub mod logs_service_server {
     #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
 
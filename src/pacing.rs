@@ -0,0 +1,81 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+
+use crate::clock::Clock;
+
+// After an outage every replica tends to reconnect at the same moment and flush
+// everything it queued. The pacer spreads that drain out: a random delay before the
+// first retry and a records/sec ceiling until the backlog is gone.
+pub(crate) struct BacklogPacer {
+    clock: Arc<dyn Clock>,
+    records_per_sec: Option<u32>,
+    initial_delay: Option<Duration>,
+    in_outage: bool,
+    draining: bool,
+    next_send: Instant,
+}
+
+impl BacklogPacer {
+    pub(crate) fn new(clock: Arc<dyn Clock>, records_per_sec: Option<u32>, initial_delay: Option<Duration>) -> Self {
+        Self {
+            next_send: clock.now(),
+            clock,
+            records_per_sec,
+            initial_delay,
+            in_outage: false,
+            draining: false,
+        }
+    }
+
+    // Extra time to wait before retrying, only added on the first failure of an outage.
+    pub(crate) fn on_failure(&mut self) -> Duration {
+        if self.in_outage {
+            return Duration::ZERO;
+        }
+        self.in_outage = true;
+        match self.initial_delay {
+            Some(max) if !max.is_zero() => rand::thread_rng().gen_range(Duration::ZERO..max),
+            _ => Duration::ZERO,
+        }
+    }
+
+    pub(crate) fn on_success(&mut self) {
+        if self.in_outage {
+            self.in_outage = false;
+            self.draining = true;
+        }
+    }
+
+    pub(crate) fn pace(&mut self, records: usize, backlog_remaining: bool) {
+        if !self.draining {
+            return;
+        }
+        if !backlog_remaining {
+            self.draining = false;
+        }
+        let Some(records_per_sec) = self.records_per_sec.filter(|rate| *rate > 0) else {
+            return;
+        };
+        let now = self.clock.now();
+        if self.next_send > now {
+            self.clock.sleep(self.next_send - now);
+        }
+        self.next_send = self.clock.now() + Duration::from_secs_f64(records as f64 / records_per_sec as f64);
+    }
+}
+
+// Offset in `[0, max)` that is stable for the lifetime of this process but differs
+// between replicas, so their periodic flushes drift apart instead of lining up.
+pub(crate) fn process_jitter(service_name: &str, max: Duration) -> Duration {
+    if max.is_zero() {
+        return Duration::ZERO;
+    }
+    let mut hasher = DefaultHasher::new();
+    std::process::id().hash(&mut hasher);
+    service_name.hash(&mut hasher);
+    Duration::from_nanos(hasher.finish() % max.as_nanos() as u64)
+}
@@ -0,0 +1,177 @@
+use std::io;
+
+use tonic::transport::Channel;
+
+use crate::builder::{Target, TelescopeLayerBuilder};
+use crate::endpoint;
+use crate::error::{ConfigError, TelescopeError};
+use crate::expr::RecordFilter;
+use crate::handle::TelescopeHandle;
+use crate::normalize::KeyNormalizer;
+use crate::opentelclient::LogRecord;
+use crate::sink::{Sink, SinkService};
+use crate::TelescopeLayer;
+
+/// A step every record passes through before it is batched: it can change the record
+/// in place, or return `false` to drop it. `target` is the tracing target, or the
+/// target of the source the record came from.
+///
+/// Processors must leave the observed time alone, not clear the timestamp, keep trace
+/// and span ids at 16 and 8 bytes (or empty) and the severity number within OTLP's
+/// range. The damage done by one that doesn't is undone before the record is queued, or,
+/// with [`crate::TelescopeLayerBuilder::with_strict_processors`], it panics right away.
+pub trait Processor: Send + Sync + 'static {
+    fn process(&self, target: &str, record: &mut LogRecord) -> bool;
+}
+
+impl<F> Processor for F
+    where F: Fn(&str, &mut LogRecord) -> bool + Send + Sync + 'static,
+{
+    fn process(&self, target: &str, record: &mut LogRecord) -> bool {
+        self(target, record)
+    }
+}
+
+/// Keeps the records matching the filter.
+impl Processor for RecordFilter {
+    fn process(&self, target: &str, record: &mut LogRecord) -> bool {
+        self.matches(target, record)
+    }
+}
+
+impl Processor for KeyNormalizer {
+    fn process(&self, _target: &str, record: &mut LogRecord) -> bool {
+        self.apply(&mut record.attributes);
+        true
+    }
+}
+
+/// Feeds records into a pipeline from outside tracing, started once the pipeline is
+/// built. Closures taking the pipeline's layer are sources, e.g.
+/// `|layer: &TelescopeLayer| layer.file_tail_source().with_pattern("/var/log/app/*.log").start()`.
+pub trait Source: Send + 'static {
+    fn start(self: Box<Self>, layer: &TelescopeLayer) -> io::Result<()>;
+}
+
+impl<F> Source for F
+    where F: FnOnce(&TelescopeLayer) -> io::Result<()> + Send + 'static,
+{
+    fn start(self: Box<Self>, layer: &TelescopeLayer) -> io::Result<()> {
+        self(layer)
+    }
+}
+
+/// Sources, processors and sinks wired up in one place:
+///
+/// ```text
+/// Pipeline::builder("legacy-app")
+///     .source(|layer: &TelescopeLayer| layer.file_tail_source().with_pattern("/var/log/app/*.log").start())
+///     .processor(KeyNormalizer::new())
+///     .endpoint("http://telescope:4317".to_string())
+///     .sink(FileSink::new("/var/spool/telescope/copy.bin"))
+///     .build()
+///     .await?
+/// ```
+///
+/// Every record goes through every processor, then to every sink; each sink has its own
+/// batching and retries. The pipeline's layer is the source for the application's own
+/// tracing events.
+pub struct Pipeline {
+    layer: TelescopeLayer,
+}
+
+impl Pipeline {
+    pub fn builder(service_name: impl Into<String>) -> PipelineBuilder {
+        PipelineBuilder {
+            service_name: service_name.into(),
+            sources: Vec::new(),
+            processors: Vec::new(),
+            sinks: Vec::new(),
+            configure: None,
+        }
+    }
+
+    pub fn handle(&self) -> TelescopeHandle {
+        self.layer.handle()
+    }
+
+    /// The layer to install in the application's subscriber.
+    pub fn into_layer(self) -> TelescopeLayer {
+        self.layer
+    }
+}
+
+pub struct PipelineBuilder {
+    service_name: String,
+    sources: Vec<Box<dyn Source>>,
+    processors: Vec<Box<dyn Processor>>,
+    sinks: Vec<Target>,
+    configure: Option<Box<dyn FnOnce(TelescopeLayerBuilder) -> TelescopeLayerBuilder + Send>>,
+}
+
+impl PipelineBuilder {
+    pub fn source(mut self, source: impl Source) -> Self {
+        self.sources.push(Box::new(source));
+        self
+    }
+
+    pub fn processor(mut self, processor: impl Processor) -> Self {
+        self.processors.push(Box::new(processor));
+        self
+    }
+
+    /// Export to a collector at `url`.
+    pub fn endpoint(mut self, url: String) -> Self {
+        self.sinks.push(Target::Url(endpoint::normalize(&url)));
+        self
+    }
+
+    pub fn channel(mut self, channel: Channel) -> Self {
+        self.sinks.push(Target::Channel(channel));
+        self
+    }
+
+    pub fn sink(mut self, sink: impl Sink) -> Self {
+        self.sinks.push(Target::Sink(SinkService::new(sink)));
+        self
+    }
+
+    /// Any other layer setting (routes, quotas, disk queue, ...). They apply to the
+    /// first sink, and batching settings to every sink.
+    pub fn configure(mut self, configure: impl FnOnce(TelescopeLayerBuilder) -> TelescopeLayerBuilder + Send + 'static) -> Self {
+        self.configure = Some(Box::new(configure));
+        self
+    }
+
+    /// Connect the sinks and start the sources. Fails without any sink, or if the layer
+    /// or a source can't start.
+    pub async fn build(self) -> Result<Pipeline, TelescopeError> {
+        let mut sinks = self.sinks.into_iter();
+        let Some(main) = sinks.next() else {
+            return Err(ConfigError { problems: vec!["a pipeline needs at least one sink".to_string()] }.into());
+        };
+        let mut builder = TelescopeLayerBuilder::with_target(self.service_name, main);
+        builder.mirrors.extend(sinks);
+        builder.processors.extend(self.processors);
+        if let Some(configure) = self.configure {
+            builder = configure(builder);
+        }
+        let layer = builder.try_build().await?;
+        for source in self.sources {
+            source.start(&layer).map_err(TelescopeError::Source)?;
+        }
+        Ok(Pipeline { layer })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pipeline;
+    use crate::error::TelescopeError;
+
+    #[tokio::test]
+    async fn pipeline_needs_a_sink() {
+        let result = Pipeline::builder("test").build().await;
+        assert!(matches!(result, Err(TelescopeError::Config(error)) if error.problems == ["a pipeline needs at least one sink"]));
+    }
+}
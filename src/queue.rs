@@ -0,0 +1,97 @@
+use std::sync::{Arc, Mutex, Weak};
+use std::sync::mpsc::{Receiver, sync_channel, SyncSender, TrySendError};
+
+use crate::admission::InFlight;
+use crate::batch::Queued;
+use crate::instrumentation;
+use crate::stats::ExportStats;
+
+/// What happens to a record logged while an exporter's queue is full, see
+/// [`crate::TelescopeLayerBuilder::with_queue`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Wait for room, slowing the logging thread down to the export rate.
+    #[default]
+    Block,
+    /// Drop the record being logged.
+    DropNewest,
+    /// Drop the oldest queued record to make room.
+    DropOldest,
+}
+
+// The bounded queue between the logging threads and one exporter thread. Records that
+// don't make it into the queue are counted in the stats of the main endpoint.
+pub(crate) fn queue(capacity: usize, overflow: OverflowPolicy, in_flight: InFlight, stats: Arc<ExportStats>) -> (QueueSender, QueueReceiver) {
+    let (tx, rx) = sync_channel(capacity);
+    let rx = Arc::new(Mutex::new(rx));
+    let sender = QueueSender { tx, rx: Arc::downgrade(&rx), overflow, in_flight, stats };
+    (sender, QueueReceiver(rx))
+}
+
+#[derive(Clone)]
+pub(crate) struct QueueSender {
+    tx: SyncSender<Queued>,
+    // Only used to evict the oldest record; weak so the queue still disconnects once the
+    // exporter exited.
+    rx: Weak<Mutex<Receiver<Queued>>>,
+    overflow: OverflowPolicy,
+    in_flight: InFlight,
+    stats: Arc<ExportStats>,
+}
+
+impl QueueSender {
+    // Exporters exit on shutdown; records sent afterwards are dropped.
+    pub(crate) fn send(&self, mut queued: Queued) {
+        self.in_flight.add(1);
+        match self.overflow {
+            OverflowPolicy::Block => {
+                if self.tx.send(queued).is_err() {
+                    self.in_flight.complete(1);
+                }
+            }
+            OverflowPolicy::DropNewest => match self.tx.try_send(queued) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => self.dropped(),
+                Err(TrySendError::Disconnected(_)) => self.in_flight.complete(1),
+            },
+            OverflowPolicy::DropOldest => loop {
+                match self.tx.try_send(queued) {
+                    Ok(()) => return,
+                    Err(TrySendError::Full(rejected)) => {
+                        let Some(rx) = self.rx.upgrade() else {
+                            self.in_flight.complete(1);
+                            return;
+                        };
+                        if rx.lock().unwrap().try_recv().is_ok() {
+                            self.dropped();
+                        }
+                        queued = rejected;
+                    }
+                    Err(TrySendError::Disconnected(_)) => {
+                        self.in_flight.complete(1);
+                        return;
+                    }
+                }
+            },
+        }
+    }
+
+    fn dropped(&self) {
+        self.in_flight.complete(1);
+        self.stats.on_records_dropped(1);
+        instrumentation::records_dropped(1);
+    }
+}
+
+pub(crate) struct QueueReceiver(Arc<Mutex<Receiver<Queued>>>);
+
+impl QueueReceiver {
+    pub(crate) fn try_recv(&self) -> Option<Queued> {
+        self.0.lock().unwrap().try_recv().ok()
+    }
+
+    // Empty the queue, returning how many records were in it.
+    pub(crate) fn drain(&self) -> usize {
+        self.0.lock().unwrap().try_iter().count()
+    }
+}
@@ -0,0 +1,93 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use tracing::Level;
+
+use crate::clock::Clock;
+use crate::internal::{attribute, internal_record};
+use crate::opentelclient::any_value::Value::{IntValue, StringValue};
+use crate::opentelclient::LogRecord;
+
+pub(crate) struct Quota {
+    limit: u64,
+    interval_nanos: u64,
+    window_start: AtomicU64,
+    admitted: AtomicU64,
+    suppressed: AtomicU64,
+}
+
+pub(crate) struct Admission {
+    pub(crate) allowed: bool,
+    // Records suppressed in the window that just ended, reported once by whichever
+    // event rolls the window over.
+    pub(crate) suppressed_in_last_window: Option<u64>,
+}
+
+impl Quota {
+    pub(crate) fn new(limit: u64, interval: Duration, now_unix_nano: u64) -> Self {
+        Self {
+            limit,
+            interval_nanos: interval.as_nanos() as u64,
+            window_start: AtomicU64::new(now_unix_nano),
+            admitted: AtomicU64::new(0),
+            suppressed: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn admit(&self, now_unix_nano: u64) -> Admission {
+        let mut suppressed_in_last_window = None;
+        let start = self.window_start.load(Ordering::Acquire);
+        if now_unix_nano.saturating_sub(start) >= self.interval_nanos
+            && self.window_start.compare_exchange(start, now_unix_nano, Ordering::AcqRel, Ordering::Relaxed).is_ok() {
+            self.admitted.store(0, Ordering::Release);
+            suppressed_in_last_window = Some(self.suppressed.swap(0, Ordering::AcqRel)).filter(|suppressed| *suppressed > 0);
+        }
+
+        let allowed = self.admitted.fetch_add(1, Ordering::AcqRel) < self.limit;
+        if !allowed {
+            self.suppressed.fetch_add(1, Ordering::AcqRel);
+        }
+        Admission { allowed, suppressed_in_last_window }
+    }
+}
+
+// A level with its own quota is only limited by that quota, so a tight global quota can
+// sit next to a generous ERROR budget without ever suppressing errors.
+#[derive(Default)]
+pub(crate) struct Quotas {
+    pub(crate) global: Option<Quota>,
+    pub(crate) by_level: Vec<(Level, Quota)>,
+}
+
+impl Quotas {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.global.is_none() && self.by_level.is_empty()
+    }
+
+    // Whether the record may be exported, plus a summary record to send if a quota
+    // window just rolled over with suppressed records.
+    pub(crate) fn admit(&self, level: &Level, clock: &dyn Clock) -> (bool, Option<LogRecord>) {
+        let (quota, severity) = match self.by_level.iter().find(|(quota_level, _)| quota_level == level) {
+            Some((_, quota)) => (quota, Some(level)),
+            None => match &self.global {
+                Some(quota) => (quota, None),
+                None => return (true, None),
+            },
+        };
+        let admission = quota.admit(clock.now_unix_nano());
+        let summary = admission.suppressed_in_last_window
+            .map(|suppressed| suppressed_record(clock, suppressed, severity));
+        (admission.allowed, summary)
+    }
+}
+
+fn suppressed_record(clock: &dyn Clock, suppressed: u64, severity: Option<&Level>) -> LogRecord {
+    let mut attributes = vec![attribute("telescope.quota.suppressed", IntValue(suppressed as i64))];
+    if let Some(severity) = severity {
+        attributes.push(attribute("telescope.quota.severity", StringValue(severity.to_string())));
+    }
+    let mut record = internal_record(clock, format!("suppressed {} records", suppressed), attributes);
+    record.severity_number = 13;
+    record.severity_text = "WARN".to_string();
+    record
+}
@@ -0,0 +1,152 @@
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tokio::net::TcpStream;
+use tokio::task::JoinSet;
+use tonic::codegen::http::Uri;
+use tower::Service;
+
+use crate::service::BoxFuture;
+
+// How long an attempt gets before the next address is tried alongside it (RFC 8305's
+// connection attempt delay).
+const ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Resolves endpoint host names in place of the system resolver, e.g. against a fake DNS
+/// in tests or a service registry in air-gapped environments. Called on a blocking
+/// thread whenever a connection is made; closures taking the host and port work too.
+pub trait Resolver: Send + Sync + 'static {
+    fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>>;
+}
+
+impl<F> Resolver for F
+    where F: Fn(&str, u16) -> io::Result<Vec<SocketAddr>> + Send + Sync + 'static
+{
+    fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        self(host, port)
+    }
+}
+
+// Connects the channels of url endpoints. Static addresses win over the resolver, which
+// wins over the system resolver. The addresses are raced happy eyeballs style, IPv6 and
+// IPv4 alternating: every ATTEMPT_DELAY (or as soon as an attempt fails) another one
+// starts next to those in flight, and the first connection wins, so an address that
+// doesn't answer costs a fraction of a second instead of a full connect timeout.
+#[derive(Clone)]
+pub(crate) struct ResolvingConnector {
+    pub(crate) resolver: Option<Arc<dyn Resolver>>,
+    pub(crate) addresses: Arc<HashMap<String, Vec<IpAddr>>>,
+    pub(crate) attempt_timeout: Option<Duration>,
+    // Set on the main endpoint's connector only.
+    pub(crate) peer: Option<Arc<ConnectedPeer>>,
+}
+
+// Where the main endpoint's channel last connected to, and over which protocol, kept
+// for the stats rather than put on records.
+#[derive(Default)]
+pub(crate) struct ConnectedPeer {
+    peer: Mutex<Option<(SocketAddr, &'static str)>>,
+}
+
+impl ConnectedPeer {
+    pub(crate) fn get(&self) -> Option<(SocketAddr, &'static str)> {
+        *self.peer.lock().unwrap()
+    }
+}
+
+impl Service<Uri> for ResolvingConnector {
+    type Response = TcpStream;
+    type Error = io::Error;
+    type Future = BoxFuture<io::Result<TcpStream>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let connector = self.clone();
+        Box::pin(async move {
+            let host = uri.host()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("{uri} has no host")))?
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .to_string();
+            let port = uri.port_u16().unwrap_or(if uri.scheme_str() == Some("https") { 443 } else { 80 });
+            let mut pending = interleave(connector.resolve(host.clone(), port).await?);
+            let mut attempts = JoinSet::new();
+            let mut last_error = io::Error::new(io::ErrorKind::NotFound, format!("no addresses for {host}"));
+            loop {
+                match pending.pop_front() {
+                    Some(address) => {
+                        attempts.spawn(attempt(address, connector.attempt_timeout));
+                    }
+                    None if attempts.is_empty() => return Err(last_error),
+                    None => {}
+                }
+                let finished = if pending.is_empty() {
+                    attempts.join_next().await
+                } else {
+                    tokio::time::timeout(ATTEMPT_DELAY, attempts.join_next()).await.ok().flatten()
+                };
+                match finished {
+                    Some(Ok(Ok(stream))) => {
+                        if let Some(peer) = &connector.peer {
+                            // gRPC is HTTP/2 only: negotiated over TLS, or spoken right away.
+                            let protocol = if uri.scheme_str() == Some("https") { "h2" } else { "h2c" };
+                            *peer.peer.lock().unwrap() = Some((stream.peer_addr()?, protocol));
+                        }
+                        return Ok(stream);
+                    }
+                    Some(Ok(Err(error))) => last_error = error,
+                    Some(Err(error)) => last_error = io::Error::other(error),
+                    None => {}
+                }
+            }
+        })
+    }
+}
+
+impl ResolvingConnector {
+    async fn resolve(&self, host: String, port: u16) -> io::Result<Vec<SocketAddr>> {
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Ok(vec![SocketAddr::new(ip, port)]);
+        }
+        if let Some(addresses) = self.addresses.get(&host) {
+            return Ok(addresses.iter().map(|ip| SocketAddr::new(*ip, port)).collect());
+        }
+        match self.resolver.clone() {
+            Some(resolver) => tokio::task::spawn_blocking(move || resolver.resolve(&host, port))
+                .await
+                .map_err(io::Error::other)?,
+            None => Ok(tokio::net::lookup_host((host.as_str(), port)).await?.collect()),
+        }
+    }
+}
+
+async fn attempt(address: SocketAddr, timeout: Option<Duration>) -> io::Result<TcpStream> {
+    let connect = TcpStream::connect(address);
+    let stream = match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, connect).await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, format!("connecting to {address} timed out")))??,
+        None => connect.await?,
+    };
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+// Alternate the address families, starting with the family of the first address.
+fn interleave(addresses: Vec<SocketAddr>) -> VecDeque<SocketAddr> {
+    let first_is_v6 = addresses.first().is_some_and(SocketAddr::is_ipv6);
+    let (mut first, mut second): (VecDeque<_>, VecDeque<_>) = addresses.into_iter()
+        .partition(|address| address.is_ipv6() == first_is_v6);
+    let mut interleaved = VecDeque::with_capacity(first.len() + second.len());
+    while !first.is_empty() || !second.is_empty() {
+        interleaved.extend(first.pop_front());
+        interleaved.extend(second.pop_front());
+    }
+    interleaved
+}
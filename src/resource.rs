@@ -0,0 +1,70 @@
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::internal::attribute;
+use crate::opentelclient::any_value::Value;
+use crate::opentelclient::any_value::Value::StringValue;
+use crate::opentelclient::KeyValue;
+
+// Versions are unique across the process, as layers sharing an exporter each have their
+// own resource.
+static NEXT_VERSION: AtomicU64 = AtomicU64::new(0);
+
+fn next_version() -> u64 {
+    NEXT_VERSION.fetch_add(1, Ordering::Relaxed)
+}
+
+// The resource as it was at one point in time. Every record is queued with the snapshot
+// that was current when it was logged, so a batch exported after a change still labels
+// older records with the values they were logged under.
+#[derive(Clone, Debug)]
+pub(crate) struct ResourceSnapshot {
+    pub(crate) version: u64,
+    pub(crate) attributes: Vec<KeyValue>,
+    pub(crate) schema_url: String,
+}
+
+// Resource attributes shared between the handle, the senders and the exporter threads.
+// Every change replaces the snapshot with one carrying the next version.
+pub(crate) struct SharedResource {
+    current: RwLock<Arc<ResourceSnapshot>>,
+}
+
+impl SharedResource {
+    pub(crate) fn new(service_name: &str) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(ResourceSnapshot {
+                version: next_version(),
+                attributes: vec![attribute("service.name", StringValue(service_name.to_string()))],
+                schema_url: String::new(),
+            })),
+        }
+    }
+
+    pub(crate) fn set(&self, key: String, value: Value) {
+        self.update(|snapshot| match snapshot.attributes.iter_mut().find(|attribute| attribute.key == key) {
+            Some(existing) => *existing = attribute(&key, value),
+            None => snapshot.attributes.push(attribute(&key, value)),
+        });
+    }
+
+    pub(crate) fn remove(&self, key: &str) {
+        self.update(|snapshot| snapshot.attributes.retain(|attribute| attribute.key != key));
+    }
+
+    pub(crate) fn set_schema_url(&self, schema_url: String) {
+        self.update(|snapshot| snapshot.schema_url = schema_url);
+    }
+
+    pub(crate) fn current(&self) -> Arc<ResourceSnapshot> {
+        self.current.read().unwrap().clone()
+    }
+
+    fn update(&self, change: impl FnOnce(&mut ResourceSnapshot)) {
+        let mut current = self.current.write().unwrap();
+        let mut next = ResourceSnapshot::clone(&current);
+        change(&mut next);
+        next.version = next_version();
+        *current = Arc::new(next);
+    }
+}
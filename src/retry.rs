@@ -0,0 +1,120 @@
+use std::time::Duration;
+
+/// How failed exports are retried, see
+/// [`TelescopeLayerBuilder::with_retry_policy`](crate::TelescopeLayerBuilder::with_retry_policy).
+/// The default retries a batch every second until it goes through, holding back every
+/// batch after it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryPolicy {
+    pub(crate) initial_backoff: Duration,
+    pub(crate) max_backoff: Duration,
+    pub(crate) multiplier: f64,
+    pub(crate) jitter: f64,
+    pub(crate) max_attempts: Option<u32>,
+    pub(crate) on_exhausted: RetryExhausted,
+}
+
+/// What happens to a batch once [`RetryPolicy::with_max_attempts`] attempts failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RetryExhausted {
+    /// Drop the batch.
+    Drop,
+    /// Set the batch aside and try it again whenever the exporter is idle, after the
+    /// batches queued behind it. Keeps at most `max_batches` aside, dropping the oldest,
+    /// whose records count towards [`crate::TelescopeStats::records_dropped`].
+    Requeue { max_batches: usize },
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(1),
+            multiplier: 1.0,
+            jitter: 0.0,
+            max_attempts: None,
+            on_exhausted: RetryExhausted::Drop,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait `initial` after the first failure and double the wait after every further
+    /// one, up to `max`.
+    pub fn exponential(initial: Duration, max: Duration) -> Self {
+        Self { initial_backoff: initial, max_backoff: max, multiplier: 2.0, ..Self::default() }
+    }
+
+    /// Grow the wait by `multiplier` (at least 1.0) per failed attempt.
+    pub fn with_multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    /// Shorten every wait by a random share of up to `jitter` (0.0 to 1.0), so clients
+    /// that failed together don't all retry at the same moment.
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Stop retrying a batch after `attempts` failed attempts, the first one included,
+    /// and handle it as `on_exhausted` says.
+    pub fn with_max_attempts(mut self, attempts: u32, on_exhausted: RetryExhausted) -> Self {
+        self.max_attempts = Some(attempts);
+        self.on_exhausted = on_exhausted;
+        self
+    }
+
+    pub(crate) fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.initial_backoff > self.max_backoff {
+            problems.push("initial retry backoff must not exceed the max backoff".to_string());
+        }
+        if self.multiplier.is_nan() || self.multiplier < 1.0 {
+            problems.push("retry backoff multiplier must be at least 1".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.jitter) {
+            problems.push("retry jitter must be between 0 and 1".to_string());
+        }
+        if self.max_attempts == Some(0) {
+            problems.push("max retry attempts must be at least 1".to_string());
+        }
+        if self.on_exhausted == (RetryExhausted::Requeue { max_batches: 0 }) {
+            problems.push("requeue must keep at least one batch".to_string());
+        }
+        problems
+    }
+
+    // Wait after the `attempt`th failed attempt, counting from 1.
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let backoff = self.initial_backoff.as_secs_f64() * self.multiplier.powi(exponent);
+        let backoff = backoff.min(self.max_backoff.as_secs_f64()) * (1.0 - self.jitter * rand::random::<f64>());
+        // A max backoff near `Duration::MAX` doesn't survive the round trip through f64.
+        Duration::try_from_secs_f64(backoff).unwrap_or(self.max_backoff)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::RetryPolicy;
+
+    #[test]
+    fn backoff_grows_up_to_max() {
+        let policy = RetryPolicy::exponential(Duration::from_millis(100), Duration::from_secs(1));
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(3), Duration::from_millis(400));
+        assert_eq!(policy.backoff(5), Duration::from_secs(1));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn backoff_near_duration_max_does_not_panic() {
+        let policy = RetryPolicy::exponential(Duration::from_secs(1), Duration::MAX);
+        assert_eq!(policy.backoff(u32::MAX), Duration::MAX);
+        assert_eq!(policy.with_multiplier(f64::INFINITY).backoff(2), Duration::MAX);
+    }
+}
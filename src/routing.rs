@@ -0,0 +1,94 @@
+use std::sync::Arc;
+
+use crate::admission::InFlight;
+use crate::batch::Queued;
+use crate::expr::RecordFilter;
+use crate::guard::RecordGuard;
+use crate::pipeline::Processor;
+use crate::queue::QueueSender;
+use crate::opentelclient::LogRecord;
+use crate::resource::SharedResource;
+
+pub(crate) enum RouteMatcher {
+    Target(String),
+    Filter(RecordFilter),
+}
+
+impl RouteMatcher {
+    fn matches(&self, target: &str, record: &LogRecord) -> bool {
+        match self {
+            RouteMatcher::Target(pattern) => target_matches(pattern, target),
+            RouteMatcher::Filter(filter) => filter.matches(target, record),
+        }
+    }
+}
+
+pub(crate) struct Route {
+    pub(crate) matcher: RouteMatcher,
+    pub(crate) tx: QueueSender,
+}
+
+// `audit::*` matches `audit` and everything below it, `audit::http` only matches that
+// exact target and its children.
+pub(crate) fn target_matches(pattern: &str, target: &str) -> bool {
+    let base = pattern.strip_suffix("::*").unwrap_or(pattern);
+    match target.strip_prefix(base) {
+        Some(rest) => rest.is_empty() || rest.starts_with("::"),
+        None => false,
+    }
+}
+
+pub(crate) fn route<'a>(routes: &'a [Route], target: &str, record: &LogRecord) -> Option<&'a QueueSender> {
+    routes.iter()
+        .find(|route| route.matcher.matches(target, record))
+        .map(|route| &route.tx)
+}
+
+// Where finished records go: the processors and the record filter, then every mirror
+// and the first matching route or the main exporter, queued with the current resource.
+// Shared by the layer and the sources that feed it from outside tracing.
+#[derive(Clone)]
+pub(crate) struct RecordSender {
+    pub(crate) tx: QueueSender,
+    pub(crate) routes: Arc<Vec<Route>>,
+    pub(crate) mirrors: Arc<Vec<QueueSender>>,
+    pub(crate) processors: Arc<Vec<Box<dyn Processor>>>,
+    pub(crate) strict_processors: bool,
+    pub(crate) record_filter: Option<RecordFilter>,
+    pub(crate) in_flight: InFlight,
+    pub(crate) resource: Arc<SharedResource>,
+}
+
+impl RecordSender {
+    pub(crate) fn send(&self, target: &str, mut record: LogRecord) {
+        if !self.processors.is_empty() {
+            let guard = RecordGuard::new(&record);
+            for (index, processor) in self.processors.iter().enumerate() {
+                if !processor.process(target, &mut record) {
+                    return;
+                }
+                if self.strict_processors {
+                    if let Err(problem) = guard.check(&record) {
+                        panic!("telescope processor {index} (for target {target:?}) broke a record: {problem}");
+                    }
+                }
+            }
+            guard.repair(&mut record);
+        }
+        if self.record_filter.as_ref().is_some_and(|filter| !filter.matches(target, &record)) {
+            return;
+        }
+        let resource = self.resource.current();
+        for mirror in self.mirrors.iter() {
+            mirror.send(Queued { record: record.clone(), resource: resource.clone() });
+        }
+        let tx = route(&self.routes, target, &record).unwrap_or(&self.tx);
+        tx.send(Queued { record, resource });
+    }
+
+    // Straight to the main exporter, for the layer's own records (span end records,
+    // quota summaries) that skip processors, filters and routes.
+    pub(crate) fn send_unrouted(&self, record: LogRecord) {
+        self.tx.send(Queued { record, resource: self.resource.current() });
+    }
+}
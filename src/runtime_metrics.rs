@@ -0,0 +1,113 @@
+use std::time::{Duration, Instant};
+
+use tokio::runtime::Handle;
+
+use crate::clock::Clock;
+use crate::internal::{attribute, internal_record};
+use crate::opentelclient::any_value::Value::{DoubleValue, IntValue};
+use crate::opentelclient::{KeyValue, LogRecord};
+
+// Periodic record about the process and the application's tokio runtime, for services
+// without a metrics stack. Process stats are read from /proc and only reported on
+// Linux.
+pub(crate) struct RuntimeMetrics {
+    interval: Duration,
+    runtime: Option<Handle>,
+    last_sample: Instant,
+    last_cpu_time: Option<Duration>,
+}
+
+impl RuntimeMetrics {
+    pub(crate) fn new(interval: Duration, runtime: Option<Handle>, now: Instant) -> Self {
+        Self {
+            interval,
+            runtime,
+            last_sample: now,
+            last_cpu_time: process::cpu_time(),
+        }
+    }
+
+    pub(crate) fn sample(&mut self, clock: &dyn Clock) -> Option<LogRecord> {
+        let now = clock.now();
+        let elapsed = now.saturating_duration_since(self.last_sample);
+        if elapsed < self.interval {
+            return None;
+        }
+        self.last_sample = now;
+        let mut attributes = Vec::new();
+        self.process_attributes(elapsed, &mut attributes);
+        if let Some(runtime) = &self.runtime {
+            let metrics = runtime.metrics();
+            attributes.push(attribute("tokio.workers", IntValue(metrics.num_workers() as i64)));
+            attributes.push(attribute("tokio.alive_tasks", IntValue(metrics.num_alive_tasks() as i64)));
+            attributes.push(attribute("tokio.global_queue_depth", IntValue(metrics.global_queue_depth() as i64)));
+        }
+        Some(internal_record(clock, "runtime metrics".to_string(), attributes))
+    }
+
+    fn process_attributes(&mut self, elapsed: Duration, attributes: &mut Vec<KeyValue>) {
+        if let Some(rss) = process::rss_bytes() {
+            attributes.push(attribute("process.memory.rss_bytes", IntValue(rss as i64)));
+        }
+        if let Some(cpu_time) = process::cpu_time() {
+            attributes.push(attribute("process.cpu.time_ns", IntValue(cpu_time.as_nanos() as i64)));
+            if let Some(last_cpu_time) = self.last_cpu_time.filter(|_| !elapsed.is_zero()) {
+                // Cores in use on average since the last sample, so 2.0 is two busy cores.
+                let utilization = cpu_time.saturating_sub(last_cpu_time).as_secs_f64() / elapsed.as_secs_f64();
+                attributes.push(attribute("process.cpu.utilization", DoubleValue(utilization)));
+            }
+            self.last_cpu_time = Some(cpu_time);
+        }
+        if let Some(open_fds) = process::open_fds() {
+            attributes.push(attribute("process.open_fds", IntValue(open_fds as i64)));
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod process {
+    use std::fs;
+    use std::time::Duration;
+
+    pub(super) fn rss_bytes() -> Option<u64> {
+        let statm = fs::read_to_string("/proc/self/statm").ok()?;
+        let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+        Some(pages * page_size()?)
+    }
+
+    pub(super) fn cpu_time() -> Option<Duration> {
+        let stat = fs::read_to_string("/proc/self/stat").ok()?;
+        // The command name may contain spaces, so count fields from after it. utime and
+        // stime are fields 14 and 15 of the line.
+        let mut fields = stat.get(stat.rfind(')')? + 2..)?.split_whitespace().skip(11);
+        let utime: u64 = fields.next()?.parse().ok()?;
+        let stime: u64 = fields.next()?.parse().ok()?;
+        let ticks_per_second = u64::try_from(unsafe { libc::sysconf(libc::_SC_CLK_TCK) }).ok().filter(|ticks| *ticks > 0)?;
+        Some(Duration::from_nanos((utime + stime) * 1_000_000_000 / ticks_per_second))
+    }
+
+    pub(super) fn open_fds() -> Option<usize> {
+        Some(fs::read_dir("/proc/self/fd").ok()?.count())
+    }
+
+    fn page_size() -> Option<u64> {
+        u64::try_from(unsafe { libc::sysconf(libc::_SC_PAGESIZE) }).ok()
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod process {
+    use std::time::Duration;
+
+    pub(super) fn rss_bytes() -> Option<u64> {
+        None
+    }
+
+    pub(super) fn cpu_time() -> Option<Duration> {
+        None
+    }
+
+    pub(super) fn open_fds() -> Option<usize> {
+        None
+    }
+}
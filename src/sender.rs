@@ -0,0 +1,60 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::clock::Clock;
+use crate::handle::TelescopeHandle;
+use crate::opentelclient::LogRecord;
+use crate::shutdown::{Shutdown, ShutdownReport};
+
+// How long the exporters get to flush once the last owner of a pipeline is gone without
+// an explicit shutdown.
+const DROP_FLUSH_DEADLINE: Duration = Duration::from_secs(5);
+
+// Held by the layer and every `TelescopeSender`. Dropping the last one starts flushing
+// in the background; it doesn't wait, as it may run on any thread.
+pub(crate) struct Owner {
+    pub(crate) shutdown: Arc<Shutdown>,
+    pub(crate) clock: Arc<dyn Clock>,
+}
+
+impl Drop for Owner {
+    fn drop(&mut self) {
+        self.shutdown.begin(self.clock.now() + DROP_FLUSH_DEADLINE);
+    }
+}
+
+/// Cheap, cloneable sender into a layer's pipeline, for feeding it from other
+/// subscribers, a secondary `tracing` dispatcher, plugins or tests.
+///
+/// Records go through the layer's processors, record filter, routes and mirrors, like the
+/// layer's own. The pipeline keeps running while the layer or any sender is alive; once
+/// the last of them is dropped it flushes in the background, and
+/// [`Self::shutdown`] on the last one flushes and waits.
+#[derive(Clone)]
+pub struct TelescopeSender {
+    pub(crate) handle: TelescopeHandle,
+    pub(crate) owner: Arc<Owner>,
+}
+
+impl TelescopeSender {
+    /// Queue `record` as if it was logged with `target`. Blocks while the exporter's queue
+    /// is full; records sent after shutdown are dropped.
+    pub fn send(&self, target: &str, mut record: LogRecord) {
+        if record.observed_time_unix_nano == 0 {
+            record.observed_time_unix_nano = self.handle.clock.now_unix_nano();
+        }
+        self.handle.sender.send(target, record);
+    }
+
+    pub fn handle(&self) -> TelescopeHandle {
+        self.handle.clone()
+    }
+
+    /// Let go of this sender. If it was the pipeline's last owner, flush and stop
+    /// exporting within `deadline` like [`TelescopeHandle::shutdown`] and return the
+    /// report; otherwise the pipeline keeps running for the others and `None` is
+    /// returned.
+    pub fn shutdown(self, deadline: Duration) -> Option<ShutdownReport> {
+        Arc::into_inner(self.owner).map(|_owner| self.handle.shutdown(deadline))
+    }
+}
@@ -0,0 +1,33 @@
+use std::sync::Mutex;
+
+use crate::internal::attribute;
+use crate::opentelclient::any_value::Value::IntValue;
+use crate::opentelclient::LogRecord;
+
+pub(crate) const SEQ_IN_NS_KEY: &str = "telescope.seq_in_ns";
+
+// Numbers records that share a timestamp in the order they were logged: the first one
+// keeps no attribute, the following ones get `telescope.seq_in_ns` = 1, 2, ... so
+// sorting by timestamp and then sequence gives a stable total order.
+#[derive(Default)]
+pub(crate) struct TimestampSequence {
+    // Timestamp of the last record and how many records before it had the same one.
+    last: Mutex<(u64, u64)>,
+}
+
+impl TimestampSequence {
+    pub(crate) fn stamp(&self, record: &mut LogRecord) {
+        let sequence = {
+            let mut last = self.last.lock().unwrap();
+            if last.0 == record.time_unix_nano {
+                last.1 += 1;
+            } else {
+                *last = (record.time_unix_nano, 0);
+            }
+            last.1
+        };
+        if sequence > 0 {
+            record.attributes.push(attribute(SEQ_IN_NS_KEY, IntValue(sequence as i64)));
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::sync::Mutex;
+
+use tonic::metadata::MetadataMap;
+use tracing::Level;
+
+use crate::clock::Clock;
+
+// `info=0.1,debug=0;ttl=60` keeps 10% of INFO and none of the DEBUG records for the next
+// 60 seconds. Every hint replaces the previous one, levels it doesn't name are kept in
+// full, and `ttl=0` lifts the hint early.
+const SAMPLE_HEADER: &str = "x-telescope-sample";
+const DEFAULT_TTL_SECS: u64 = 60;
+
+struct SamplingHint {
+    rates: Vec<(Level, f64)>,
+    ttl_nanos: u64,
+    // Set by the first record checked against the hint; responses arrive without a clock.
+    until_unix_nano: Option<u64>,
+}
+
+// Sampling rates a telescope server asks for while its ingestion is overloaded, so
+// shedding is coordinated centrally instead of by every client on its own.
+#[derive(Default)]
+pub(crate) struct ServerSampling {
+    hint: Mutex<Option<SamplingHint>>,
+}
+
+impl ServerSampling {
+    pub(crate) fn update(&self, metadata: &MetadataMap) {
+        if let Some(value) = metadata.get(SAMPLE_HEADER).and_then(|value| value.to_str().ok()) {
+            *self.hint.lock().unwrap() = parse(value);
+        }
+    }
+
+    pub(crate) fn admit(&self, level: &Level, clock: &dyn Clock) -> bool {
+        let mut hint = self.hint.lock().unwrap();
+        let Some(active) = hint.as_mut() else {
+            return true;
+        };
+        let now = clock.now_unix_nano();
+        if now >= *active.until_unix_nano.get_or_insert(now.saturating_add(active.ttl_nanos)) {
+            *hint = None;
+            return true;
+        }
+        match active.rates.iter().find(|(rate_level, _)| rate_level == level) {
+            Some((_, rate)) => rand::random::<f64>() < *rate,
+            None => true,
+        }
+    }
+}
+
+fn parse(value: &str) -> Option<SamplingHint> {
+    let mut parts = value.split(';');
+    let rates = parts.next()?.split(',')
+        .filter_map(|rate| {
+            let (level, rate) = rate.split_once('=')?;
+            Some((level.trim().parse::<Level>().ok()?, rate.trim().parse::<f64>().ok()?.clamp(0.0, 1.0)))
+        })
+        .collect::<Vec<_>>();
+    let ttl_secs = parts.filter_map(|part| part.split_once('='))
+        .find(|(key, _)| key.trim() == "ttl")
+        .map_or(Some(DEFAULT_TTL_SECS), |(_, ttl)| ttl.trim().parse().ok())?;
+    if rates.is_empty() || ttl_secs == 0 {
+        return None;
+    }
+    Some(SamplingHint { rates, ttl_nanos: ttl_secs.saturating_mul(1_000_000_000), until_unix_nano: None })
+}
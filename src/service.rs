@@ -0,0 +1,210 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use tonic::metadata::{AsciiMetadataKey, AsciiMetadataValue, MetadataMap, MetadataValue};
+use tonic::{Extensions, Request};
+use tower::{BoxError, Service};
+use tower::retry::Policy;
+use tower::util::BoxCloneService;
+
+use crate::auth::AuthChannel;
+use crate::capabilities::Capabilities;
+use crate::clock::Clock;
+use crate::export::ExportClient;
+use crate::hedge::{BATCH_ID_HEADER, Hedge};
+use crate::instrumentation;
+use crate::opentelclient::ExportLogsServiceResponse;
+use crate::pacing::BacklogPacer;
+use crate::retry::RetryPolicy;
+use crate::shutdown::Shutdown;
+use crate::stats::ExportStats;
+use crate::trace_context::hex;
+
+/// One encoded batch on its way to the collector, as seen by export middleware.
+#[derive(Clone, Debug)]
+pub struct ExportRequest {
+    /// Encoded `ExportLogsServiceRequest`.
+    pub payload: Bytes,
+    /// gRPC metadata sent with the request.
+    pub metadata: MetadataMap,
+}
+
+impl ExportRequest {
+    /// Id of the batch, sent as the `x-telescope-request-id` header and kept across
+    /// retries, for matching client-side failures with server-side logs.
+    pub fn request_id(&self) -> Option<&str> {
+        self.metadata.get(REQUEST_ID_HEADER).and_then(|value| value.to_str().ok())
+    }
+
+    pub(crate) fn into_request(self) -> Request<Bytes> {
+        Request::from_parts(self.metadata, Extensions::default(), self.payload)
+    }
+}
+
+pub(crate) const REQUEST_ID_HEADER: &str = "x-telescope-request-id";
+
+/// A failed export attempt, as passed to the callback set with
+/// [`crate::TelescopeLayerBuilder::with_export_error_callback`].
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct ExportFailure {
+    pub request_id: Option<String>,
+    pub error: String,
+}
+
+pub(crate) type ExportErrorCallback = Arc<dyn Fn(&ExportFailure) + Send + Sync>;
+
+/// Type-erased export service that middleware added with
+/// [`crate::TelescopeLayerBuilder::with_export_layer`] wraps.
+pub type BoxExportService = BoxCloneService<ExportRequest, ExportLogsServiceResponse, BoxError>;
+
+pub(crate) type ExportLayerFn = Arc<dyn Fn(BoxExportService) -> BoxExportService + Send + Sync>;
+
+pub(crate) type BoxFuture<T> = Pin<Box<dyn Future<Output=T> + Send>>;
+
+pub(crate) type StaticHeaders = Vec<(AsciiMetadataKey, AsciiMetadataValue)>;
+
+// Parses headers set on the builder, or says which one (described as `kind`) is not a
+// valid gRPC header.
+pub(crate) fn parse_headers(headers: &[(String, String)], kind: &str) -> Result<StaticHeaders, String> {
+    headers.iter()
+        .map(|(name, value)| {
+            let key = name.parse::<AsciiMetadataKey>()
+                .map_err(|_| format!("{name:?} is not a valid {kind} name"))?;
+            let value = value.parse::<AsciiMetadataValue>()
+                .map_err(|_| format!("value of {kind} {name:?} is not valid header text"))?;
+            Ok((key, value))
+        })
+        .collect()
+}
+
+// Adds the headers set with `with_metadata` to every request the exporter sends, fresh,
+// retried or replayed from the disk queue, before export middleware sees it.
+#[derive(Clone)]
+pub(crate) struct MetadataService {
+    pub(crate) inner: BoxExportService,
+    pub(crate) headers: Arc<StaticHeaders>,
+}
+
+impl Service<ExportRequest> for MetadataService {
+    type Response = ExportLogsServiceResponse;
+    type Error = BoxError;
+    type Future = <BoxExportService as Service<ExportRequest>>::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: ExportRequest) -> Self::Future {
+        for (key, value) in self.headers.iter() {
+            request.metadata.insert(key.clone(), value.clone());
+        }
+        self.inner.call(request)
+    }
+}
+
+// Innermost service: sends the request to the collector (and the hedge endpoint). Hedged
+// requests get a fresh batch id here, so retries of a batch are deduplicated separately.
+#[derive(Clone)]
+pub(crate) struct ExportService {
+    pub(crate) client: ExportClient<AuthChannel>,
+    pub(crate) hedge: Option<Hedge>,
+    pub(crate) stats: Option<Arc<ExportStats>>,
+    pub(crate) capabilities: Option<Arc<Capabilities>>,
+}
+
+impl Service<ExportRequest> for ExportService {
+    type Response = ExportLogsServiceResponse;
+    type Error = BoxError;
+    type Future = BoxFuture<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, mut request: ExportRequest) -> Self::Future {
+        if self.hedge.is_some() {
+            if let Ok(batch_id) = MetadataValue::try_from(hex(&rand::random::<[u8; 16]>())) {
+                request.metadata.insert(BATCH_ID_HEADER, batch_id);
+            }
+        }
+        let mut client = self.client.clone();
+        let mut hedge = self.hedge.clone();
+        let stats = self.stats.clone();
+        let capabilities = self.capabilities.clone();
+        let request_id = request.request_id().map(str::to_string);
+        Box::pin(async move {
+            if let Some(stats) = &stats {
+                stats.on_attempt();
+            }
+            let response = match hedge.as_mut() {
+                Some(hedge) => hedge.export(&mut client, || request.clone().into_request()).await,
+                None => client.export(request.into_request()).await,
+            };
+            if let Some(stats) = &stats {
+                stats.on_result(&response, request_id.as_deref());
+            }
+            let response = response?;
+            if let Some(capabilities) = &capabilities {
+                capabilities.update(response.metadata());
+            }
+            Ok(response.into_inner())
+        })
+    }
+}
+
+// Retries failed batches as the configured policy says; by default forever, one second
+// apart, which is what the exporter has always done. Cloned per batch, so `attempts`
+// counts the batch's failures. The backoff runs on the exporter thread that drives the
+// request, so a blocking sleep on the configured clock is fine here.
+#[derive(Clone)]
+pub(crate) struct ExportRetryPolicy {
+    pub(crate) policy: RetryPolicy,
+    pub(crate) attempts: u32,
+    pub(crate) clock: Arc<dyn Clock>,
+    pub(crate) pacer: Arc<Mutex<BacklogPacer>>,
+    pub(crate) error_callback: Option<ExportErrorCallback>,
+    pub(crate) shutdown: Arc<Shutdown>,
+}
+
+impl<E: std::fmt::Display> Policy<ExportRequest, ExportLogsServiceResponse, E> for ExportRetryPolicy {
+    type Future = BoxFuture<Self>;
+
+    fn retry(&self, request: &ExportRequest, result: Result<&ExportLogsServiceResponse, &E>) -> Option<Self::Future> {
+        match result {
+            Ok(_) => {
+                self.pacer.lock().unwrap().on_success();
+                None
+            }
+            Err(error) => {
+                instrumentation::export_failed();
+                if let Some(callback) = &self.error_callback {
+                    callback(&ExportFailure {
+                        request_id: request.request_id().map(str::to_string),
+                        error: error.to_string(),
+                    });
+                }
+                let attempts = self.attempts + 1;
+                if self.policy.max_attempts.is_some_and(|max_attempts| attempts >= max_attempts) {
+                    return None;
+                }
+                let delay = self.policy.backoff(attempts) + self.pacer.lock().unwrap().on_failure();
+                if self.shutdown.remaining(self.clock.as_ref()).is_some_and(|remaining| remaining <= delay) {
+                    return None;
+                }
+                let policy = Self { attempts, ..self.clone() };
+                Some(Box::pin(async move {
+                    policy.clock.sleep(delay);
+                    policy
+                }))
+            }
+        }
+    }
+
+    fn clone_request(&self, request: &ExportRequest) -> Option<ExportRequest> {
+        Some(request.clone())
+    }
+}
@@ -0,0 +1,117 @@
+use std::future::Future;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use tower::BoxError;
+
+use crate::admission::InFlight;
+use crate::clock::Clock;
+
+const DEADLINE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// What happened to the records queued when
+/// [`TelescopeHandle::shutdown`](crate::TelescopeHandle::shutdown) was called, counted
+/// per destination (a mirrored record counts once per mirror).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ShutdownReport {
+    /// Records exported during the final flush.
+    pub exported: u64,
+    /// Records that could not be delivered before the deadline.
+    pub dropped: u64,
+    /// Records written to the disk queue, to be sent by the next run.
+    pub persisted_to_disk: u64,
+    /// How long the shutdown took.
+    pub elapsed: Duration,
+}
+
+impl ShutdownReport {
+    /// Whether every record queued at shutdown was exported.
+    pub fn is_clean(&self) -> bool {
+        self.dropped == 0 && self.persisted_to_disk == 0
+    }
+
+    pub(crate) fn add(&mut self, other: ShutdownReport) {
+        self.exported += other.exported;
+        self.dropped += other.dropped;
+        self.persisted_to_disk += other.persisted_to_disk;
+    }
+}
+
+#[derive(Default)]
+struct State {
+    deadline: Option<Instant>,
+    running_exporters: usize,
+    report: ShutdownReport,
+}
+
+// Shared by the handle and every exporter thread of a layer. Once a deadline is set the
+// exporters flush what they hold, bounded by it, report what they could not deliver and
+// exit.
+#[derive(Default)]
+pub(crate) struct Shutdown {
+    state: Mutex<State>,
+    exporter_finished: Condvar,
+}
+
+impl Shutdown {
+    pub(crate) fn register_exporter(&self) {
+        self.state.lock().unwrap().running_exporters += 1;
+    }
+
+    pub(crate) fn exporter_finished(&self, report: ShutdownReport) {
+        let mut state = self.state.lock().unwrap();
+        state.running_exporters -= 1;
+        state.report.add(report);
+        self.exporter_finished.notify_all();
+    }
+
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        self.state.lock().unwrap().deadline
+    }
+
+    // Time left until the deadline, or `None` when not shutting down.
+    pub(crate) fn remaining(&self, clock: &dyn Clock) -> Option<Duration> {
+        self.deadline().map(|deadline| deadline.saturating_duration_since(clock.now()))
+    }
+
+    // Start shutting down without waiting for the exporters. An earlier deadline that was
+    // already set wins.
+    pub(crate) fn begin(&self, deadline: Instant) {
+        let mut state = self.state.lock().unwrap();
+        state.deadline = Some(state.deadline.map_or(deadline, |earlier| earlier.min(deadline)));
+    }
+
+    /// Start shutting down and wait up to `timeout` for every exporter to finish. Records
+    /// still in flight in exporters that did not finish in time count as dropped.
+    pub(crate) fn shutdown(&self, deadline: Instant, timeout: Duration, in_flight: &InFlight) -> ShutdownReport {
+        let give_up = Instant::now() + timeout;
+        self.begin(deadline);
+        let mut state = self.state.lock().unwrap();
+        while state.running_exporters > 0 {
+            let now = Instant::now();
+            if now >= give_up {
+                let mut report = state.report;
+                report.dropped += in_flight.get() as u64;
+                return report;
+            }
+            state = self.exporter_finished.wait_timeout(state, give_up - now).unwrap().0;
+        }
+        state.report
+    }
+
+    // Fail `export` once the shutdown deadline passes, so a hanging request can't hold
+    // up the final flush.
+    pub(crate) async fn bounded<T>(&self, clock: &dyn Clock, export: impl Future<Output=Result<T, BoxError>>) -> Result<T, BoxError> {
+        tokio::select! {
+            result = export => result,
+            _ = self.expired(clock) => Err("shutdown deadline passed".into()),
+        }
+    }
+
+    async fn expired(&self, clock: &dyn Clock) {
+        while self.remaining(clock) != Some(Duration::ZERO) {
+            tokio::time::sleep(DEADLINE_POLL_INTERVAL).await;
+        }
+    }
+}
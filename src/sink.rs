@@ -0,0 +1,75 @@
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use tower::{BoxError, Service};
+
+use crate::instrumentation;
+use crate::opentelclient::ExportLogsServiceResponse;
+use crate::service::{BoxExportService, BoxFuture, ExportRequest};
+
+/// A destination for batches other than an OTLP/gRPC collector, e.g. a
+/// [`crate::FileSink`]. Sinks get the same batching, retry and middleware as the
+/// collector and run on the exporter thread, so blocking I/O is fine.
+pub trait Sink: Send + 'static {
+    fn export(&mut self, request: &ExportRequest) -> Result<(), BoxError>;
+}
+
+// Adapts a sink to the export service stack. Clones share the sink, so routes and
+// fallbacks can point at the same file.
+#[derive(Clone)]
+pub(crate) struct SinkService {
+    sink: Arc<Mutex<dyn Sink>>,
+}
+
+impl SinkService {
+    pub(crate) fn new(sink: impl Sink) -> Self {
+        Self { sink: Arc::new(Mutex::new(sink)) }
+    }
+}
+
+impl Service<ExportRequest> for SinkService {
+    type Response = ExportLogsServiceResponse;
+    type Error = BoxError;
+    type Future = BoxFuture<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: ExportRequest) -> Self::Future {
+        let result = self.sink.lock().unwrap().export(&request);
+        Box::pin(std::future::ready(result.map(|_| ExportLogsServiceResponse::default())))
+    }
+}
+
+// Hands a batch to the fallback sink when the primary destination fails. The retry
+// policy only sees an error when both failed.
+#[derive(Clone)]
+pub(crate) struct FallbackService {
+    pub(crate) primary: BoxExportService,
+    pub(crate) fallback: SinkService,
+}
+
+impl Service<ExportRequest> for FallbackService {
+    type Response = ExportLogsServiceResponse;
+    type Error = BoxError;
+    type Future = BoxFuture<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.primary.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: ExportRequest) -> Self::Future {
+        let primary = self.primary.call(request.clone());
+        let mut fallback = self.fallback.clone();
+        Box::pin(async move {
+            match primary.await {
+                Ok(response) => Ok(response),
+                Err(_) => {
+                    instrumentation::export_failed();
+                    fallback.call(request).await
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,182 @@
+use tracing::{Event, Subscriber};
+use tracing::field::{Field, Visit};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::internal::attribute;
+use crate::opentelclient::any_value::Value;
+use crate::opentelclient::any_value::Value::{BoolValue, DoubleValue, IntValue, StringValue};
+use crate::opentelclient::KeyValue;
+use crate::trace_context::TRACEPARENT_FIELD;
+
+// Which span fields are copied onto the records emitted inside the span. `#[instrument]`
+// records every argument, including `self` and large Debug dumps, so fields can be
+// excluded per span name and string values capped.
+#[derive(Clone, Default)]
+pub(crate) struct SpanFieldRules {
+    // (span name pattern, field name pattern), `*` matches any run of characters.
+    pub(crate) exclusions: Vec<(String, String)>,
+    // (span name pattern, field name patterns); the first matching span pattern decides.
+    pub(crate) allowlists: Vec<(String, Vec<String>)>,
+    pub(crate) max_value_length: Option<usize>,
+    pub(crate) max_depth: Option<usize>,
+    pub(crate) conflict: SpanFieldConflict,
+}
+
+/// What happens when an inherited span field has the same key as a field already on the
+/// record. Fields of the event itself always win.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SpanFieldConflict {
+    /// The innermost span's value is kept.
+    #[default]
+    Innermost,
+    /// The outermost span's value is kept, e.g. for ids set on the request span.
+    Outermost,
+    /// Colliding fields are kept under `<span name>.<field>`.
+    Prefix,
+}
+
+impl SpanFieldRules {
+    fn excluded(&self, span_name: &str, field: &str) -> bool {
+        field == TRACEPARENT_FIELD
+            || self.allowlists.iter()
+            .find(|(span, _)| glob_matches(span, span_name))
+            .is_some_and(|(_, fields)| !fields.iter().any(|name| glob_matches(name, field)))
+            || self.exclusions.iter().any(|(span, name)| glob_matches(span, span_name) && glob_matches(name, field))
+    }
+
+    fn cap(&self, mut value: String) -> String {
+        if let Some(max_length) = self.max_value_length {
+            if value.len() > max_length {
+                // The ellipsis counts towards the cap, unless the cap can't even hold it.
+                let ellipsis = max_length >= '…'.len_utf8();
+                let mut end = if ellipsis { max_length - '…'.len_utf8() } else { max_length };
+                while !value.is_char_boundary(end) {
+                    end -= 1;
+                }
+                value.truncate(end);
+                if ellipsis {
+                    value.push('…');
+                }
+            }
+        }
+        value
+    }
+}
+
+pub(crate) struct SpanFields(pub(crate) Vec<KeyValue>);
+
+pub(crate) struct SpanFieldVisitor<'a> {
+    pub(crate) span_name: &'a str,
+    pub(crate) rules: &'a SpanFieldRules,
+    pub(crate) fields: &'a mut Vec<KeyValue>,
+}
+
+impl SpanFieldVisitor<'_> {
+    fn set(&mut self, field: &Field, value: Value) {
+        if self.rules.excluded(self.span_name, field.name()) {
+            return;
+        }
+        let value = match value {
+            StringValue(value) => StringValue(self.rules.cap(value)),
+            value => value,
+        };
+        match self.fields.iter_mut().find(|existing| existing.key == field.name()) {
+            Some(existing) => *existing = attribute(field.name(), value),
+            None => self.fields.push(attribute(field.name(), value)),
+        }
+    }
+}
+
+impl Visit for SpanFieldVisitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.set(field, DoubleValue(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.set(field, IntValue(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.set(field, IntValue(value as i64));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.set(field, BoolValue(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.set(field, StringValue(value.to_string()));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.set(field, StringValue(format!("{:?}", value)));
+    }
+}
+
+// Adds the fields of the spans enclosing the event, up to `max_depth` of them counting
+// from the innermost. Keys the record already has win over span fields.
+pub(crate) fn inherit<S>(ctx: &Context<'_, S>, event: &Event<'_>, rules: &SpanFieldRules, attributes: &mut Vec<KeyValue>)
+    where S: Subscriber + for<'a> LookupSpan<'a>
+{
+    let Some(scope) = ctx.event_scope(event) else {
+        return;
+    };
+    let mut spans: Vec<_> = scope.take(rules.max_depth.unwrap_or(usize::MAX)).collect();
+    if rules.conflict == SpanFieldConflict::Outermost {
+        spans.reverse();
+    }
+    for span in spans {
+        let extensions = span.extensions();
+        let Some(fields) = extensions.get::<SpanFields>() else {
+            continue;
+        };
+        for field in &fields.0 {
+            if !attributes.iter().any(|attribute| attribute.key == field.key) {
+                attributes.push(field.clone());
+            } else if rules.conflict == SpanFieldConflict::Prefix {
+                let key = format!("{}.{}", span.name(), field.key);
+                if !attributes.iter().any(|attribute| attribute.key == key) {
+                    attributes.push(KeyValue { key, value: field.value.clone() });
+                }
+            }
+        }
+    }
+}
+
+pub(crate) fn glob_matches(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capped_values_stay_within_the_maximum() {
+        let rules = |max| SpanFieldRules { max_value_length: Some(max), ..SpanFieldRules::default() };
+        assert_eq!(rules(8).cap("abcdefghij".to_string()), "abcde…");
+        assert_eq!(rules(8).cap("abcdefgh".to_string()), "abcdefgh");
+        // 'é' is two bytes and would straddle the cut.
+        assert_eq!(rules(7).cap("abcdéfgh".to_string()), "abcd…");
+        assert_eq!(rules(2).cap("abc".to_string()), "ab");
+        for max in 0..12 {
+            assert!(rules(max).cap("aéb€cdéfghijk".to_string()).len() <= max);
+        }
+    }
+}
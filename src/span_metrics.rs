@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::clock::Clock;
+use crate::internal::{attribute, internal_record};
+use crate::opentelclient::any_value::Value::{IntValue, StringValue};
+use crate::opentelclient::LogRecord;
+
+// Durations kept per span name and interval; the count keeps going past it.
+const MAX_SAMPLES: usize = 10_000;
+
+/// How span durations are reported.
+#[derive(Clone, Copy, Debug)]
+pub enum SpanMetrics {
+    /// One internal record per closed span with its name and duration.
+    SpanEndRecords,
+    /// Per span name count/p50/p99/max, exported as internal records every interval.
+    Summary(Duration),
+}
+
+pub(crate) struct SpanTiming(pub(crate) Instant);
+
+#[derive(Default)]
+struct Samples {
+    count: u64,
+    durations: Vec<u64>,
+}
+
+#[derive(Default)]
+pub(crate) struct SpanStats {
+    samples: Mutex<HashMap<&'static str, Samples>>,
+}
+
+impl SpanStats {
+    pub(crate) fn record(&self, name: &'static str, duration: Duration) {
+        let mut samples = self.samples.lock().unwrap();
+        let samples = samples.entry(name).or_default();
+        samples.count += 1;
+        if samples.durations.len() < MAX_SAMPLES {
+            samples.durations.push(duration.as_nanos() as u64);
+        }
+    }
+
+    pub(crate) fn drain(&self, clock: &dyn Clock) -> Vec<LogRecord> {
+        let samples = std::mem::take(&mut *self.samples.lock().unwrap());
+        samples.into_iter()
+            .map(|(name, mut samples)| {
+                samples.durations.sort_unstable();
+                internal_record(clock, format!("span summary {}", name), vec![
+                    attribute("span.name", StringValue(name.to_string())),
+                    attribute("span.count", IntValue(samples.count as i64)),
+                    attribute("span.duration.p50_ns", IntValue(percentile(&samples.durations, 50) as i64)),
+                    attribute("span.duration.p99_ns", IntValue(percentile(&samples.durations, 99) as i64)),
+                    attribute("span.duration.max_ns", IntValue(samples.durations.last().copied().unwrap_or_default() as i64)),
+                ])
+            })
+            .collect()
+    }
+}
+
+fn percentile(sorted: &[u64], percentile: usize) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    sorted[(sorted.len() - 1) * percentile / 100]
+}
+
+pub(crate) fn span_end_record(clock: &dyn Clock, name: &'static str, duration: Duration) -> LogRecord {
+    internal_record(clock, format!("span closed {}", name), vec![
+        attribute("span.name", StringValue(name.to_string())),
+        attribute("span.duration_ns", IntValue(duration.as_nanos() as i64)),
+    ])
+}
@@ -0,0 +1,197 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tonic::Status;
+
+use crate::clock::{system_timestamps_clamped, Clock};
+use crate::internal::{attribute, internal_record};
+use crate::opentelclient::any_value::Value::StringValue;
+use crate::opentelclient::LogRecord;
+use crate::resolver::ConnectedPeer;
+
+/// Connectivity of the main export endpoint, as seen from export attempts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// No export has completed yet, or one is being attempted after a failure.
+    Connecting,
+    /// The last export reached the endpoint (even if it rejected the batch).
+    Ready,
+    /// The last export could not reach the endpoint.
+    TransientFailure,
+}
+
+impl ConnectionState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ConnectionState::Connecting => "connecting",
+            ConnectionState::Ready => "ready",
+            ConnectionState::TransientFailure => "transient_failure",
+        }
+    }
+}
+
+/// Snapshot of the exporter's state, from [`crate::TelescopeHandle::stats`].
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct TelescopeStats {
+    pub connection_state: ConnectionState,
+    /// Number of connection state changes so far.
+    pub state_changes: u64,
+    /// Error of the last failed export attempt.
+    pub last_error: Option<String>,
+    /// Request id (`x-telescope-request-id`) of the last failed export attempt.
+    pub last_error_request_id: Option<String>,
+    pub export_attempts: u64,
+    pub export_failures: u64,
+    /// Records dropped because an exporter's queue was full, see
+    /// [`crate::OverflowPolicy`], or because their batch was pushed out of the batches
+    /// set aside by [`crate::RetryExhausted::Requeue`].
+    pub records_dropped: u64,
+    /// Batches given up on after the retry policy's last attempt, see
+    /// [`crate::RetryPolicy::with_max_attempts`]. A requeued batch is counted once, however
+    /// often it fails again.
+    pub retries_exhausted: u64,
+    /// Ingestion quota reported by the server, see
+    /// [`crate::TelescopeLayerBuilder::with_quota_polling`].
+    pub quota: Option<IngestionQuota>,
+    /// Address the main endpoint last connected to, after name resolution and racing
+    /// its addresses. `None` until connected, and for channels and transports passed in,
+    /// as well as for endpoints passed in without a resolver or resolved addresses.
+    pub peer_address: Option<SocketAddr>,
+    /// Protocol of that connection: `h2` (HTTP/2 over TLS) or `h2c` (plaintext HTTP/2).
+    pub protocol: Option<&'static str>,
+    /// Timestamps [`crate::SystemClock`] moved forward because the system clock went
+    /// back, counted across every layer in the process.
+    pub timestamps_clamped: u64,
+}
+
+/// Ingestion quota of the service, as of the last poll of the server's quota endpoint and
+/// counted down by the records exported since.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct IngestionQuota {
+    pub remaining_records: u64,
+    pub limit_records: u64,
+    /// Time until the quota window resets.
+    pub resets_in: Duration,
+}
+
+pub(crate) struct ExportStats {
+    clock: Arc<dyn Clock>,
+    stats: Mutex<TelescopeStats>,
+    // Internal records about state changes, when enabled, waiting for the exporter.
+    events: Option<Mutex<(ConnectionState, Vec<LogRecord>)>>,
+    peer: Option<Arc<ConnectedPeer>>,
+}
+
+impl ExportStats {
+    pub(crate) fn new(clock: Arc<dyn Clock>, connection_events: bool) -> Self {
+        Self {
+            clock,
+            stats: Mutex::new(TelescopeStats {
+                connection_state: ConnectionState::Connecting,
+                state_changes: 0,
+                last_error: None,
+                last_error_request_id: None,
+                export_attempts: 0,
+                export_failures: 0,
+                records_dropped: 0,
+                retries_exhausted: 0,
+                quota: None,
+                peer_address: None,
+                protocol: None,
+                timestamps_clamped: 0,
+            }),
+            events: connection_events.then(|| Mutex::new((ConnectionState::Connecting, Vec::new()))),
+            peer: None,
+        }
+    }
+
+    pub(crate) fn with_peer(mut self, peer: Arc<ConnectedPeer>) -> Self {
+        self.peer = Some(peer);
+        self
+    }
+
+    pub(crate) fn snapshot(&self) -> TelescopeStats {
+        let mut stats = self.stats.lock().unwrap().clone();
+        if let Some((address, protocol)) = self.peer.as_ref().and_then(|peer| peer.get()) {
+            stats.peer_address = Some(address);
+            stats.protocol = Some(protocol);
+        }
+        stats.timestamps_clamped = system_timestamps_clamped();
+        stats
+    }
+
+    pub(crate) fn on_records_dropped(&self, records: u64) {
+        self.stats.lock().unwrap().records_dropped += records;
+    }
+
+    pub(crate) fn on_retries_exhausted(&self) {
+        self.stats.lock().unwrap().retries_exhausted += 1;
+    }
+
+    pub(crate) fn on_quota(&self, quota: IngestionQuota) {
+        self.stats.lock().unwrap().quota = Some(quota);
+    }
+
+    pub(crate) fn on_attempt(&self) {
+        let mut stats = self.stats.lock().unwrap();
+        stats.export_attempts += 1;
+        if stats.connection_state == ConnectionState::TransientFailure {
+            self.transition(&mut stats, ConnectionState::Connecting, None);
+        }
+    }
+
+    pub(crate) fn on_result<T>(&self, result: &Result<T, Status>, request_id: Option<&str>) {
+        let mut stats = self.stats.lock().unwrap();
+        match result {
+            Ok(_) => self.transition(&mut stats, ConnectionState::Ready, None),
+            Err(status) => {
+                stats.export_failures += 1;
+                stats.last_error = Some(format!("{:?}: {}", status.code(), status.message()));
+                stats.last_error_request_id = request_id.map(str::to_string);
+                // Anything but an unreachable endpoint means the server answered.
+                let state = match status.code() {
+                    tonic::Code::Unavailable | tonic::Code::Unknown | tonic::Code::DeadlineExceeded => ConnectionState::TransientFailure,
+                    _ => ConnectionState::Ready,
+                };
+                self.transition(&mut stats, state, Some((status.message(), request_id)));
+            }
+        }
+    }
+
+    fn transition(&self, stats: &mut TelescopeStats, state: ConnectionState, error: Option<(&str, Option<&str>)>) {
+        if stats.connection_state == state {
+            return;
+        }
+        stats.connection_state = state;
+        stats.state_changes += 1;
+        let Some(events) = &self.events else {
+            return;
+        };
+        // Only report settled states, and not the same one twice in a row, so a long
+        // outage doesn't produce a failure record per retry.
+        let mut events = events.lock().unwrap();
+        if state == ConnectionState::Connecting || events.0 == state {
+            return;
+        }
+        events.0 = state;
+        let mut attributes = vec![attribute("telescope.connection.state", StringValue(state.as_str().to_string()))];
+        if let Some((error, request_id)) = error {
+            attributes.push(attribute("telescope.connection.error", StringValue(error.to_string())));
+            if let Some(request_id) = request_id {
+                attributes.push(attribute("telescope.export.request_id", StringValue(request_id.to_string())));
+            }
+        }
+        let record = internal_record(self.clock.as_ref(), format!("telescope connection {}", state.as_str()), attributes);
+        events.1.push(record);
+    }
+
+    pub(crate) fn drain_events(&self) -> Vec<LogRecord> {
+        match &self.events {
+            Some(events) => std::mem::take(&mut events.lock().unwrap().1),
+            None => Vec::new(),
+        }
+    }
+}
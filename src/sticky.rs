@@ -0,0 +1,108 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::clock::Clock;
+use crate::internal::{attribute, internal_record};
+use crate::opentelclient::any_value::Value::{IntValue, StringValue};
+use crate::opentelclient::LogRecord;
+
+/// Export a target's DEBUG records for a while after a burst of errors in it.
+///
+/// Once `errors` ERROR records of one target arrive within `window`, that target is
+/// exported from DEBUG up for `duration`. With [`StickyDebug::with_buffered_records`]
+/// the most recent DEBUG records from before the burst are kept and exported with it.
+#[derive(Clone, Copy, Debug)]
+pub struct StickyDebug {
+    errors: usize,
+    window: Duration,
+    duration: Duration,
+    buffered_records: usize,
+}
+
+impl StickyDebug {
+    pub fn new(errors: usize, window: Duration, duration: Duration) -> Self {
+        Self {
+            errors: errors.max(1),
+            window,
+            duration,
+            buffered_records: 0,
+        }
+    }
+
+    /// Keep the last `records` DEBUG records per target while debug export is off.
+    pub fn with_buffered_records(mut self, records: usize) -> Self {
+        self.buffered_records = records;
+        self
+    }
+}
+
+#[derive(Default)]
+struct TargetState {
+    errors: VecDeque<Instant>,
+    debug_until: Option<Instant>,
+    buffer: VecDeque<LogRecord>,
+}
+
+pub(crate) struct StickyDebugState {
+    policy: StickyDebug,
+    targets: Mutex<HashMap<&'static str, TargetState>>,
+}
+
+impl StickyDebugState {
+    pub(crate) fn new(policy: StickyDebug) -> Self {
+        Self { policy, targets: Mutex::new(HashMap::new()) }
+    }
+
+    pub(crate) fn buffers_records(&self) -> bool {
+        self.policy.buffered_records > 0
+    }
+
+    pub(crate) fn is_active(&self, target: &str, now: Instant) -> bool {
+        self.targets.lock().unwrap()
+            .get(target)
+            .and_then(|state| state.debug_until)
+            .is_some_and(|until| now < until)
+    }
+
+    pub(crate) fn buffer(&self, target: &'static str, record: LogRecord) {
+        let mut targets = self.targets.lock().unwrap();
+        let buffer = &mut targets.entry(target).or_default().buffer;
+        if buffer.len() == self.policy.buffered_records {
+            buffer.pop_front();
+        }
+        buffer.push_back(record);
+    }
+
+    /// Count an error of `target`. When it completes a burst, debug export is switched
+    /// on and the records to export ahead of the error are returned: an internal record
+    /// announcing it, then the buffered lead-up.
+    pub(crate) fn on_error(&self, target: &'static str, clock: &dyn Clock) -> Vec<LogRecord> {
+        let now = clock.now();
+        let mut targets = self.targets.lock().unwrap();
+        let state = targets.entry(target).or_default();
+        while state.errors.front().is_some_and(|error| now.saturating_duration_since(*error) > self.policy.window) {
+            state.errors.pop_front();
+        }
+        state.errors.push_back(now);
+        if state.errors.len() < self.policy.errors {
+            return Vec::new();
+        }
+        state.errors.clear();
+        let was_active = state.debug_until.is_some_and(|until| now < until);
+        state.debug_until = Some(now + self.policy.duration);
+        if was_active {
+            return Vec::new();
+        }
+        let mut records = vec![internal_record(
+            clock,
+            format!("exporting DEBUG records of {target} for {:?} after {} errors", self.policy.duration, self.policy.errors),
+            vec![
+                attribute("telescope.sticky_debug.target", StringValue(target.to_string())),
+                attribute("telescope.sticky_debug.buffered", IntValue(state.buffer.len() as i64)),
+            ],
+        )];
+        records.extend(state.buffer.drain(..));
+        records
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::capabilities::ServerCapabilities;
+use crate::config::{field, optional};
+use crate::decode::{display, push_json_string};
+use crate::disk_queue::{crc32, DiskQueueStats};
+use crate::opentelclient::any_value::Value;
+use crate::opentelclient::{AnyValue, LogRecord};
+use crate::stats::TelescopeStats;
+use crate::trace_context::hex;
+
+// How many records of each kind a support bundle shows at most.
+const RECENT_RECORDS: usize = 50;
+// Records kept from each exported batch, so one busy batch doesn't push out the others.
+const RECORDS_PER_BATCH: usize = 5;
+
+// The main exporter's recent connection events and runtime metrics, and a few records of
+// every batch it exported, for support bundles. Kept as the JSON lines the bundle
+// shows, so exported records are redacted right away and their values never copied.
+#[derive(Default)]
+pub(crate) struct RecentRecords {
+    internal: Mutex<VecDeque<String>>,
+    exported: Mutex<VecDeque<String>>,
+}
+
+impl RecentRecords {
+    pub(crate) fn on_internal(&self, records: &[LogRecord]) {
+        keep(&mut self.internal.lock().unwrap(), records, false);
+    }
+
+    pub(crate) fn on_batch(&self, records: &[LogRecord]) {
+        keep(&mut self.exported.lock().unwrap(), &records[records.len().saturating_sub(RECORDS_PER_BATCH)..], true);
+    }
+}
+
+fn keep(recent: &mut VecDeque<String>, records: &[LogRecord], redact: bool) {
+    recent.extend(records.iter().map(|record| line(record, redact)));
+    let excess = recent.len().saturating_sub(RECENT_RECORDS);
+    recent.drain(..excess);
+}
+
+// Everything `TelescopeHandle::support_bundle` puts in the zip.
+pub(crate) struct SupportBundle {
+    pub(crate) config: String,
+    pub(crate) stats: TelescopeStats,
+    pub(crate) capabilities: ServerCapabilities,
+    pub(crate) disk_queue: Option<io::Result<DiskQueueStats>>,
+}
+
+impl SupportBundle {
+    pub(crate) fn write(&self, path: &Path, recent: &RecentRecords) -> io::Result<()> {
+        let internal = recent.internal.lock().unwrap().iter().map(String::as_str).collect::<String>();
+        let exported = recent.exported.lock().unwrap().iter().map(String::as_str).collect::<String>();
+        let mut zip = ZipWriter::new(BufWriter::new(File::create(path)?));
+        zip.add("config.json", self.config.as_bytes())?;
+        zip.add("stats.json", self.stats_json().as_bytes())?;
+        zip.add("diagnostics.jsonl", internal.as_bytes())?;
+        zip.add("records.jsonl", exported.as_bytes())?;
+        zip.finish()?.flush()
+    }
+
+    fn stats_json(&self) -> String {
+        let stats = &self.stats;
+        let mut json = String::from("{");
+        field(&mut json, "connection_state");
+        push_json_string(&mut json, &format!("{:?}", stats.connection_state));
+        let _ = write!(json, ",\"state_changes\":{}", stats.state_changes);
+        field(&mut json, "last_error");
+        optional(&mut json, stats.last_error.as_deref(), push_json_string);
+        field(&mut json, "last_error_request_id");
+        optional(&mut json, stats.last_error_request_id.as_deref(), push_json_string);
+        let _ = write!(json, ",\"export_attempts\":{},\"export_failures\":{},\"records_dropped\":{},\"retries_exhausted\":{},\"timestamps_clamped\":{}",
+                       stats.export_attempts, stats.export_failures, stats.records_dropped, stats.retries_exhausted,
+                       stats.timestamps_clamped);
+        field(&mut json, "quota");
+        optional(&mut json, stats.quota, |json, quota| {
+            let _ = write!(json, "{{\"remaining_records\":{},\"limit_records\":{},\"resets_in_ms\":{}}}",
+                           quota.remaining_records, quota.limit_records, quota.resets_in.as_millis());
+        });
+        field(&mut json, "peer_address");
+        optional(&mut json, stats.peer_address, |json, address| push_json_string(json, &address.to_string()));
+        field(&mut json, "protocol");
+        optional(&mut json, stats.protocol, push_json_string);
+        field(&mut json, "max_batch_records");
+        optional(&mut json, self.capabilities.max_batch_records, |json, max| {
+            let _ = write!(json, "{max}");
+        });
+        field(&mut json, "dictionary_version");
+        optional(&mut json, self.capabilities.dictionary_version, |json, version| {
+            let _ = write!(json, "{version}");
+        });
+        field(&mut json, "disk_queue");
+        match &self.disk_queue {
+            Some(Ok(queue)) => {
+                let _ = write!(json, "{{\"queued_batches\":{},\"queued_bytes\":{},\"corrupted_batches\":{}}}",
+                               queue.queued_batches, queue.queued_bytes, queue.corrupted_batches);
+            }
+            Some(Err(error)) => {
+                json.push_str("{\"error\":");
+                push_json_string(&mut json, &error.to_string());
+                json.push('}');
+            }
+            None => json.push_str("null"),
+        }
+        json.push('}');
+        json
+    }
+}
+
+// A JSON object on a line of its own. Redacted records keep their shape (timestamps,
+// severity, trace context, attribute keys and value types) but none of the logged values.
+fn line(record: &LogRecord, redact: bool) -> String {
+    let mut line = String::from("{");
+    let _ = write!(line, "\"time_unix_nano\":{},\"severity\":", record.time_unix_nano);
+    push_json_string(&mut line, &record.severity_text);
+    if !record.trace_id.is_empty() {
+        field(&mut line, "trace_id");
+        push_json_string(&mut line, &hex(&record.trace_id));
+    }
+    if !record.span_id.is_empty() {
+        field(&mut line, "span_id");
+        push_json_string(&mut line, &hex(&record.span_id));
+    }
+    field(&mut line, "body");
+    optional(&mut line, record.body.as_ref(), |json, body| value(json, body, redact));
+    field(&mut line, "attributes");
+    line.push('{');
+    for attribute in &record.attributes {
+        field(&mut line, &attribute.key);
+        optional(&mut line, attribute.value.as_ref(), |json, attribute| value(json, attribute, redact));
+    }
+    line.push_str("}}\n");
+    line
+}
+
+fn value(json: &mut String, value: &AnyValue, redact: bool) {
+    if !redact {
+        push_json_string(json, &display(value));
+        return;
+    }
+    let redacted = match &value.value {
+        Some(Value::StringValue(text)) => format!("<string, {} bytes>", text.len()),
+        Some(Value::BytesValue(bytes)) => format!("<bytes, {} bytes>", bytes.len()),
+        Some(Value::BoolValue(_)) => "<bool>".to_string(),
+        Some(Value::IntValue(_)) => "<int>".to_string(),
+        Some(Value::DoubleValue(_)) => "<double>".to_string(),
+        Some(Value::ArrayValue(array)) => format!("<array, {} values>", array.values.len()),
+        Some(Value::KvlistValue(list)) => format!("<map, {} entries>", list.values.len()),
+        None => "<empty>".to_string(),
+    };
+    push_json_string(json, &redacted);
+}
+
+// Just enough of the zip format for a few small text files: stored (uncompressed)
+// entries, no zip64, no timestamps.
+struct ZipWriter<W> {
+    out: W,
+    offset: u32,
+    central: Vec<u8>,
+    entries: u16,
+}
+
+impl<W: Write> ZipWriter<W> {
+    fn new(out: W) -> Self {
+        Self { out, offset: 0, central: Vec::new(), entries: 0 }
+    }
+
+    fn add(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        let crc = crc32(data);
+        let size = u32::try_from(data.len()).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("{name} is too large")))?;
+        // Version 2.0, no flags, stored, zero time and date.
+        let common = [&20u16.to_le_bytes()[..], &0u16.to_le_bytes(), &0u16.to_le_bytes(), &0u32.to_le_bytes(),
+            &crc.to_le_bytes(), &size.to_le_bytes(), &size.to_le_bytes(), &(name.len() as u16).to_le_bytes(), &0u16.to_le_bytes()].concat();
+        let mut local = 0x04034b50u32.to_le_bytes().to_vec();
+        local.extend_from_slice(&common);
+        local.extend_from_slice(name.as_bytes());
+        self.out.write_all(&local)?;
+        self.out.write_all(data)?;
+
+        self.central.extend_from_slice(&0x02014b50u32.to_le_bytes());
+        self.central.extend_from_slice(&20u16.to_le_bytes());
+        self.central.extend_from_slice(&common);
+        // Comment length, disk number, internal and external attributes.
+        self.central.extend_from_slice(&[0; 10]);
+        self.central.extend_from_slice(&self.offset.to_le_bytes());
+        self.central.extend_from_slice(name.as_bytes());
+        self.offset += local.len() as u32 + size;
+        self.entries += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<W> {
+        self.out.write_all(&self.central)?;
+        let mut end = 0x06054b50u32.to_le_bytes().to_vec();
+        end.extend_from_slice(&[0; 4]);
+        end.extend_from_slice(&self.entries.to_le_bytes());
+        end.extend_from_slice(&self.entries.to_le_bytes());
+        end.extend_from_slice(&(self.central.len() as u32).to_le_bytes());
+        end.extend_from_slice(&self.offset.to_le_bytes());
+        end.extend_from_slice(&0u16.to_le_bytes());
+        self.out.write_all(&end)?;
+        Ok(self.out)
+    }
+}
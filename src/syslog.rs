@@ -0,0 +1,240 @@
+use std::io::{self, Write};
+use std::net::{TcpStream, UdpSocket};
+#[cfg(feature = "syslog-tls")]
+use std::sync::Arc;
+
+use tower::BoxError;
+
+use crate::decode::{body, display, DecodedBatch, string_attribute, syslog_severity};
+use crate::opentelclient::{KeyValue, LogRecord};
+use crate::service::ExportRequest;
+use crate::sink::Sink;
+use crate::trace_context::hex;
+
+enum Transport {
+    Udp(String),
+    Tcp(String),
+    #[cfg(feature = "syslog-tls")]
+    Tls {
+        address: String,
+        server_name: String,
+        config: Arc<rustls::ClientConfig>,
+    },
+}
+
+enum Connection {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+    #[cfg(feature = "syslog-tls")]
+    Tls(Box<rustls::StreamOwned<rustls::ClientConnection, TcpStream>>),
+}
+
+/// Sends every record to a syslog relay as an RFC 5424 message. Record attributes (and
+/// the trace context) become parameters of one structured data element, by default
+/// `[otel@32473 ...]`. UDP sends one datagram per message; TCP and TLS use octet
+/// counting framing (RFC 6587 / RFC 5425).
+///
+/// Typically added as a route with [`crate::TelescopeLayerBuilder::with_route_sink`] or
+/// as the destination of a second layer, to duplicate records to the relay.
+pub struct SyslogSink {
+    transport: Transport,
+    connection: Option<Connection>,
+    facility: u8,
+    hostname: Option<String>,
+    app_name: Option<String>,
+    sd_id: String,
+}
+
+impl SyslogSink {
+    pub fn udp(address: impl Into<String>) -> Self {
+        Self::new(Transport::Udp(address.into()))
+    }
+
+    pub fn tcp(address: impl Into<String>) -> Self {
+        Self::new(Transport::Tcp(address.into()))
+    }
+
+    /// TCP with TLS, verifying the relay's certificate as `server_name` with `config`.
+    #[cfg(feature = "syslog-tls")]
+    pub fn tls(address: impl Into<String>, server_name: impl Into<String>, config: Arc<rustls::ClientConfig>) -> Self {
+        Self::new(Transport::Tls { address: address.into(), server_name: server_name.into(), config })
+    }
+
+    fn new(transport: Transport) -> Self {
+        Self {
+            transport,
+            connection: None,
+            facility: 1,
+            hostname: None,
+            app_name: None,
+            sd_id: "otel@32473".to_string(),
+        }
+    }
+
+    /// Syslog facility code, `1` (user-level) by default.
+    pub fn with_facility(mut self, facility: u8) -> Self {
+        self.facility = facility.min(23);
+        self
+    }
+
+    /// HOSTNAME of every message. Defaults to the `host.name` resource attribute.
+    pub fn with_hostname(mut self, hostname: impl Into<String>) -> Self {
+        self.hostname = Some(hostname.into());
+        self
+    }
+
+    /// APP-NAME of every message. Defaults to the service name.
+    pub fn with_app_name(mut self, app_name: impl Into<String>) -> Self {
+        self.app_name = Some(app_name.into());
+        self
+    }
+
+    /// SD-ID of the structured data element carrying the attributes.
+    pub fn with_structured_data_id(mut self, sd_id: impl Into<String>) -> Self {
+        self.sd_id = sd_id.into();
+        self
+    }
+
+    fn connect(&self) -> io::Result<Connection> {
+        Ok(match &self.transport {
+            Transport::Udp(address) => {
+                let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+                socket.connect(address)?;
+                Connection::Udp(socket)
+            }
+            Transport::Tcp(address) => Connection::Tcp(TcpStream::connect(address)?),
+            #[cfg(feature = "syslog-tls")]
+            Transport::Tls { address, server_name, config } => {
+                let server_name = rustls::pki_types::ServerName::try_from(server_name.clone())
+                    .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+                let connection = rustls::ClientConnection::new(config.clone(), server_name)
+                    .map_err(io::Error::other)?;
+                Connection::Tls(Box::new(rustls::StreamOwned::new(connection, TcpStream::connect(address)?)))
+            }
+        })
+    }
+
+    fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        let mut connection = match self.connection.take() {
+            Some(connection) => connection,
+            None => self.connect()?,
+        };
+        let result = match &mut connection {
+            Connection::Udp(socket) => socket.send(message).map(|_| ()),
+            Connection::Tcp(stream) => write_frame(stream, message),
+            #[cfg(feature = "syslog-tls")]
+            Connection::Tls(stream) => write_frame(stream, message),
+        };
+        // Reconnect on the next message after a failure.
+        if result.is_ok() {
+            self.connection = Some(connection);
+        }
+        result
+    }
+
+    fn message(&self, resource: &[KeyValue], record: &LogRecord) -> String {
+        let priority = self.facility * 8 + syslog_severity(record.severity_number);
+        let timestamp = match record.time_unix_nano {
+            0 => "-".to_string(),
+            time => rfc3339(time),
+        };
+        let hostname = self.hostname.as_deref().or_else(|| string_attribute(resource, "host.name"));
+        let app_name = self.app_name.as_deref().or_else(|| string_attribute(resource, "service.name"));
+        format!(
+            "<{priority}>1 {timestamp} {} {} {} - {} {}",
+            header_field(hostname, 255),
+            header_field(app_name, 48),
+            std::process::id(),
+            self.structured_data(record),
+            body(record),
+        )
+    }
+
+    fn structured_data(&self, record: &LogRecord) -> String {
+        let mut params = Vec::new();
+        if !record.trace_id.is_empty() {
+            params.push(("trace_id".to_string(), hex(&record.trace_id)));
+            params.push(("span_id".to_string(), hex(&record.span_id)));
+        }
+        for attribute in &record.attributes {
+            if let (Some(name), Some(value)) = (param_name(&attribute.key), &attribute.value) {
+                params.push((name, display(value)));
+            }
+        }
+        if params.is_empty() {
+            return "-".to_string();
+        }
+        let mut element = format!("[{}", self.sd_id);
+        for (name, value) in params {
+            element.push(' ');
+            element.push_str(&name);
+            element.push_str("=\"");
+            for c in value.chars() {
+                if matches!(c, '"' | '\\' | ']') {
+                    element.push('\\');
+                }
+                element.push(c);
+            }
+            element.push('"');
+        }
+        element.push(']');
+        element
+    }
+}
+
+impl Sink for SyslogSink {
+    fn export(&mut self, request: &ExportRequest) -> Result<(), BoxError> {
+        let batch = DecodedBatch::decode(&request.payload)?;
+        for (resource, record) in batch.records() {
+            let message = self.message(resource, record);
+            self.send(message.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+fn write_frame(stream: &mut impl Write, message: &[u8]) -> io::Result<()> {
+    write!(stream, "{} ", message.len())?;
+    stream.write_all(message)?;
+    stream.flush()
+}
+
+// Header fields are printable US-ASCII without spaces, `-` when unknown.
+fn header_field(value: Option<&str>, max_len: usize) -> String {
+    let value: String = value.unwrap_or_default().chars()
+        .filter(|c| c.is_ascii_graphic())
+        .take(max_len)
+        .collect();
+    if value.is_empty() { "-".to_string() } else { value }
+}
+
+fn param_name(key: &str) -> Option<String> {
+    let name: String = key.chars()
+        .filter(|c| c.is_ascii_graphic() && !matches!(c, '=' | ']' | '"'))
+        .take(32)
+        .collect();
+    (!name.is_empty()).then_some(name)
+}
+
+/// RFC 3339 UTC timestamp with microseconds, e.g. `2024-05-01T12:00:00.000000Z`.
+fn rfc3339(unix_nano: u64) -> String {
+    let secs = unix_nano / 1_000_000_000;
+    let micros = unix_nano % 1_000_000_000 / 1000;
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let secs_of_day = secs % 86_400;
+    format!("{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{micros:06}Z", secs_of_day / 3600, secs_of_day / 60 % 60, secs_of_day % 60)
+}
+
+// Days since the unix epoch to a proleptic Gregorian date (Howard Hinnant's algorithm).
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
@@ -0,0 +1,315 @@
+use std::io::{self, BufRead, BufReader, Read};
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use crate::clock::Clock;
+use crate::internal::attribute;
+use crate::opentelclient::any_value::Value::{IntValue, StringValue};
+use crate::opentelclient::{AnyValue, KeyValue, LogRecord};
+use crate::routing::RecordSender;
+use crate::timestamp::{days_from_civil, parse_rfc3339};
+
+// Messages longer than this are dropped on TCP, where the length comes from the peer.
+const MAX_MESSAGE_LEN: usize = 64 * 1024;
+// Digits of MAX_MESSAGE_LEN and the space after them.
+const MAX_LENGTH_PREFIX: u64 = 6;
+// Connections each TCP address serves at once, one thread each; more are closed right
+// away.
+const MAX_CONNECTIONS: usize = 256;
+
+/// Receives syslog messages from network devices and appliances and exports them
+/// through the layer's pipeline. Both RFC 5424 and the older BSD format (RFC 3164) are
+/// parsed; over TCP messages may be newline delimited or octet counted (RFC 6587). Get
+/// one from [`crate::TelescopeLayer::syslog_listener`].
+///
+/// The syslog severity maps to the record severity. Facility, hostname, app name,
+/// process id, message id and the sender's address become `syslog.*` and
+/// `client.address` attributes, structured data parameters
+/// `syslog.sd.<id>.<name>`. Messages without a usable timestamp are stamped with the
+/// time they arrived; BSD timestamps are taken as UTC.
+///
+/// Each TCP address serves up to 256 connections at once and closes any beyond that.
+pub struct SyslogListener {
+    sender: RecordSender,
+    clock: Arc<dyn Clock>,
+    udp: Vec<String>,
+    tcp: Vec<String>,
+    target: String,
+}
+
+impl SyslogListener {
+    pub(crate) fn new(sender: RecordSender, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            sender,
+            clock,
+            udp: Vec::new(),
+            tcp: Vec::new(),
+            target: "syslog".to_string(),
+        }
+    }
+
+    /// Listen for datagrams on `address`, e.g. `0.0.0.0:514`.
+    pub fn with_udp(mut self, address: impl Into<String>) -> Self {
+        self.udp.push(address.into());
+        self
+    }
+
+    pub fn with_tcp(mut self, address: impl Into<String>) -> Self {
+        self.tcp.push(address.into());
+        self
+    }
+
+    /// Target used for routing and record filters, `syslog` by default.
+    pub fn with_target(mut self, target: impl Into<String>) -> Self {
+        self.target = target.into();
+        self
+    }
+
+    /// Bind every address and start receiving on background threads.
+    pub fn start(self) -> io::Result<()> {
+        let udp = self.udp.iter().map(UdpSocket::bind).collect::<io::Result<Vec<_>>>()?;
+        let tcp = self.tcp.iter().map(TcpListener::bind).collect::<io::Result<Vec<_>>>()?;
+        let listener = Arc::new(self);
+        for socket in udp {
+            let listener = listener.clone();
+            thread::Builder::new().name("telescope-syslog-udp".to_string()).spawn(move || {
+                let mut buf = vec![0; MAX_MESSAGE_LEN];
+                loop {
+                    if let Ok((len, peer)) = socket.recv_from(&mut buf) {
+                        listener.receive(&buf[..len], peer);
+                    }
+                }
+            })?;
+        }
+        for tcp in tcp {
+            let listener = listener.clone();
+            thread::Builder::new().name("telescope-syslog-tcp".to_string()).spawn(move || {
+                let connections = Arc::new(AtomicUsize::new(0));
+                for stream in tcp.incoming().flatten() {
+                    if connections.fetch_add(1, Ordering::Relaxed) >= MAX_CONNECTIONS {
+                        connections.fetch_sub(1, Ordering::Relaxed);
+                        continue;
+                    }
+                    let listener = listener.clone();
+                    let served = connections.clone();
+                    let spawned = thread::Builder::new().name("telescope-syslog-tcp".to_string()).spawn(move || {
+                        listener.serve(stream);
+                        served.fetch_sub(1, Ordering::Relaxed);
+                    });
+                    if spawned.is_err() {
+                        connections.fetch_sub(1, Ordering::Relaxed);
+                    }
+                }
+            })?;
+        }
+        Ok(())
+    }
+
+    // Reads messages off one connection until the peer closes it or sends garbage.
+    fn serve(&self, stream: TcpStream) {
+        let Ok(peer) = stream.peer_addr() else {
+            return;
+        };
+        let mut reader = BufReader::new(stream);
+        loop {
+            let Ok(buf) = reader.fill_buf() else {
+                return;
+            };
+            let Some(&first) = buf.first() else {
+                return;
+            };
+            let message = if first.is_ascii_digit() {
+                // Octet counting: `<length> <message>`.
+                let mut length = Vec::new();
+                if (&mut reader).take(MAX_LENGTH_PREFIX).read_until(b' ', &mut length).is_err() {
+                    return;
+                }
+                let Some(length) = length.strip_suffix(b" ")
+                    .and_then(|length| std::str::from_utf8(length).ok())
+                    .and_then(|length| length.parse::<usize>().ok())
+                    .filter(|length| *length <= MAX_MESSAGE_LEN) else {
+                    return;
+                };
+                let mut message = vec![0; length];
+                if reader.read_exact(&mut message).is_err() {
+                    return;
+                }
+                message
+            } else {
+                let mut message = Vec::new();
+                match (&mut reader).take(MAX_MESSAGE_LEN as u64).read_until(b'\n', &mut message) {
+                    Ok(0) | Err(_) => return,
+                    Ok(_) => message,
+                }
+            };
+            self.receive(&message, peer);
+        }
+    }
+
+    fn receive(&self, message: &[u8], peer: SocketAddr) {
+        let message = String::from_utf8_lossy(message);
+        let now = self.clock.now_unix_nano();
+        let mut record = parse(message.trim_end_matches(['\n', '\r', '\0']), now);
+        record.attributes.push(attribute("client.address", StringValue(peer.ip().to_string())));
+        self.sender.send(&self.target, record);
+    }
+}
+
+fn parse(message: &str, now: u64) -> LogRecord {
+    let mut attributes = Vec::new();
+    let (priority, rest) = match parse_priority(message) {
+        Some((priority, rest)) => (priority, rest),
+        // RFC 3164: a message without PRI is user.notice.
+        None => (13, message),
+    };
+    attributes.push(attribute("syslog.facility", IntValue(i64::from(priority / 8))));
+    let (time, body) = match rest.strip_prefix("1 ") {
+        Some(rest) => parse_rfc5424(rest, &mut attributes),
+        None => parse_rfc3164(rest, now, &mut attributes),
+    };
+    let (severity_number, severity_text) = severity(priority % 8);
+    LogRecord {
+        time_unix_nano: time.unwrap_or(now),
+        observed_time_unix_nano: now,
+        severity_number,
+        severity_text: severity_text.to_string(),
+        body: Some(AnyValue { value: Some(StringValue(body.to_string())) }),
+        attributes,
+        dropped_attributes_count: 0,
+        flags: 0,
+        trace_id: vec![],
+        span_id: vec![],
+    }
+}
+
+fn parse_priority(message: &str) -> Option<(u8, &str)> {
+    let rest = message.strip_prefix('<')?;
+    let end = rest.find('>')?;
+    let priority = rest[..end].parse().ok().filter(|priority| *priority < 192)?;
+    Some((priority, &rest[end + 1..]))
+}
+
+// syslog severity to the OTLP severity number and text.
+fn severity(severity: u8) -> (i32, &'static str) {
+    match severity {
+        0..=2 => (21, "FATAL"),
+        3 => (17, "ERROR"),
+        4 => (13, "WARN"),
+        5 => (10, "INFO"),
+        6 => (9, "INFO"),
+        _ => (5, "DEBUG"),
+    }
+}
+
+// `TIMESTAMP HOSTNAME APP-NAME PROCID MSGID STRUCTURED-DATA [MSG]`, `-` for nil values.
+fn parse_rfc5424<'a>(rest: &'a str, attributes: &mut Vec<KeyValue>) -> (Option<u64>, &'a str) {
+    let mut fields = rest.splitn(6, ' ');
+    let time = fields.next().and_then(parse_rfc3339);
+    for key in ["syslog.hostname", "syslog.appname", "syslog.procid", "syslog.msgid"] {
+        if let Some(value) = fields.next().filter(|value| *value != "-") {
+            attributes.push(attribute(key, StringValue(value.to_string())));
+        }
+    }
+    let rest = fields.next().unwrap_or_default();
+    let message = match rest.strip_prefix('-') {
+        Some(message) => message,
+        None => parse_structured_data(rest, attributes),
+    };
+    let message = message.strip_prefix(' ').unwrap_or(message);
+    (time, message.strip_prefix('\u{feff}').unwrap_or(message))
+}
+
+// `[id name="value" ...][id ...]`, returns what follows the last element.
+fn parse_structured_data<'a>(mut rest: &'a str, attributes: &mut Vec<KeyValue>) -> &'a str {
+    while let Some(element) = rest.strip_prefix('[') {
+        let id_end = element.find([' ', ']']).unwrap_or(element.len());
+        let id = &element[..id_end];
+        let mut params = &element[id_end..];
+        loop {
+            params = params.trim_start_matches(' ');
+            if let Some(after) = params.strip_prefix(']') {
+                rest = after;
+                break;
+            }
+            let Some((name, value)) = params.split_once("=\"") else {
+                return params;
+            };
+            let mut unescaped = String::new();
+            let mut chars = value.char_indices();
+            let mut end = None;
+            while let Some((index, char)) = chars.next() {
+                match char {
+                    '\\' => unescaped.extend(chars.next().map(|(_, escaped)| escaped)),
+                    '"' => {
+                        end = Some(index + 1);
+                        break;
+                    }
+                    char => unescaped.push(char),
+                }
+            }
+            let Some(end) = end else {
+                return "";
+            };
+            attributes.push(attribute(&format!("syslog.sd.{id}.{name}"), StringValue(unescaped)));
+            params = &value[end..];
+        }
+    }
+    rest
+}
+
+// `Mmm dd hh:mm:ss HOSTNAME TAG[PID]: MSG`. Everything that doesn't fit is the message.
+fn parse_rfc3164<'a>(rest: &'a str, now: u64, attributes: &mut Vec<KeyValue>) -> (Option<u64>, &'a str) {
+    let Some(time) = rest.get(..15).and_then(|time| parse_bsd_timestamp(time, now)) else {
+        return (None, rest);
+    };
+    let rest = rest[15..].trim_start_matches(' ');
+    let Some((hostname, message)) = rest.split_once(' ') else {
+        return (Some(time), rest);
+    };
+    attributes.push(attribute("syslog.hostname", StringValue(hostname.to_string())));
+    let tag_end = message.find(|c: char| !(c.is_alphanumeric() || "-_./[]".contains(c))).unwrap_or(0);
+    let Some(after_tag) = message[tag_end..].strip_prefix(':') else {
+        return (Some(time), message);
+    };
+    let tag = &message[..tag_end];
+    match tag.split_once('[') {
+        Some((app_name, pid)) => {
+            attributes.push(attribute("syslog.appname", StringValue(app_name.to_string())));
+            attributes.push(attribute("syslog.procid", StringValue(pid.trim_end_matches(']').to_string())));
+        }
+        None => attributes.push(attribute("syslog.appname", StringValue(tag.to_string()))),
+    }
+    (Some(time), after_tag.trim_start_matches(' '))
+}
+
+// `Oct  6 14:03:09`, in the current year (or the previous one for a date that would
+// otherwise be more than a day in the future, around new year).
+fn parse_bsd_timestamp(time: &str, now: u64) -> Option<u64> {
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+    let month = MONTHS.iter().position(|month| time.starts_with(month))? as i64 + 1;
+    let day: i64 = time.get(4..6)?.trim_start().parse().ok()?;
+    let hour: i64 = time.get(7..9)?.parse().ok()?;
+    let minute: i64 = time.get(10..12)?.parse().ok()?;
+    let second: i64 = time.get(13..15)?.parse().ok()?;
+    let now_secs = (now / 1_000_000_000) as i64;
+    let year = current_year(now_secs);
+    let at = |year| days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second;
+    let mut secs = at(year);
+    if secs > now_secs + 86_400 {
+        secs = at(year - 1);
+    }
+    u64::try_from(secs).ok().map(|secs| secs * 1_000_000_000)
+}
+
+fn current_year(now_secs: i64) -> i64 {
+    let mut year = 1970 + now_secs / 31_556_952;
+    while days_from_civil(year + 1, 1, 1) * 86_400 <= now_secs {
+        year += 1;
+    }
+    while days_from_civil(year, 1, 1) * 86_400 > now_secs {
+        year -= 1;
+    }
+    year
+}
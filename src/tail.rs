@@ -0,0 +1,107 @@
+use std::time::{Duration, Instant};
+
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::{LookupSpan, SpanRef};
+
+use crate::opentelclient::LogRecord;
+use crate::span_fields::glob_matches;
+
+/// Hold back the records of request spans until the span closes, then export all of
+/// them (DEBUG and TRACE included) if the request failed or was slow, and only the
+/// records that would have been exported anyway otherwise.
+///
+/// A request failed when any of its records is an ERROR. Spans are matched by name; the
+/// innermost matching span enclosing a record buffers it.
+#[derive(Clone, Debug)]
+pub struct TailBuffering {
+    span_pattern: String,
+    latency_threshold: Option<Duration>,
+    max_records: usize,
+}
+
+impl TailBuffering {
+    /// Buffer the records of spans whose name matches `span_pattern` (`*` wildcards).
+    pub fn new(span_pattern: impl Into<String>) -> Self {
+        Self {
+            span_pattern: span_pattern.into(),
+            latency_threshold: None,
+            max_records: 1000,
+        }
+    }
+
+    /// Also export everything for spans that were open longer than `threshold`.
+    pub fn with_latency_threshold(mut self, threshold: Duration) -> Self {
+        self.latency_threshold = Some(threshold);
+        self
+    }
+
+    /// Records held per span, 1000 by default. Once full, records that would be exported
+    /// anyway are sent right away and the rest are dropped.
+    pub fn with_max_records(mut self, records: usize) -> Self {
+        self.max_records = records;
+        self
+    }
+
+    pub(crate) fn matches(&self, span_name: &str) -> bool {
+        glob_matches(&self.span_pattern, span_name)
+    }
+
+    pub(crate) fn max_records(&self) -> usize {
+        self.max_records
+    }
+}
+
+pub(crate) struct TailBuffer {
+    started: Instant,
+    failed: bool,
+    // (target, record, whether it passes the export filter on its own)
+    records: Vec<(&'static str, LogRecord, bool)>,
+}
+
+impl TailBuffer {
+    pub(crate) fn new(started: Instant) -> Self {
+        Self { started, failed: false, records: Vec::new() }
+    }
+
+    /// The records to export now that the span closed at `now`.
+    pub(crate) fn finish(self, policy: &TailBuffering, now: Instant) -> impl Iterator<Item=(&'static str, LogRecord)> {
+        let slow = policy.latency_threshold.is_some_and(|threshold| now.saturating_duration_since(self.started) > threshold);
+        let keep_all = self.failed || slow;
+        self.records.into_iter()
+            .filter(move |(_, _, exported)| keep_all || *exported)
+            .map(|(target, record, _)| (target, record))
+    }
+}
+
+fn buffering_span<'a, S>(ctx: &'a Context<'_, S>, event: &Event<'_>) -> Option<SpanRef<'a, S>>
+    where S: Subscriber + for<'b> LookupSpan<'b>
+{
+    ctx.event_scope(event)?.find(|span| span.extensions().get::<TailBuffer>().is_some())
+}
+
+pub(crate) fn has_room<S>(ctx: &Context<'_, S>, event: &Event<'_>, max_records: usize) -> bool
+    where S: Subscriber + for<'a> LookupSpan<'a>
+{
+    buffering_span(ctx, event)
+        .is_some_and(|span| span.extensions().get::<TailBuffer>().is_some_and(|buffer| buffer.records.len() < max_records))
+}
+
+// Holds `record` in the enclosing buffering span, or hands it back when there is none
+// or its buffer is full.
+pub(crate) fn buffer<S>(ctx: &Context<'_, S>, event: &Event<'_>, max_records: usize, exported: bool, record: LogRecord) -> Option<LogRecord>
+    where S: Subscriber + for<'a> LookupSpan<'a>
+{
+    let Some(span) = buffering_span(ctx, event) else {
+        return Some(record);
+    };
+    let mut extensions = span.extensions_mut();
+    let buffer = extensions.get_mut::<TailBuffer>()?;
+    // An error past the cap still marks the span failed, so what was buffered goes out.
+    buffer.failed |= record.severity_number >= 17;
+    if buffer.records.len() >= max_records {
+        return Some(record);
+    }
+    buffer.records.push((event.metadata().target(), record, exported));
+    None
+}
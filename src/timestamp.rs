@@ -0,0 +1,55 @@
+// `2024-05-01T12:00:00.123456789Z` or with a `+hh:mm`/`-hh:mm` offset, to nanoseconds
+// since the unix epoch.
+pub(crate) fn parse_rfc3339(time: &str) -> Option<u64> {
+    let number = |range: std::ops::Range<usize>| time.get(range).and_then(|digits| digits.parse::<i64>().ok());
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+    let rest = time.get(19..)?;
+    let (fraction, zone) = match rest.strip_prefix('.') {
+        Some(rest) => rest.split_at(rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len())),
+        None => ("", rest),
+    };
+    let nanos = format!("{:0<9}", &fraction[..fraction.len().min(9)]).parse::<i64>().ok()?;
+    let offset_secs = match zone {
+        "Z" | "z" => 0,
+        zone if zone.len() == 6 && (zone.starts_with('+') || zone.starts_with('-')) => {
+            let hours: i64 = zone.get(1..3)?.parse().ok()?;
+            let minutes: i64 = zone.get(4..6)?.parse().ok()?;
+            let offset = hours * 3600 + minutes * 60;
+            if zone.starts_with('-') { -offset } else { offset }
+        }
+        _ => return None,
+    };
+    let secs = days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second - offset_secs;
+    u64::try_from(secs.checked_mul(1_000_000_000)?.checked_add(nanos)?).ok()
+}
+
+// Proleptic Gregorian date to days since the unix epoch (Howard Hinnant's algorithm).
+pub(crate) fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_rfc3339;
+
+    #[test]
+    fn parses_fraction_and_offset() {
+        assert_eq!(parse_rfc3339("1970-01-01T00:00:01.5Z"), Some(1_500_000_000));
+        assert_eq!(parse_rfc3339("1970-01-01T01:00:00+01:00"), Some(0));
+        assert_eq!(parse_rfc3339("1970-01-01T00:00:00-00:01"), Some(60_000_000_000));
+    }
+
+    #[test]
+    fn rejects_times_outside_u64_nanos() {
+        assert_eq!(parse_rfc3339("2262-04-11T23:47:16.854775807Z"), Some(i64::MAX as u64));
+        assert_eq!(parse_rfc3339("2262-04-11T23:47:16.854775808Z"), None);
+        assert_eq!(parse_rfc3339("9999-12-31T23:59:59Z"), None);
+        assert_eq!(parse_rfc3339("1969-12-31T23:59:59Z"), None);
+    }
+}
@@ -0,0 +1,102 @@
+use std::fmt::Write;
+
+use tonic::metadata::{MetadataMap, MetadataValue};
+use tracing::{Event, Subscriber};
+use tracing::field::{Field, Visit};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::ids::IdGenerator;
+use crate::opentelclient::{LogRecord, LogRecordFlags};
+
+// Span field carrying an incoming W3C trace context, e.g.
+// `info_span!("request", traceparent = %header_value)`.
+pub(crate) const TRACEPARENT_FIELD: &str = "traceparent";
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct TraceContext {
+    pub(crate) trace_id: [u8; 16],
+    pub(crate) span_id: [u8; 8],
+    pub(crate) flags: u8,
+}
+
+impl TraceContext {
+    pub(crate) fn generate(ids: &dyn IdGenerator) -> Self {
+        Self {
+            trace_id: ids.trace_id(),
+            span_id: ids.span_id(),
+            flags: 1,
+        }
+    }
+
+    pub(crate) fn parse(traceparent: &str) -> Option<Self> {
+        let mut parts = traceparent.trim().split('-');
+        let version = parts.next()?;
+        let trace_id = parse_hex::<16>(parts.next()?)?;
+        let span_id = parse_hex::<8>(parts.next()?)?;
+        let [flags] = parse_hex::<1>(parts.next()?)?;
+        if version.len() != 2 || version == "ff" || trace_id == [0; 16] || span_id == [0; 8] {
+            return None;
+        }
+        Some(Self { trace_id, span_id, flags })
+    }
+
+    pub(crate) fn stamp(&self, record: &mut LogRecord) {
+        record.trace_id = self.trace_id.to_vec();
+        record.span_id = self.span_id.to_vec();
+        record.flags = self.flags as u32 & LogRecordFlags::TraceFlagsMask as u32;
+    }
+
+    pub(crate) fn traceparent(&self) -> String {
+        format!("00-{}-{}-{:02x}", hex(&self.trace_id), hex(&self.span_id), self.flags)
+    }
+
+    pub(crate) fn inject(&self, metadata: &mut MetadataMap) {
+        if let Ok(value) = MetadataValue::try_from(self.traceparent()) {
+            metadata.insert("traceparent", value);
+        }
+    }
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::with_capacity(bytes.len() * 2), |mut out, byte| {
+        let _ = write!(out, "{:02x}", byte);
+        out
+    })
+}
+
+fn parse_hex<const N: usize>(value: &str) -> Option<[u8; N]> {
+    if value.len() != N * 2 || !value.is_ascii() {
+        return None;
+    }
+    let mut bytes = [0; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&value[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(bytes)
+}
+
+#[derive(Default)]
+pub(crate) struct TraceparentVisitor(pub(crate) Option<TraceContext>);
+
+impl Visit for TraceparentVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == TRACEPARENT_FIELD {
+            self.0 = TraceContext::parse(value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == TRACEPARENT_FIELD {
+            self.0 = TraceContext::parse(&format!("{:?}", value));
+        }
+    }
+}
+
+// Closest enclosing span that carries a trace context.
+pub(crate) fn current<S>(ctx: &Context<'_, S>, event: &Event<'_>) -> Option<TraceContext>
+    where S: Subscriber + for<'a> LookupSpan<'a>
+{
+    ctx.event_scope(event)?
+        .find_map(|span| span.extensions().get::<TraceContext>().copied())
+}
@@ -0,0 +1,68 @@
+use std::sync::{Arc, OnceLock};
+
+use tonic::transport::Channel;
+
+use crate::clock::Clock;
+use crate::endpoint;
+use crate::error::{invalid_uri, TelescopeError};
+use crate::stats::ExportStats;
+
+/// One connection to the collector shared by several layers, e.g. a logs layer and a
+/// spans-only layer, instead of a connection each. Requests of all layers are
+/// multiplexed over the channel's HTTP/2 connection, and their handles report the same
+/// connection state.
+#[derive(Clone)]
+pub struct TelescopeTransport {
+    pub(crate) channel: Channel,
+    stats: Arc<OnceLock<Arc<ExportStats>>>,
+}
+
+impl TelescopeTransport {
+    /// Connects lazily, on the first export of any layer using the transport. Has to be
+    /// called inside a tokio runtime, which the connection is then driven on; outside
+    /// one, use [`TelescopeTransport::from_channel`].
+    pub fn new(url: String) -> Result<Self, TelescopeError> {
+        let url = endpoint::normalize(&url);
+        let channel = Channel::from_shared(url.clone()).map_err(|error| invalid_uri(&url, error))?;
+        #[cfg(feature = "tls")]
+        let channel = match channel.uri().scheme_str() {
+            Some("https") => channel.tls_config(tonic::transport::ClientTlsConfig::new()).map_err(TelescopeError::Transport)?,
+            _ => channel,
+        };
+        if tokio::runtime::Handle::try_current().is_err() {
+            return Err(TelescopeError::NoRuntime);
+        }
+        Ok(Self::from_channel(channel.connect_lazy()))
+    }
+
+    pub fn from_channel(channel: Channel) -> Self {
+        Self { channel, stats: Arc::new(OnceLock::new()) }
+    }
+
+    // The first layer built on the transport decides the clock and whether connection
+    // events are recorded.
+    pub(crate) fn stats(&self, clock: Arc<dyn Clock>, connection_events: bool) -> Arc<ExportStats> {
+        self.stats.get_or_init(|| Arc::new(ExportStats::new(clock, connection_events))).clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TelescopeTransport;
+    use crate::error::TelescopeError;
+
+    #[test]
+    fn invalid_url_is_reported() {
+        let result = TelescopeTransport::new("http://collector:43 17".to_string());
+        assert!(matches!(result, Err(TelescopeError::InvalidUri { uri, .. }) if uri == "http://collector:43 17"));
+    }
+
+    #[test]
+    fn missing_runtime_is_reported() {
+        let result = TelescopeTransport::new("http://collector:4317".to_string());
+        assert!(matches!(result, Err(TelescopeError::NoRuntime)));
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let _guard = runtime.enter();
+        assert!(TelescopeTransport::new("http://collector:4317".to_string()).is_ok());
+    }
+}
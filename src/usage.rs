@@ -0,0 +1,110 @@
+use std::time::{Duration, Instant};
+
+use tokio::runtime::Runtime;
+use tonic::codec::ProstCodec;
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::{GrpcMethod, Request, Response, Status};
+
+use crate::auth::AuthChannel;
+use crate::clock::Clock;
+use crate::stats::{ExportStats, IngestionQuota};
+
+const USAGE_PATH: &str = "/telescope.v1.QuotaService/GetUsage";
+const POLL_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub(crate) struct UsageRequest {
+    #[prost(string, tag = "1")]
+    pub(crate) service_name: String,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub(crate) struct UsageResponse {
+    #[prost(uint64, tag = "1")]
+    pub(crate) remaining_records: u64,
+    #[prost(uint64, tag = "2")]
+    pub(crate) limit_records: u64,
+    #[prost(uint64, tag = "3")]
+    pub(crate) reset_after_seconds: u64,
+}
+
+// Telescope's quota endpoint, called by its fixed path so the server doesn't need gRPC
+// reflection.
+#[derive(Debug, Clone)]
+pub(crate) struct UsageClient {
+    inner: tonic::client::Grpc<AuthChannel>,
+}
+
+impl UsageClient {
+    pub(crate) fn new(channel: AuthChannel) -> Self {
+        Self { inner: tonic::client::Grpc::new(channel) }
+    }
+
+    async fn usage(&mut self, request: UsageRequest) -> Result<Response<UsageResponse>, Status> {
+        self.inner
+            .ready()
+            .await
+            .map_err(|e| Status::unknown(format!("Service was not ready: {}", e)))?;
+        let mut request = Request::new(request);
+        request.extensions_mut().insert(GrpcMethod::new("telescope.v1.QuotaService", "GetUsage"));
+        self.inner.unary(request, PathAndQuery::from_static(USAGE_PATH), ProstCodec::default()).await
+    }
+}
+
+// Polls the quota endpoint from the exporter thread and holds exports back while the
+// quota is used up, instead of sending batches the server would reject. Between polls
+// the remaining records are counted down by what was exported.
+pub(crate) struct UsagePoller {
+    client: UsageClient,
+    service_name: String,
+    interval: Duration,
+    next_poll: Option<Instant>,
+    quota: Option<(IngestionQuota, Instant)>,
+}
+
+impl UsagePoller {
+    pub(crate) fn new(client: UsageClient, interval: Duration, service_name: String) -> Self {
+        Self { client, service_name, interval, next_poll: None, quota: None }
+    }
+
+    pub(crate) fn poll(&mut self, rt: &Runtime, clock: &dyn Clock, stats: Option<&ExportStats>) {
+        let now = clock.now();
+        if self.next_poll.is_some_and(|next_poll| now < next_poll) {
+            return;
+        }
+        self.next_poll = Some(now + self.interval);
+        let mut client = self.client.clone();
+        let request = UsageRequest { service_name: self.service_name.clone() };
+        let response = rt.block_on(async { tokio::time::timeout(POLL_TIMEOUT, client.usage(request)).await });
+        // An endpoint that doesn't answer leaves the last known quota in place.
+        let Ok(Ok(response)) = response else {
+            return;
+        };
+        let usage = response.into_inner();
+        let resets_in = Duration::from_secs(usage.reset_after_seconds);
+        let quota = IngestionQuota {
+            remaining_records: usage.remaining_records,
+            limit_records: usage.limit_records,
+            resets_in,
+        };
+        self.quota = Some((quota, now + resets_in));
+        if let Some(stats) = stats {
+            stats.on_quota(quota);
+        }
+    }
+
+    pub(crate) fn exhausted(&self, clock: &dyn Clock) -> bool {
+        self.quota.is_some_and(|(quota, reset_at)| quota.remaining_records == 0 && clock.now() < reset_at)
+    }
+
+    pub(crate) fn on_exported(&mut self, records: usize, clock: &dyn Clock, stats: Option<&ExportStats>) {
+        let Some((quota, reset_at)) = self.quota.as_mut() else {
+            return;
+        };
+        quota.remaining_records = quota.remaining_records.saturating_sub(records as u64);
+        quota.resets_in = reset_at.saturating_duration_since(clock.now());
+        if let Some(stats) = stats {
+            stats.on_quota(*quota);
+        }
+    }
+}